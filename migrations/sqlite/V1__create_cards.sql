@@ -0,0 +1,21 @@
+-- Cards as described in docs/fields. Password, expiration date and PAN hold sealed values, so
+-- lookups by PAN go through its keyed hash.
+CREATE TABLE cards (
+    id                 TEXT    NOT NULL PRIMARY KEY,
+    customer_id        TEXT    NOT NULL,
+    org_id             TEXT    NOT NULL,
+    program_id         TEXT    NOT NULL,
+    account_id         TEXT    NOT NULL,
+    printed_name       TEXT    NOT NULL,
+    password           TEXT    NOT NULL,
+    password_attempts  INTEGER NOT NULL DEFAULT 0,
+    expiration_date    TEXT    NOT NULL,
+    issuing_date       TEXT    NOT NULL,
+    pan                TEXT    NOT NULL,
+    pan_hash           TEXT    UNIQUE,
+    kind               TEXT    NOT NULL,
+    status             TEXT    NOT NULL,
+    status_reason      TEXT    NOT NULL DEFAULT '',
+    status_updated_at  TEXT    NOT NULL DEFAULT '',
+    cvv                TEXT    NOT NULL
+);
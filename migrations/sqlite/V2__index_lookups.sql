@@ -0,0 +1,4 @@
+-- Lookups of the list endpoint, paged in id order.
+CREATE INDEX cards_customer_id ON cards (customer_id, id);
+CREATE INDEX cards_account_id ON cards (account_id, id);
+CREATE INDEX cards_program_id ON cards (program_id, id);
@@ -0,0 +1,2 @@
+-- Version of every card for conditional updates. Cards saved before it count as version 0.
+ALTER TABLE cards ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
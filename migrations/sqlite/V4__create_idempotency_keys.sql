@@ -0,0 +1,7 @@
+-- Idempotency keys of create requests, free again once expires_at (Unix seconds) has passed.
+CREATE TABLE idempotency_keys (
+    key TEXT PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    card_id TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
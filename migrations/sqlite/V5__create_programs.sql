@@ -0,0 +1,11 @@
+-- Card programs. Kinds hold the names of the card kinds issued, separated by commas.
+CREATE TABLE programs (
+    id               TEXT    NOT NULL PRIMARY KEY,
+    name             TEXT    NOT NULL,
+    bin              TEXT    NOT NULL,
+    pan_length       INTEGER NOT NULL,
+    kinds            TEXT    NOT NULL,
+    validity_months  INTEGER NOT NULL,
+    funding          TEXT    NOT NULL,
+    active           INTEGER NOT NULL
+);
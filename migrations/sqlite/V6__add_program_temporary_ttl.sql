@@ -0,0 +1,2 @@
+-- Hours a temporary card of the program lives. Programs saved before it have none.
+ALTER TABLE programs ADD COLUMN temporary_ttl_hours INTEGER NOT NULL DEFAULT 0;
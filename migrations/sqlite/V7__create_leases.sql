@@ -0,0 +1,7 @@
+-- Leases of background jobs run by one replica at a time, free again once expires_at (Unix
+-- seconds) has passed.
+CREATE TABLE leases (
+    name TEXT PRIMARY KEY,
+    holder TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
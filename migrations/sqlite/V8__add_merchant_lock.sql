@@ -0,0 +1,3 @@
+-- Merchant a recurring card is locked to. Cards saved before it are not locked.
+ALTER TABLE cards ADD COLUMN merchant_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE cards ADD COLUMN mcc TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,33 @@
+-- The CVV is derived from the PAN and expiry whenever it is needed and must not be stored (PCI
+-- DSS 3.2). SQLite before 3.35 cannot drop a column, so the table is rebuilt without it.
+CREATE TABLE cards_without_cvv (
+    id                 TEXT    NOT NULL PRIMARY KEY,
+    customer_id        TEXT    NOT NULL,
+    org_id             TEXT    NOT NULL,
+    program_id         TEXT    NOT NULL,
+    account_id         TEXT    NOT NULL,
+    printed_name       TEXT    NOT NULL,
+    password           TEXT    NOT NULL,
+    password_attempts  INTEGER NOT NULL DEFAULT 0,
+    expiration_date    TEXT    NOT NULL,
+    issuing_date       TEXT    NOT NULL,
+    pan                TEXT    NOT NULL,
+    pan_hash           TEXT    UNIQUE,
+    kind               TEXT    NOT NULL,
+    status             TEXT    NOT NULL,
+    status_reason      TEXT    NOT NULL DEFAULT '',
+    status_updated_at  TEXT    NOT NULL DEFAULT '',
+    version            INTEGER NOT NULL DEFAULT 0,
+    merchant_id        TEXT    NOT NULL DEFAULT '',
+    mcc                TEXT    NOT NULL DEFAULT ''
+);
+INSERT INTO cards_without_cvv
+SELECT id, customer_id, org_id, program_id, account_id, printed_name, password,
+       password_attempts, expiration_date, issuing_date, pan, pan_hash, kind, status,
+       status_reason, status_updated_at, version, merchant_id, mcc
+FROM cards;
+DROP TABLE cards;
+ALTER TABLE cards_without_cvv RENAME TO cards;
+CREATE INDEX cards_customer_id ON cards (customer_id, id);
+CREATE INDEX cards_account_id ON cards (account_id, id);
+CREATE INDEX cards_program_id ON cards (program_id, id);
@@ -0,0 +1,23 @@
+#!/bin/bash
+# Removes the CVV still held by cards written before it stopped being stored. The service
+# derives it from the PAN and expiry, so the items need nothing in its place.
+
+. env/.aws.env
+
+TABLE=${1:-Cards}
+
+for id in $(aws dynamodb scan \
+	--table-name "$TABLE" \
+	--projection-expression ID \
+	--filter-expression "attribute_exists(CVV)" \
+	--query "Items[].ID.S" \
+	--output text \
+	--region "$REGION" \
+	--endpoint-url "$ENDPOINT"); do
+	aws dynamodb update-item \
+		--table-name "$TABLE" \
+		--key "{\"ID\": {\"S\": \"$id\"}}" \
+		--update-expression "REMOVE CVV" \
+		--region "$REGION" \
+		--endpoint-url "$ENDPOINT" >/dev/null
+done
@@ -1,12 +1,29 @@
 use actix_web::{App, HttpServer};
-
-static ADDRESS: &str = "127.0.0.1";
-static PORT: &str = "8080";
+use cards::config;
+use cards::settings::Settings;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| App::new().configure(cards::config::default))
-        .bind(format!("{}:{}", ADDRESS, PORT))?
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Invalid settings: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let components = match config::Builder::new(&settings).build() {
+        Ok(components) => components,
+        Err(err) => {
+            eprintln!("Cannot start the server: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    components.start_expiry(|err| eprintln!("Expiry sweep failed: {:?}", err));
+
+    HttpServer::new(move || App::new().configure(|cfg| components.configure(cfg)))
+        .workers(settings.workers())
+        .bind(settings.bind())?
         .run()
         .await
 }
@@ -1,27 +1,351 @@
-use crate::domain::card;
+use crate::crypto::envelope::Cipher;
+use crate::crypto::password;
+use crate::domain::{card, expiry, idempotency, program};
+use crate::generator::{clock, pan, uuid};
 use crate::handler;
+use crate::handler::error::Error;
+#[cfg(feature = "sqlite")]
+use crate::repository::sqlite;
+use crate::repository::{dynamo, encrypted, memory};
+use crate::settings::Settings;
 use actix_web::web;
+use std::sync::Arc;
 
-pub fn default(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope(handler::card::SCOPE)
-            //FIXME: fix injection here
-            .data::<Box<dyn card::Creator>>(Box::new(card::Service::new(Box::new(()), Box::new(()), Box::new(()), Box::new(()))))
-            .route("", web::post().to(handler::card::create)),
-    )
-    .route("/status", web::get().to(handler::status::check_status));
+// Composition root: builds the collaborators of the card service from the settings. Tests
+// replace any of them, such as the repository, with in-memory substitutes. Cards are sealed
+// before they reach the repository, whether it was substituted or not.
+pub struct Builder {
+    settings: Settings,
+    uuid_generator: Option<Box<dyn card::UuidGenerator>>,
+    time_service: Option<Arc<dyn card::TimeService>>,
+    pan_generator: Option<Box<dyn card::PanGenerator>>,
+    cvv_generator: Option<Box<dyn card::CvvGenerator>>,
+    password_hasher: Option<Box<dyn card::PasswordHasher>>,
+    repository: Option<Arc<dyn card::Repository>>,
+    idempotency_store: Option<Arc<dyn idempotency::Store>>,
+    programs: Option<Arc<dyn program::Repository>>,
+    lease: Option<Arc<dyn expiry::Lease>>,
+}
+
+// Services built once and shared by every worker of the server.
+#[derive(Clone)]
+pub struct Components {
+    service: Arc<card::Service>,
+    idempotent: Arc<idempotency::Service>,
+    programs: Arc<program::Service>,
+    scheduler: Arc<expiry::Scheduler>,
+    payload_limit: usize,
+}
+
+impl Builder {
+    pub fn new(settings: &Settings) -> Builder {
+        Builder {
+            settings: settings.clone(),
+            uuid_generator: None,
+            time_service: None,
+            pan_generator: None,
+            cvv_generator: None,
+            password_hasher: None,
+            repository: None,
+            idempotency_store: None,
+            programs: None,
+            lease: None,
+        }
+    }
+
+    pub fn uuid_generator(mut self, uuid_generator: Box<dyn card::UuidGenerator>) -> Builder {
+        self.uuid_generator = Some(uuid_generator);
+        self
+    }
+
+    pub fn time_service(mut self, time_service: Arc<dyn card::TimeService>) -> Builder {
+        self.time_service = Some(time_service);
+        self
+    }
+
+    pub fn pan_generator(mut self, pan_generator: Box<dyn card::PanGenerator>) -> Builder {
+        self.pan_generator = Some(pan_generator);
+        self
+    }
+
+    pub fn cvv_generator(mut self, cvv_generator: Box<dyn card::CvvGenerator>) -> Builder {
+        self.cvv_generator = Some(cvv_generator);
+        self
+    }
+
+    pub fn password_hasher(mut self, password_hasher: Box<dyn card::PasswordHasher>) -> Builder {
+        self.password_hasher = Some(password_hasher);
+        self
+    }
+
+    pub fn repository(mut self, repository: Arc<dyn card::Repository>) -> Builder {
+        self.repository = Some(repository);
+        self
+    }
+
+    pub fn idempotency_store(mut self, store: Arc<dyn idempotency::Store>) -> Builder {
+        self.idempotency_store = Some(store);
+        self
+    }
+
+    pub fn programs(mut self, programs: Arc<dyn program::Repository>) -> Builder {
+        self.programs = Some(programs);
+        self
+    }
+
+    pub fn lease(mut self, lease: Arc<dyn expiry::Lease>) -> Builder {
+        self.lease = Some(lease);
+        self
+    }
+
+    // Builds what was not substituted, failing when a key or a setting needed for it is missing.
+    pub fn build(self) -> Result<Components, String> {
+        let settings = &self.settings;
+        let cipher = Cipher::new(settings.key_provider()?);
+        let (repository, idempotency_store, programs, lease) = match (
+            self.repository,
+            self.idempotency_store,
+            self.programs,
+            self.lease,
+        ) {
+            (Some(repository), Some(store), Some(programs), Some(lease)) => {
+                (repository, store, programs, lease)
+            }
+            (repository, store, programs, lease) => {
+                let (inner, inner_store, inner_programs, inner_lease) = storage(settings)?;
+                (
+                    repository.unwrap_or(inner),
+                    store.unwrap_or(inner_store),
+                    programs.unwrap_or(inner_programs),
+                    lease.unwrap_or(inner_lease),
+                )
+            }
+        };
+        let repository: Arc<dyn card::Repository> = Arc::new(encrypted::Repository::new(
+            Box::new(repository),
+            cipher.clone(),
+        ));
+        let pan_generator = match self.pan_generator {
+            Some(pan_generator) => pan_generator,
+            None => Box::new(pan::Generator::new(Box::new(repository.clone()))),
+        };
+        let cvv_generator = match self.cvv_generator {
+            Some(cvv_generator) => cvv_generator,
+            None => Box::new(settings.cvv_generator()?),
+        };
+        let password_hasher = match self.password_hasher {
+            Some(password_hasher) => password_hasher,
+            None => Box::new(
+                password::Hasher::new(password::DEFAULT_MEMORY, password::DEFAULT_ITERATIONS)
+                    .map_err(|_| String::from("Invalid password hashing parameters"))?,
+            ),
+        };
+
+        let time_service = self
+            .time_service
+            .unwrap_or_else(|| Arc::new(clock::Utc) as Arc<dyn card::TimeService>);
+
+        let service = Arc::new(card::Service::new(
+            self.uuid_generator.unwrap_or_else(|| Box::new(uuid::V4)),
+            Box::new(time_service.clone()),
+            pan_generator,
+            cvv_generator,
+            password_hasher,
+            Box::new(repository),
+            Box::new(programs.clone()),
+        ));
+        let idempotent = Arc::new(idempotency::Service::new(
+            service.clone(),
+            service.clone(),
+            Box::new(idempotency_store),
+            Box::new(cipher),
+            Box::new(time_service.clone()),
+            settings.idempotency_ttl(),
+            settings.idempotency_lease(),
+        ));
+
+        let programs = Arc::new(program::Service::new(
+            Box::new(uuid::V4),
+            Box::new(programs),
+        ));
+        let scheduler = Arc::new(expiry::Scheduler::new(
+            service.clone(),
+            Box::new(lease),
+            Box::new(time_service),
+            card::UuidGenerator::generate(&uuid::V4)
+                .map_err(|_| String::from("Cannot identify the replica"))?
+                .to_string(),
+            settings.expiry_interval(),
+        ));
+
+        Ok(Components {
+            service,
+            idempotent,
+            programs,
+            scheduler,
+            payload_limit: settings.payload_limit(),
+        })
+    }
+}
+
+// Cards, idempotency keys, programs and leases, kept by the same backend.
+type Storage = (
+    Arc<dyn card::Repository>,
+    Arc<dyn idempotency::Store>,
+    Arc<dyn program::Repository>,
+    Arc<dyn expiry::Lease>,
+);
+
+// Backend selected by the settings.
+fn storage(settings: &Settings) -> Result<Storage, String> {
+    Ok(match settings.repository() {
+        "memory" => {
+            let memory = Arc::new(memory::Repository::new());
+            (memory.clone(), memory.clone(), memory.clone(), memory)
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let sqlite = Arc::new(sqlite::Repository::open(settings.sqlite())?);
+            (sqlite.clone(), sqlite.clone(), sqlite.clone(), sqlite)
+        }
+        _ => {
+            let dynamo = Arc::new(dynamo::Repository::connect(settings.dynamo()));
+            (dynamo.clone(), dynamo.clone(), dynamo.clone(), dynamo)
+        }
+    })
+}
+
+impl Components {
+    // Starts sweeping expired cards in the background, once per process, handing every failed
+    // sweep to `report`.
+    pub fn start_expiry(&self, report: impl Fn(card::Error) + Send + 'static) {
+        self.scheduler.clone().start(report);
+    }
+
+    pub fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.service(
+            web::scope(handler::card::SCOPE)
+                .data::<Arc<dyn card::Creator>>(self.service.clone())
+                .data::<Arc<dyn idempotency::Creator>>(self.idempotent.clone())
+                .data::<Arc<dyn card::Finder>>(self.service.clone())
+                .data::<Arc<dyn card::Updater>>(self.service.clone())
+                .data::<Arc<dyn card::Lifecycle>>(self.service.clone())
+                .data::<Arc<dyn card::Verifier>>(self.service.clone())
+                .data::<Arc<dyn card::MerchantChecker>>(self.service.clone())
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(self.payload_limit)
+                        .error_handler(Error::malformed_json),
+                )
+                .app_data(web::QueryConfig::default().error_handler(Error::malformed_query))
+                .route("", web::post().to(handler::card::create))
+                .route("", web::get().to(handler::card::list))
+                .route("/{id}", web::get().to(handler::card::find))
+                .route("/{id}", web::patch().to(handler::card::update))
+                .route("/{id}/block", web::post().to(handler::card::block))
+                .route("/{id}/unblock", web::post().to(handler::card::unblock))
+                .route("/{id}/cancel", web::post().to(handler::card::cancel))
+                .route(
+                    "/{id}/password/verify",
+                    web::post().to(handler::card::verify_password),
+                )
+                .route(
+                    "/{id}/merchant/check",
+                    web::post().to(handler::card::check_merchant),
+                ),
+        )
+        .service(
+            web::scope(handler::program::SCOPE)
+                .data::<Arc<dyn program::Creator>>(self.programs.clone())
+                .data::<Arc<dyn program::Finder>>(self.programs.clone())
+                .data::<Arc<dyn program::Updater>>(self.programs.clone())
+                .data::<Arc<dyn program::Remover>>(self.programs.clone())
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(self.payload_limit)
+                        .error_handler(Error::malformed_json),
+                )
+                .route("", web::post().to(handler::program::create))
+                .route("", web::get().to(handler::program::list))
+                .route("/{id}", web::get().to(handler::program::find))
+                .route("/{id}", web::put().to(handler::program::update))
+                .route("/{id}", web::delete().to(handler::program::delete)),
+        )
+        .route("/status", web::get().to(handler::status::check_status));
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::config;
+    use super::*;
+    use crate::domain::card::TimeService as _;
+    use crate::domain::expiry::Lease as _;
+    use crate::domain::idempotency::Store as _;
+    use crate::domain::program::Repository as _;
+    use crate::handler::error;
+    use crate::protocol;
     use actix_service::Service;
-    use actix_web::http::StatusCode;
+    use actix_web::http::{header, StatusCode};
     use actix_web::{test, App};
 
+    const PROGRAM_ID: &str = "35d86d4b-55e6-49c6-bc83-0b52d68b75f5";
+
+    fn settings() -> Settings {
+        let mut settings = Settings::default();
+        settings.cvv.key = String::from("0123456789ABCDEFFEDCBA9876543210");
+        settings.keys.provider = String::from("file");
+        settings.keys.file = String::from("scripts/env/local-keys");
+        settings.repository = String::from("memory");
+        settings
+    }
+
+    fn a_program() -> serde_json::Value {
+        serde_json::json!({
+            "name": "Gold",
+            "bin": "41234567",
+            "pan_length": 16,
+            "kinds": ["PLASTIC", "RECURRING", "TEMPORARY"],
+            "validity_months": 60,
+            "temporary_ttl_hours": 24,
+            "funding": "CREDIT",
+        })
+    }
+
+    // Clock stopped at the time the tests are written against.
+    struct Stopped;
+
+    impl card::TimeService for Stopped {
+        fn now(&self) -> chrono::NaiveDateTime {
+            chrono::NaiveDate::from_ymd(2021, 2, 16).and_hms(19, 20, 0)
+        }
+    }
+
+    fn programs() -> Arc<memory::Repository> {
+        let programs = Arc::new(memory::Repository::new());
+        programs
+            .save(&protocol::Program {
+                id: String::from(PROGRAM_ID),
+                ..serde_json::from_value(a_program()).unwrap()
+            })
+            .unwrap();
+        programs
+    }
+
+    fn components() -> Components {
+        Builder::new(&settings())
+            .repository(Arc::new(memory::Repository::new()))
+            .idempotency_store(Arc::new(memory::Repository::new()))
+            .programs(programs())
+            .lease(Arc::new(memory::Repository::new()))
+            .build()
+            .unwrap()
+    }
+
     #[actix_rt::test]
     async fn test_status_ok() {
-        let mut app = test::init_service(App::new().configure(config::default)).await;
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
         let req = test::TestRequest::get().uri("/status").to_request();
 
         let resp = app.call(req).await.unwrap();
@@ -31,4 +355,361 @@ mod tests {
         assert_eq!(status, StatusCode::OK);
         assert_eq!(body, "OK");
     }
+
+    #[actix_rt::test]
+    async fn test_create_and_find() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let req = test::TestRequest::post()
+            .uri("/cards")
+            .set_json(&serde_json::json!({
+                "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+                "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+                "program_id": PROGRAM_ID,
+                "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+                "printed_name": "BAKER MAYFIELD",
+                "password": "078123",
+                "kind": "PLASTIC",
+            }))
+            .to_request();
+
+        let created: protocol::Card = test::read_response_json(&mut app, req).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/cards/{}", created.id))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let found: protocol::Card = test::read_body_json(resp).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(found.id, created.id);
+        assert!(found.pan.starts_with("412345"));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_with_idempotency_key() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let create = |printed_name: &str| {
+            test::TestRequest::post()
+                .uri("/cards")
+                .header("Idempotency-Key", "c0ffee-create-1")
+                .set_json(&serde_json::json!({
+                    "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+                    "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+                    "program_id": PROGRAM_ID,
+                    "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+                    "printed_name": printed_name,
+                    "password": "078123",
+                    "kind": "PLASTIC",
+                }))
+                .to_request()
+        };
+
+        let created: protocol::Card =
+            test::read_response_json(&mut app, create("BAKER MAYFIELD")).await;
+        let replayed: protocol::Card =
+            test::read_response_json(&mut app, create("BAKER MAYFIELD")).await;
+        let resp = app.call(create("BAKER")).await.unwrap();
+
+        assert_eq!(replayed, created);
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_rt::test]
+    async fn test_issue_only_from_active_programs() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let create_card = |program_id: &str| {
+            test::TestRequest::post()
+                .uri("/cards")
+                .set_json(&serde_json::json!({
+                    "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+                    "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+                    "program_id": program_id,
+                    "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+                    "printed_name": "BAKER MAYFIELD",
+                    "password": "078123",
+                    "kind": "PLASTIC",
+                }))
+                .to_request()
+        };
+        let mut input = a_program();
+        input["bin"] = serde_json::json!("52143302");
+        let req = test::TestRequest::post()
+            .uri("/programs")
+            .set_json(&input)
+            .to_request();
+
+        let program: protocol::Program = test::read_response_json(&mut app, req).await;
+        let issued: protocol::Card =
+            test::read_response_json(&mut app, create_card(&program.id)).await;
+        let req = test::TestRequest::put()
+            .uri(&format!("/programs/{}", program.id))
+            .set_json(&protocol::Program {
+                active: false,
+                ..program.clone()
+            })
+            .to_request();
+        let deactivated = app.call(req).await.unwrap().status();
+        let refused = app.call(create_card(&program.id)).await.unwrap();
+        let status = refused.status();
+        let body: protocol::Problem = test::read_body_json(refused).await;
+
+        assert!(issued.pan.starts_with("521433"));
+        assert_eq!(deactivated, StatusCode::OK);
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            body.errors(),
+            vec![protocol::ValidationError::new(
+                String::from("program_id"),
+                program.id,
+                protocol::Code::Inactive,
+            )]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_lock_recurring_card_on_first_charge() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let req = test::TestRequest::post()
+            .uri("/cards")
+            .set_json(&serde_json::json!({
+                "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+                "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+                "program_id": PROGRAM_ID,
+                "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+                "printed_name": "BAKER MAYFIELD",
+                "password": "078123",
+                "kind": "RECURRING",
+            }))
+            .to_request();
+        let created: protocol::Card = test::read_response_json(&mut app, req).await;
+        let check = |merchant_id: &str, lock: bool| {
+            test::TestRequest::post()
+                .uri(&format!("/cards/{}/merchant/check", created.id))
+                .set_json(&serde_json::json!({
+                    "merchant_id": merchant_id,
+                    "mcc": "4899",
+                    "lock": lock,
+                }))
+                .to_request()
+        };
+
+        let looked_up: protocol::MerchantVerification =
+            test::read_response_json(&mut app, check("000000000067890", false)).await;
+        let first: protocol::MerchantVerification =
+            test::read_response_json(&mut app, check("000000000012345", true)).await;
+        let again: protocol::MerchantVerification =
+            test::read_response_json(&mut app, check("000000000012345", true)).await;
+        let other: protocol::MerchantVerification =
+            test::read_response_json(&mut app, check("000000000067890", false)).await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/cards/{}", created.id))
+            .to_request();
+        let found: protocol::Card = test::read_response_json(&mut app, req).await;
+
+        assert!(looked_up.allowed());
+        assert!(first.allowed());
+        assert!(again.allowed());
+        assert!(!other.allowed());
+        assert_eq!(found.merchant_id, "000000000012345");
+        assert_eq!(found.mcc, "4899");
+    }
+
+    #[actix_rt::test]
+    async fn test_find_unknown_card() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let req = test::TestRequest::get()
+            .uri("/cards/5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let body: protocol::Problem = test::read_body_json(resp).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body.instance(),
+            "/cards/5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_malformed_json() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let req = test::TestRequest::post()
+            .uri("/cards")
+            .header(header::CONTENT_TYPE, "application/json")
+            .set_payload("{\"printed_name\":")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let content_type = resp.headers().get(header::CONTENT_TYPE).cloned();
+        let body: protocol::Problem = test::read_body_json(resp).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type.unwrap(), error::CONTENT_TYPE);
+        assert_eq!(body.status(), 400);
+        assert_eq!(body.instance(), "/cards");
+    }
+
+    #[actix_rt::test]
+    async fn test_malformed_query() {
+        let components = components();
+        let mut app =
+            test::init_service(App::new().configure(|cfg| components.configure(cfg))).await;
+        let req = test::TestRequest::get()
+            .uri("/cards?limit=many")
+            .to_request();
+
+        let resp = app.call(req).await.unwrap();
+        let status = resp.status();
+        let body: protocol::Problem = test::read_body_json(resp).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.problem_type(), "/problems/malformed-request");
+    }
+
+    #[test]
+    fn build_memory_repository_from_settings() {
+        let mut settings = settings();
+        settings.repository = String::from("memory");
+
+        assert!(Builder::new(&settings).build().is_ok());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn build_sqlite_repository_from_settings() {
+        let mut settings = settings();
+        settings.repository = String::from("sqlite");
+        settings.sqlite.path = String::from(":memory:");
+
+        assert!(Builder::new(&settings).build().is_ok());
+    }
+
+    #[test]
+    fn build_scheduler_with_lease() {
+        let components = components();
+
+        assert_eq!(components.scheduler.run_once(), Ok(Some(0)));
+    }
+
+    #[test]
+    fn build_shares_the_clock_with_idempotency() {
+        let store = Arc::new(memory::Repository::new());
+        let components = Builder::new(&settings())
+            .time_service(Arc::new(Stopped))
+            .idempotency_store(store.clone())
+            .programs(programs())
+            .lease(Arc::new(memory::Repository::new()))
+            .build()
+            .unwrap();
+        let card = serde_json::from_value(serde_json::json!({
+            "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+            "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+            "program_id": PROGRAM_ID,
+            "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+            "printed_name": "BAKER MAYFIELD",
+            "password": "078123",
+            "kind": "PLASTIC",
+        }))
+        .unwrap();
+
+        let created =
+            idempotency::Creator::create(&*components.idempotent, "c0ffee-clock", card).unwrap();
+
+        let held = store
+            .claim(
+                &idempotency::Record {
+                    key: String::from("c0ffee-clock"),
+                    ..Default::default()
+                },
+                0,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.issuing_date, Stopped.now().to_string());
+        assert_eq!(
+            held.expires_at,
+            Stopped.now().timestamp() + settings().idempotency_ttl()
+        );
+    }
+
+    #[test]
+    fn build_seals_cards_in_a_substituted_repository() {
+        let cards = Arc::new(memory::Repository::new());
+        let components = Builder::new(&settings())
+            .repository(cards.clone())
+            .idempotency_store(Arc::new(memory::Repository::new()))
+            .programs(programs())
+            .lease(Arc::new(memory::Repository::new()))
+            .build()
+            .unwrap();
+        let card = serde_json::from_value(serde_json::json!({
+            "customer_id": "29ce6541-302b-405e-9dfe-549934d4e4b2",
+            "org_id": "876ce143-6fcb-4c17-aaf1-f02c1d3654ce",
+            "program_id": PROGRAM_ID,
+            "account_id": "a2d46c49-262e-431d-8f1a-ff5b18b44982",
+            "printed_name": "BAKER MAYFIELD",
+            "password": "078123",
+            "kind": "PLASTIC",
+        }))
+        .unwrap();
+
+        let created = card::Creator::create(&*components.service, card).unwrap();
+
+        let id = ::uuid::Uuid::parse_str(&created.id).unwrap();
+        let stored = card::Repository::find_by_id(&*cards, id).unwrap().unwrap();
+        assert!(stored.pan.starts_with("enc:"));
+        assert!(stored.password.starts_with("enc:"));
+        assert_eq!(
+            card::Finder::find(&*components.service, &created.id),
+            Ok(created)
+        );
+    }
+
+    #[test]
+    fn build_shares_the_clock_with_the_scheduler() {
+        let lease = Arc::new(memory::Repository::new());
+        let components = Builder::new(&settings())
+            .time_service(Arc::new(Stopped))
+            .idempotency_store(Arc::new(memory::Repository::new()))
+            .programs(programs())
+            .lease(lease.clone())
+            .build()
+            .unwrap();
+        let held_until = Stopped.now().timestamp() + 2 * settings().expiry_interval() as i64;
+
+        assert_eq!(components.scheduler.run_once(), Ok(Some(0)));
+        assert_eq!(
+            lease.acquire(expiry::LEASE, "another replica", held_until - 1, held_until),
+            Ok(false)
+        );
+        assert_eq!(
+            lease.acquire(expiry::LEASE, "another replica", held_until, held_until),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn build_requires_cvv_key() {
+        let act = Builder::new(&Settings::default())
+            .repository(Arc::new(memory::Repository::new()))
+            .idempotency_store(Arc::new(memory::Repository::new()))
+            .build();
+
+        assert!(act.is_err());
+    }
 }
@@ -0,0 +1,236 @@
+use crate::crypto::key::KeyProvider;
+use crate::domain::{card, idempotency};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::fmt::Error;
+use std::sync::Arc;
+
+static PREFIX: &str = "enc";
+const DATA_KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+// Envelope encryption: every value is sealed with a fresh AES-256-GCM data key, which is in turn
+// sealed with the provider's current key. Sealed values read
+// `enc:<key version>:<sealed data key>:<sealed value>`, each part being nonce and ciphertext in
+// base64, and the field name is authenticated so a value cannot be moved to another field.
+// Clones share the provider, so its keys are loaded once.
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    provider: Arc<dyn KeyProvider>,
+}
+
+impl Cipher {
+    pub(crate) fn new(provider: Box<dyn KeyProvider>) -> Cipher {
+        Cipher {
+            provider: Arc::from(provider),
+        }
+    }
+
+    pub(crate) fn seal(&self, field: &str, value: &str) -> Result<String, Error> {
+        let version = self.provider.current_version();
+        let mut data_key = [0u8; DATA_KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut data_key);
+
+        let sealed_key = encrypt(
+            &self.provider.key(version)?,
+            &data_key,
+            version.to_string().as_bytes(),
+        )?;
+        let sealed_value = encrypt(&data_key, value.as_bytes(), field.as_bytes())?;
+
+        Ok(format!(
+            "{}:{}:{}:{}",
+            PREFIX,
+            version,
+            base64::encode_config(sealed_key, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(sealed_value, base64::URL_SAFE_NO_PAD)
+        ))
+    }
+
+    pub(crate) fn open(&self, field: &str, value: &str) -> Result<String, Error> {
+        // written before encryption was enabled
+        if !value.starts_with(&format!("{}:", PREFIX)) {
+            return Ok(String::from(value));
+        }
+        let parts: Vec<&str> = value.split(':').collect();
+        let (version, sealed_key, sealed_value) = match parts.as_slice() {
+            [_, version, key, value] => (*version, *key, *value),
+            // sealed, but truncated or tampered with
+            _ => return Err(Error),
+        };
+
+        let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD);
+        let key_version = version.parse::<u32>().map_err(|_| Error)?;
+        let data_key = decrypt(
+            &self.provider.key(key_version)?,
+            &decode(sealed_key).map_err(|_| Error)?,
+            version.as_bytes(),
+        )?;
+        let plain = decrypt(
+            &data_key,
+            &decode(sealed_value).map_err(|_| Error)?,
+            field.as_bytes(),
+        )?;
+
+        String::from_utf8(plain).map_err(|_| Error)
+    }
+
+    // Keyed hash allowing equality lookups on a sealed value without decrypting it.
+    pub(crate) fn fingerprint(&self, value: &str) -> Result<String, Error> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.provider.index_key()?)
+            .map_err(|_| Error)?;
+        mac.update(value.as_bytes());
+
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+impl idempotency::Fingerprinter for Cipher {
+    fn fingerprint(&self, value: &str) -> Result<String, card::Error> {
+        Cipher::fingerprint(self, value).map_err(|_| card::Error::Unavailable)
+    }
+}
+
+fn encrypt(key: &[u8], plain: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error)?;
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plain, aad })
+        .map_err(|_| Error)?;
+
+    Ok([&nonce[..], &sealed].concat())
+}
+
+fn decrypt(key: &[u8], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+    if sealed.len() < NONCE_LENGTH {
+        return Err(Error);
+    }
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| Error)?;
+    let (nonce, sealed) = sealed.split_at(NONCE_LENGTH);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .map_err(|_| Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key::Local;
+
+    const KEY_1: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    fn a_cipher(entries: &str) -> Cipher {
+        Cipher::new(Box::new(Local::parse(entries).unwrap()))
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+
+        let sealed = cipher.seal("pan", "5214330278318136").unwrap();
+
+        assert!(sealed.starts_with("enc:1:"));
+        assert!(!sealed.contains("5214330278318136"));
+        assert_eq!(
+            cipher.open("pan", &sealed),
+            Ok(String::from("5214330278318136"))
+        );
+    }
+
+    #[test]
+    fn seal_is_randomized() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+
+        let act = cipher.seal("pan", "5214330278318136").unwrap();
+
+        assert_ne!(act, cipher.seal("pan", "5214330278318136").unwrap());
+    }
+
+    #[test]
+    fn open_after_rotation() {
+        let sealed = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2))
+            .seal("password", "517412")
+            .unwrap();
+        let rotated = a_cipher(&format!("1:{},2:{},index:{}", KEY_1, KEY_2, KEY_2));
+
+        assert_eq!(
+            rotated.open("password", &sealed),
+            Ok(String::from("517412"))
+        );
+        assert!(rotated
+            .seal("password", "517412")
+            .unwrap()
+            .starts_with("enc:2:"));
+    }
+
+    #[test]
+    fn open_with_retired_key() {
+        let sealed = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2))
+            .seal("password", "517412")
+            .unwrap();
+
+        let act = a_cipher(&format!("2:{},index:{}", KEY_2, KEY_2)).open("password", &sealed);
+
+        assert_eq!(act, Err(Error));
+    }
+
+    #[test]
+    fn open_in_another_field() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+        let sealed = cipher.seal("expiration_date", "0724").unwrap();
+
+        assert_eq!(cipher.open("password", &sealed), Err(Error));
+    }
+
+    #[test]
+    fn open_tampered() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+        let sealed = cipher.seal("pan", "5214330278318136").unwrap();
+        let (head, last) = sealed.split_at(sealed.len() - 1);
+        let tampered = format!("{}{}", head, if last == "A" { "B" } else { "A" });
+
+        assert_eq!(cipher.open("pan", &tampered), Err(Error));
+        assert_eq!(cipher.open("pan", "enc:1:AAAA:AAAA"), Err(Error));
+        assert_eq!(cipher.open("pan", "enc:x:AAAA:AAAA"), Err(Error));
+    }
+
+    #[test]
+    fn open_malformed() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+        let sealed = cipher.seal("pan", "5214330278318136").unwrap();
+        let truncated = &sealed[..sealed.rfind(':').unwrap()];
+
+        assert_eq!(cipher.open("pan", truncated), Err(Error));
+        assert_eq!(cipher.open("pan", &format!("{}:AAAA", sealed)), Err(Error));
+        assert_eq!(cipher.open("pan", "enc:"), Err(Error));
+    }
+
+    #[test]
+    fn open_plain_value() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+
+        assert_eq!(
+            cipher.open("pan", "5214330278318136"),
+            Ok(String::from("5214330278318136"))
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        let cipher = a_cipher(&format!("1:{},index:{}", KEY_1, KEY_2));
+        let rotated = a_cipher(&format!("1:{},2:{},index:{}", KEY_1, KEY_2, KEY_2));
+
+        let act = cipher.fingerprint("5214330278318136").unwrap();
+
+        assert_eq!(act.len(), 64);
+        assert_eq!(act, rotated.fingerprint("5214330278318136").unwrap());
+        assert_ne!(act, cipher.fingerprint("5214330278318137").unwrap());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fmt::Error;
+use std::{env, fs};
+
+pub static KEYS_VAR: &str = "CARDS_KEYS";
+const KEY_LENGTH: usize = 32;
+
+// Source of the key-encryption keys wrapping the per-value data keys. Every key ever used must
+// stay available under its version so values sealed before a rotation can still be opened.
+pub trait KeyProvider: Send + Sync {
+    fn current_version(&self) -> u32;
+    fn key(&self, version: u32) -> Result<Vec<u8>, Error>;
+    // Key for the keyed hashes used to look up encrypted values. It does not rotate, otherwise
+    // values hashed under the previous key could no longer be found.
+    fn index_key(&self) -> Result<Vec<u8>, Error>;
+}
+
+// Development provider reading `<version>:<hex key>` and `index:<hex key>` entries, separated
+// by commas or new lines, from a file or an environment variable. The highest version is the
+// current one.
+pub(crate) struct Local {
+    keys: BTreeMap<u32, Vec<u8>>,
+    index_key: Vec<u8>,
+}
+
+impl Local {
+    pub(crate) fn parse(entries: &str) -> Result<Local, Error> {
+        let mut keys = BTreeMap::new();
+        let mut index_key = None;
+
+        for entry in entries
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|e| !e.is_empty() && !e.starts_with('#'))
+        {
+            let mut parts = entry.splitn(2, ':');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => return Err(Error),
+            };
+            let key = hex::decode(value).map_err(|_| Error)?;
+            if key.len() != KEY_LENGTH {
+                return Err(Error);
+            }
+
+            match name {
+                "index" => index_key = Some(key),
+                version => {
+                    keys.insert(version.parse::<u32>().map_err(|_| Error)?, key);
+                }
+            }
+        }
+
+        match (keys.is_empty(), index_key) {
+            (false, Some(index_key)) => Ok(Local { keys, index_key }),
+            _ => Err(Error),
+        }
+    }
+
+    pub(crate) fn from_file(path: &str) -> Result<Local, Error> {
+        Local::parse(&fs::read_to_string(path).map_err(|_| Error)?)
+    }
+
+    pub(crate) fn from_env(var: &str) -> Result<Local, Error> {
+        Local::parse(&env::var(var).map_err(|_| Error)?)
+    }
+}
+
+impl KeyProvider for Local {
+    fn current_version(&self) -> u32 {
+        *self.keys.keys().next_back().unwrap()
+    }
+
+    fn key(&self, version: u32) -> Result<Vec<u8>, Error> {
+        self.keys.get(&version).cloned().ok_or(Error)
+    }
+
+    fn index_key(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.index_key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_1: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    #[test]
+    fn parse() {
+        let act = Local::parse(&format!("1:{}, 2:{},index:{}", KEY_1, KEY_2, KEY_1)).unwrap();
+
+        assert_eq!(act.current_version(), 2);
+        assert_eq!(act.key(1), Ok(hex::decode(KEY_1).unwrap()));
+        assert_eq!(act.key(2), Ok(hex::decode(KEY_2).unwrap()));
+        assert_eq!(act.key(3), Err(Error));
+        assert_eq!(act.index_key(), Ok(hex::decode(KEY_1).unwrap()));
+    }
+
+    #[test]
+    fn parse_lines_with_comments() {
+        let act = Local::parse(&format!(
+            "# development keys\n7:{}\nindex:{}\n",
+            KEY_2, KEY_1
+        ));
+
+        assert_eq!(act.unwrap().current_version(), 7);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Local::parse("").is_err());
+        assert!(Local::parse(&format!("1:{}", KEY_1)).is_err());
+        assert!(Local::parse(&format!("index:{}", KEY_1)).is_err());
+        assert!(Local::parse(&format!("1:{},index:0a0b", KEY_1)).is_err());
+        assert!(Local::parse(&format!("one:{},index:{}", KEY_1, KEY_1)).is_err());
+        assert!(Local::parse(&format!("1={},index:{}", KEY_1, KEY_1)).is_err());
+    }
+
+    #[test]
+    fn from_file_and_env() {
+        let entries = format!("3:{}\nindex:{}", KEY_1, KEY_2);
+        let path = env::temp_dir().join("cards-local-keys");
+        fs::write(&path, &entries).unwrap();
+        env::set_var("CARDS_KEYS_TEST", &entries);
+
+        assert_eq!(
+            Local::from_file(path.to_str().unwrap())
+                .unwrap()
+                .current_version(),
+            3
+        );
+        assert_eq!(
+            Local::from_env("CARDS_KEYS_TEST")
+                .unwrap()
+                .current_version(),
+            3
+        );
+        assert!(Local::from_file("/nonexistent/cards-keys").is_err());
+        assert!(Local::from_env("CARDS_KEYS_UNSET").is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod envelope;
+pub(crate) mod key;
+pub(crate) mod password;
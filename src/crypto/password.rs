@@ -0,0 +1,102 @@
+use crate::domain::card;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use std::fmt::Error;
+
+// OWASP recommended Argon2id cost: 19 MiB of memory and two passes.
+pub static DEFAULT_MEMORY: u32 = 19 * 1024;
+pub static DEFAULT_ITERATIONS: u32 = 2;
+const SALT_LENGTH: usize = 16;
+
+// Hashes PINs with Argon2id into PHC strings, which carry their own salt and cost so hashes made
+// under previous parameters keep verifying.
+pub(crate) struct Hasher {
+    argon2: Argon2<'static>,
+}
+
+impl Hasher {
+    pub(crate) fn new(memory: u32, iterations: u32) -> Result<Hasher, Error> {
+        let params = Params::new(memory, iterations, 1, None).map_err(|_| Error)?;
+
+        Ok(Hasher {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+}
+
+impl card::PasswordHasher for Hasher {
+    fn hash(&self, password: &str) -> Result<String, card::Error> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let salt = SaltString::encode_b64(&salt).map_err(|_| card::Error::Generator)?;
+
+        match self.argon2.hash_password(password.as_bytes(), &salt) {
+            Ok(hash) => Ok(hash.to_string()),
+            Err(_) => Err(card::Error::Generator),
+        }
+    }
+
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, card::Error> {
+        let hash = PasswordHash::new(hash).map_err(|_| card::Error::Generator)?;
+
+        Ok(self
+            .argon2
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::PasswordHasher as _;
+
+    // cheap parameters keep the tests fast, the algorithm is the same
+    fn a_hasher() -> Hasher {
+        Hasher::new(64, 1).unwrap()
+    }
+
+    #[test]
+    fn hash_and_verify() {
+        let hasher = a_hasher();
+
+        let act = hasher.hash("517412").unwrap();
+
+        assert!(act.starts_with("$argon2id$"));
+        assert!(!act.contains("517412"));
+        assert_eq!(hasher.verify("517412", &act), Ok(true));
+        assert_eq!(hasher.verify("517413", &act), Ok(false));
+    }
+
+    #[test]
+    fn hash_is_salted() {
+        let hasher = a_hasher();
+
+        assert_ne!(hasher.hash("517412"), hasher.hash("517412"));
+    }
+
+    #[test]
+    fn verify_with_other_parameters() {
+        let hash = a_hasher().hash("517412").unwrap();
+
+        let act = Hasher::new(128, 2).unwrap().verify("517412", &hash);
+
+        assert_eq!(act, Ok(true));
+    }
+
+    #[test]
+    fn verify_malformed_hash() {
+        assert_eq!(
+            a_hasher().verify("517412", "517412"),
+            Err(card::Error::Generator)
+        );
+    }
+
+    #[test]
+    fn new_invalid_parameters() {
+        assert!(Hasher::new(1, 1).is_err());
+        assert!(Hasher::new(DEFAULT_MEMORY, 0).is_err());
+        assert!(Hasher::new(DEFAULT_MEMORY, DEFAULT_ITERATIONS).is_ok());
+    }
+}
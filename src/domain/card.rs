@@ -1,34 +1,123 @@
+use crate::domain::{embossing, luhn, program};
 use crate::protocol;
 use uuid::Uuid;
-use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use std::format;
-use std::fmt::Error;
 use regex::Regex;
-use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::fmt;
+use chrono::Datelike;
 
 enum Status {
     Enabled,
     Cancelled,
-    Blocked
+    Blocked,
+    Expired
 }
 impl Status {
+    fn from(description: &str) -> Result<Status, String> {
+        match description.to_uppercase().as_str() {
+            "ENABLED" => Ok(Status::Enabled),
+            "CANCELLED" => Ok(Status::Cancelled),
+            "BLOCKED" => Ok(Status::Blocked),
+            "EXPIRED" => Ok(Status::Expired),
+            _ => Err(format!("Unknown status {}", description))
+        }
+    }
+
     fn to_string(&self) -> Result<String, String> {
         match self {
             Status::Enabled => Ok("ENABLED".to_string()),
             Status::Cancelled => Ok("CANCELLED".to_string()),
             Status::Blocked => Ok("BLOCKED".to_string()),
+            Status::Expired => Ok("EXPIRED".to_string()),
+        }
+    }
+
+    // Cancelled and Expired are terminal: no transition leaves them.
+    fn transit(&self, transition: &Transition) -> Option<Status> {
+        match (self, transition) {
+            (Status::Enabled, Transition::Block) => Some(Status::Blocked),
+            (Status::Blocked, Transition::Unblock) => Some(Status::Enabled),
+            (Status::Enabled, Transition::Cancel) | (Status::Blocked, Transition::Cancel) => Some(Status::Cancelled),
+            (Status::Enabled, Transition::Expire) | (Status::Blocked, Transition::Expire) => Some(Status::Expired),
+            _ => None,
+        }
+    }
+}
+
+enum Transition {
+    Block,
+    Unblock,
+    Cancel,
+    // taken by the expiry sweep, never requested by a client
+    Expire,
+}
+
+impl Transition {
+    fn to_string(&self) -> Result<String, String> {
+        match self {
+            Transition::Block => Ok("BLOCK".to_string()),
+            Transition::Unblock => Ok("UNBLOCK".to_string()),
+            Transition::Cancel => Ok("CANCEL".to_string()),
+            Transition::Expire => Ok("EXPIRE".to_string()),
+        }
+    }
+}
+
+enum Reason {
+    Fraud,
+    Lost,
+    Stolen,
+    Damaged,
+    CustomerRequest,
+    IssuerRequest,
+    PasswordAttempts,
+    // the time to live of a temporary card elapsed
+    TimeToLive,
+    // the month of the expiration date is over
+    ExpirationDate,
+}
+
+impl Reason {
+    fn from(description: &str) -> Result<Reason, String> {
+        match description.to_uppercase().as_str() {
+            "FRAUD" => Ok(Reason::Fraud),
+            "LOST" => Ok(Reason::Lost),
+            "STOLEN" => Ok(Reason::Stolen),
+            "DAMAGED" => Ok(Reason::Damaged),
+            "CUSTOMER_REQUEST" => Ok(Reason::CustomerRequest),
+            "ISSUER_REQUEST" => Ok(Reason::IssuerRequest),
+            "PASSWORD_ATTEMPTS" => Ok(Reason::PasswordAttempts),
+            "TIME_TO_LIVE" => Ok(Reason::TimeToLive),
+            "EXPIRATION_DATE" => Ok(Reason::ExpirationDate),
+            _ => Err(format!("Unknown reason {}", description))
+        }
+    }
+
+    fn to_string(&self) -> Result<String, String> {
+        match self {
+            Reason::Fraud => Ok("FRAUD".to_string()),
+            Reason::Lost => Ok("LOST".to_string()),
+            Reason::Stolen => Ok("STOLEN".to_string()),
+            Reason::Damaged => Ok("DAMAGED".to_string()),
+            Reason::CustomerRequest => Ok("CUSTOMER_REQUEST".to_string()),
+            Reason::IssuerRequest => Ok("ISSUER_REQUEST".to_string()),
+            Reason::PasswordAttempts => Ok("PASSWORD_ATTEMPTS".to_string()),
+            Reason::TimeToLive => Ok("TIME_TO_LIVE".to_string()),
+            Reason::ExpirationDate => Ok("EXPIRATION_DATE".to_string()),
         }
     }
 }
 
-enum Kind {
+pub(crate) enum Kind {
     Plastic,
     Recurring,
     Temporary,
 }
 
 impl Kind {
-    fn from(description: &str) -> Result<Kind, String>  {
+    pub(crate) fn from(description: &str) -> Result<Kind, String>  {
         match description.to_uppercase().as_str() {
             "PLASTIC" => Ok(Kind::Plastic),
             "RECURRING" => Ok(Kind::Recurring),
@@ -37,7 +126,7 @@ impl Kind {
         }
     }
 
-    fn to_string(&self) -> Result<String, String> {
+    pub(crate) fn to_string(&self) -> Result<String, String> {
         match self {
             Kind::Plastic => Ok("PLASTIC".to_string()),
             Kind::Recurring => Ok("RECURRING".to_string()),
@@ -46,6 +135,38 @@ impl Kind {
     }
 }
 
+// Month a card expires in, printed as MMYY; the card stays valid until the end of that month.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+struct Expiry {
+    year: i32,
+    month: u32,
+}
+
+impl Expiry {
+    fn from(mmyy: &str) -> Result<Expiry, String> {
+        match (mmyy.get(..2).map(str::parse::<u32>), mmyy.get(2..).map(str::parse::<i32>)) {
+            (Some(Ok(month)), Some(Ok(year))) if mmyy.len() == 4 && (1..=12).contains(&month) => Ok(Expiry{year: 2000 + year, month}),
+            _ => Err(format!("Invalid expiration date {}", mmyy))
+        }
+    }
+
+    // The month of the given date.
+    fn of(date: chrono::NaiveDateTime) -> Expiry {
+        Expiry{year: date.year(), month: date.month()}
+    }
+
+    fn plus_months(&self, months: u32) -> Expiry {
+        let months = self.year * 12 + self.month as i32 - 1 + months as i32;
+        Expiry{year: months / 12, month: (months % 12) as u32 + 1}
+    }
+}
+
+impl fmt::Display for Expiry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}{:02}", self.month, self.year % 100)
+    }
+}
+
 struct Entity {
     id: uuid::Uuid,
     customer_id: uuid::Uuid,
@@ -54,247 +175,1700 @@ struct Entity {
     account_id: uuid::Uuid,
     printed_name: String,
     password: String,
-    expiration_date: String,
+    expiration_date: Expiry,
     issuing_date: chrono::NaiveDateTime,
     pan: String,
     kind: Kind,
     status: Status,
     cvv: String,
+    merchant_id: String,
+    mcc: String,
+}
+
+impl Entity {
+    fn to_protocol(&self) -> protocol::Card {
+        protocol::Card{
+            id: self.id.to_string(),
+            customer_id: self.customer_id.to_string(),
+            org_id: self.org_id.to_string(),
+            program_id: self.program_id.to_string(),
+            account_id: self.account_id.to_string(),
+            printed_name: self.printed_name.to_string(),
+            password: self.password.to_string(),
+            password_attempts: 0,
+            expiration_date: self.expiration_date.to_string(),
+            issuing_date: self.issuing_date.to_string(),
+            pan: self.pan.to_string(),
+            pan_hash: "".to_string(),
+            kind: self.kind.to_string().unwrap(),
+            status: self.status.to_string().unwrap(),
+            status_reason: "".to_string(),
+            status_updated_at: self.issuing_date.to_string(),
+            cvv: self.cvv.to_string(),
+            merchant_id: self.merchant_id.to_string(),
+            mcc: self.mcc.to_string(),
+            version: 1
+        }
+    }
+}
+
+// Failures of the card service and of the infrastructure behind it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    Invalid(Vec<protocol::ValidationError>),
+    NotFound,
+    Disallowed(protocol::TransitionError),
+    // no PAN left unused in the program BIN after the generator attempts
+    DuplicatePan,
+    // a card already saved under the id
+    DuplicateId,
+    // the card changed since the version the caller read
+    VersionMismatch,
+    // an Idempotency-Key sent again with another request body
+    KeyReused,
+    // an Idempotency-Key sent again before the first request finished
+    InProgress,
+    // the card kept changing under a write the service retries on its own
+    Contention,
+    Unavailable,
+    Generator,
+}
+
+pub trait PanGenerator: Send + Sync {
+    // Draws an unused PAN from the BIN range of the program.
+    fn generate(&self, program: &protocol::Program) -> Result<String, Error>;
+}
+
+pub trait CvvGenerator: Send + Sync {
+    fn generate(&self, pan: &str, expiration_date: &str) -> Result<String, Error>;
+}
+
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &str) -> Result<String, Error>;
+    fn verify(&self, password: &str, hash: &str) -> Result<bool, Error>;
+}
+
+pub trait UuidGenerator: Send + Sync {
+    fn generate(&self) -> Result<uuid::Uuid, Error>;
+}
+
+pub trait TimeService: Send + Sync {
+    fn now(&self) -> chrono::NaiveDateTime;
+}
+
+// One clock is shared by every service the composition root builds.
+impl<T: TimeService + ?Sized> TimeService for Arc<T> {
+    fn now(&self) -> chrono::NaiveDateTime { (**self).now() }
+}
+
+pub trait Repository: Send + Sync {
+    fn save(&self, card: &protocol::Card) -> Result<(), Error>;
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, Error>;
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, Error>;
+    // Replaces the stored card only if its version is the one before `card.version`, otherwise
+    // fails with VersionMismatch, so concurrent writers cannot overwrite each other.
+    fn update(&self, card: &protocol::Card) -> Result<(), Error>;
+    fn exists_pan(&self, pan: &str) -> Result<bool, Error>;
+}
+
+// One repository is shared by the service and the PAN generator, which checks PANs for reuse.
+impl<T: Repository + ?Sized> Repository for Arc<T> {
+    fn save(&self, card: &protocol::Card) -> Result<(), Error> { (**self).save(card) }
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, Error> { (**self).find_by_id(id) }
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, Error> { (**self).list(filter) }
+    fn update(&self, card: &protocol::Card) -> Result<(), Error> { (**self).update(card) }
+    fn exists_pan(&self, pan: &str) -> Result<bool, Error> { (**self).exists_pan(pan) }
+}
+
+const PASSWORD_PATTERN: &str = r"^\d{6}$";
+const EXPIRATION_DATE_PATTERN: &str = r"^(0[1-9]|1[0-2])\d{2}$";
+// Card acceptor identification code (ISO 8583 field 42) and merchant category code.
+const MERCHANT_ID_PATTERN: &str = r"^[A-Za-z0-9]{1,15}$";
+const MCC_PATTERN: &str = r"^\d{4}$";
+// How NaiveDateTime renders the issuing date the card is stored with.
+const ISSUING_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+const MAX_PASSWORD_ATTEMPTS: u32 = 3;
+// Times a write the caller did not pin to a version is tried before giving up on a busy card.
+const MAX_WRITE_ATTEMPTS: usize = 3;
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+
+// A card may expire no earlier than the current month and no later than the validity of its
+// program allows from the month it was issued in.
+fn within_validity(expiry: Expiry, issuing_date: chrono::NaiveDateTime, now: chrono::NaiveDateTime, program: &protocol::Program) -> bool {
+    Expiry::of(now) <= expiry && expiry <= Expiry::of(issuing_date).plus_months(program.validity_months)
+}
+
+// A merchant lock field is optional and only carried by recurring cards.
+fn validate_merchant_field(field: &str, value: &str, pattern: &str, recurring: bool) -> Option<protocol::ValidationError> {
+    match (value.is_empty(), recurring, Regex::new(pattern).unwrap().is_match(value)) {
+        (true, _, _) | (false, true, true) => None,
+        (false, false, _) => Some(invalid(field, value, protocol::Code::NotAllowed)),
+        (false, true, false) => Some(invalid(field, value, protocol::Code::PatternMismatch))
+    }
+}
+
+pub(crate) fn invalid(field: &str, value: &str, code: protocol::Code) -> protocol::ValidationError {
+    protocol::ValidationError::new(String::from(field), String::from(value), code)
+}
+
+// Reads and writes the card again while a concurrent writer gets there first. The caller named no
+// version, so losing the race is not theirs to resolve: it is retried, then reported as contention.
+fn retry_on_conflict<T>(mut write: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+    for _ in 0..MAX_WRITE_ATTEMPTS {
+        match write() {
+            Err(Error::VersionMismatch) => continue,
+            result => return result
+        }
+    }
+    Err(Error::Contention)
+}
+
+pub(crate) struct Service {
+    uuid_generator: Box<dyn UuidGenerator>,
+    time_service: Box<dyn TimeService>,
+    pan_generator: Box<dyn PanGenerator>,
+    cvv_generator: Box<dyn CvvGenerator>,
+    password_hasher: Box<dyn PasswordHasher>,
+    repository: Box<dyn Repository>,
+    programs: Box<dyn program::Repository>,
+}
+
+impl Service {
+    pub(crate) fn new(uuid_generator :Box<dyn UuidGenerator>, time_service :Box<dyn TimeService>,
+                      pan_generator :Box<dyn PanGenerator>, cvv_generator :Box<dyn CvvGenerator>,
+                      password_hasher :Box<dyn PasswordHasher>, repository :Box<dyn Repository>,
+                      programs :Box<dyn program::Repository>) -> Service {
+        Service {
+            uuid_generator,
+            time_service,
+            pan_generator,
+            cvv_generator,
+            password_hasher,
+            repository,
+            programs
+        }
+    }
+
+    fn validate(&self, card: protocol::Card) -> Result<Entity, Error> {
+        let mut errors = Vec::new();
+
+        macro_rules! validate_uuid_field {
+        ($field:tt, $field_str:expr) => {
+            let $field = match (card.$field.is_empty(), Uuid::parse_str(card.$field.as_str())) {
+                (false, Ok(id)) => id,
+                (true, _) => { errors.push(invalid($field_str, "", protocol::Code::Required)); Uuid::nil() },
+                (false, Err(_)) => { errors.push(invalid($field_str, &card.$field, protocol::Code::UuidFormat)); Uuid::nil() }
+            };
+        }}
+
+        macro_rules! validate_str_field_with_regex {
+        ($field:tt, $regex:expr, $field_str:expr) => {
+            let re = Regex::new($regex).unwrap();
+            match (card.$field.is_empty(), re.is_match(&card.$field)) {
+                (false, true) => (),
+                (true, _) => errors.push(invalid($field_str, "", protocol::Code::Required)),
+                (false, false) => errors.push(invalid($field_str, &card.$field, protocol::Code::PatternMismatch))
+            };
+            let $field = card.$field;
+        }}
+
+        validate_uuid_field!(customer_id, "customer_id");
+        validate_uuid_field!(org_id, "org_id");
+        validate_uuid_field!(program_id, "program_id");
+        validate_uuid_field!(account_id, "account_id");
+
+        // cards are only issued by a registered program that is still active
+        let program = match Uuid::parse_str(card.program_id.as_str()) {
+            Ok(_) => match self.programs.find_by_id(program_id)? {
+                Some(program) if program.active => Some(program),
+                Some(_) => { errors.push(invalid("program_id", &card.program_id, protocol::Code::Inactive)); None },
+                None => { errors.push(invalid("program_id", &card.program_id, protocol::Code::UnknownReference)); None }
+            },
+            Err(_) => None
+        };
+
+        // the name is embossed, so it is stored and returned the way the card will show it
+        let printed_name = match (card.printed_name.is_empty(), embossing::normalize(&card.printed_name)) {
+            (_, Ok(name)) => name,
+            (true, Err(_)) => { errors.push(invalid("printed_name", "", protocol::Code::Required)); String::new() },
+            (false, Err(code)) => { errors.push(invalid("printed_name", &card.printed_name, code)); String::new() }
+        };
+        validate_str_field_with_regex!(password, PASSWORD_PATTERN, "password");
+        // the security code is derived from the card data, never chosen by the client
+        if !card.cvv.is_empty() {
+            errors.push(invalid("cvv", &card.cvv, protocol::Code::NotAllowed))
+        }
+        // the expiry is derived from the program unless the client asks for one
+        let expiration_date = match (card.expiration_date.is_empty(), Regex::new(EXPIRATION_DATE_PATTERN).unwrap().is_match(&card.expiration_date)) {
+            (true, _) => None,
+            (false, true) => Expiry::from(&card.expiration_date).ok(),
+            (false, false) => { errors.push(invalid("expiration_date", &card.expiration_date, protocol::Code::PatternMismatch)); None }
+        };
+
+        let kind = match (card.kind.is_empty(), Kind::from(card.kind.as_str())) {
+            (_, Ok(k)) => Some(k),
+            (true, Err(_)) => { errors.push(invalid("kind", "", protocol::Code::Required)); None },
+            (false, Err(_)) => { errors.push(invalid("kind", &card.kind, protocol::Code::UnknownEnum)); None }
+        };
+
+        if let (Some(program), Some(k)) = (&program, &kind) {
+            if !program.kinds.contains(&k.to_string().unwrap()) {
+                errors.push(invalid("kind", &card.kind, protocol::Code::Unsupported))
+            }
+        }
+
+        // a recurring card may be locked to its merchant up front instead of on its first charge
+        let recurring = !matches!(kind, Some(Kind::Plastic) | Some(Kind::Temporary));
+        errors.extend(validate_merchant_field("merchant_id", &card.merchant_id, MERCHANT_ID_PATTERN, recurring));
+        errors.extend(validate_merchant_field("mcc", &card.mcc, MCC_PATTERN, recurring));
+
+        let issuing_date = self.time_service.now();
+        let expiration_date = match (&program, expiration_date) {
+            (Some(program), None) => Some(Expiry::of(issuing_date).plus_months(program.validity_months)),
+            (Some(program), Some(expiry)) if !within_validity(expiry, issuing_date, issuing_date, program) => {
+                errors.push(invalid("expiration_date", &card.expiration_date, protocol::Code::OutOfRange)); None
+            },
+            (_, expiry) => expiry
+        };
+
+        let (kind, program, expiration_date) = match (kind, program, expiration_date, errors.is_empty()) {
+            (Some(kind), Some(program), Some(expiration_date), true) => (kind, program, expiration_date),
+            _ => return Err(Error::Invalid(errors))
+        };
+
+        let pan = self.pan_generator.generate(&program)?;
+        let cvv = self.cvv_generator.generate(&pan, &expiration_date.to_string())?;
+        let password = self.password_hasher.hash(&password)?;
+
+        Ok(Entity{
+            id: self.uuid_generator.generate()?,
+            customer_id,
+            org_id,
+            program_id,
+            account_id,
+            printed_name,
+            password,
+            expiration_date,
+            issuing_date,
+            pan,
+            kind,
+            status: Status::Enabled,
+            cvv,
+            merchant_id: card.merchant_id,
+            mcc: card.mcc
+        })
+    }
+}
+
+pub trait Creator {
+    fn create(&self, dto: protocol::Card) -> Result<protocol::Card, Error>;
+}
+
+impl Creator for Service {
+    fn create(&self, input: protocol::Card) -> Result<protocol::Card, Error> {
+        let entity = self.validate(input)?;
+        let output = entity.to_protocol();
+        self.repository.save(&output)?;
+
+        Ok(output)
+    }
+}
+
+pub trait Finder {
+    fn find(&self, id: &str) -> Result<protocol::Card, Error>;
+    fn list(&self, filter: protocol::Filter) -> Result<protocol::Page, Error>;
+}
+
+impl Service {
+    fn validate_filter(&self, filter: protocol::Filter) -> Result<protocol::Filter, Vec<protocol::ValidationError>> {
+        let mut errors = Vec::new();
+
+        macro_rules! validate_optional_uuid_field {
+        ($field:tt, $field_str:expr) => {
+            let $field = match filter.$field.is_empty() {
+                true => filter.$field,
+                false => match Uuid::parse_str(filter.$field.as_str()) {
+                    Ok(id) => id.to_string(),
+                    Err(_) => { errors.push(invalid($field_str, &filter.$field, protocol::Code::UuidFormat)); filter.$field }
+                }
+            };
+        }}
+
+        validate_optional_uuid_field!(customer_id, "customer_id");
+        validate_optional_uuid_field!(account_id, "account_id");
+        validate_optional_uuid_field!(program_id, "program_id");
+        validate_optional_uuid_field!(org_id, "org_id");
+
+        let kind = match filter.kind.is_empty() {
+            true => filter.kind,
+            false => match Kind::from(filter.kind.as_str()) {
+                Ok(k) => k.to_string().unwrap(),
+                Err(_) => { errors.push(invalid("kind", &filter.kind, protocol::Code::UnknownEnum)); filter.kind }
+            }
+        };
+
+        let status = match filter.status.is_empty() {
+            true => filter.status,
+            false => match Status::from(filter.status.as_str()) {
+                Ok(s) => s.to_string().unwrap(),
+                Err(_) => { errors.push(invalid("status", &filter.status, protocol::Code::UnknownEnum)); filter.status }
+            }
+        };
+
+        let limit = match filter.limit {
+            0 => DEFAULT_PAGE_SIZE,
+            l if l <= MAX_PAGE_SIZE => l,
+            l => { errors.push(invalid("limit", &l.to_string(), protocol::Code::OutOfRange)); l }
+        };
+
+        if !errors.is_empty() {
+            return Err(errors)
+        }
+
+        Ok(protocol::Filter{
+            customer_id,
+            account_id,
+            program_id,
+            org_id,
+            kind,
+            status,
+            limit,
+            next_token: filter.next_token,
+        })
+    }
+}
+
+impl Service {
+    // The CVV must not be stored after issuing, so it is derived again for every card read.
+    fn with_cvv(&self, card: protocol::Card) -> Result<protocol::Card, Error> {
+        let cvv = self.cvv_generator.generate(&card.pan, &card.expiration_date)?;
+        Ok(protocol::Card{cvv, ..card})
+    }
+}
+
+impl Finder for Service {
+    fn find(&self, id: &str) -> Result<protocol::Card, Error> {
+        let id = match Uuid::parse_str(id) {
+            Ok(id) => id,
+            Err(_) => return Err(Error::Invalid(vec![invalid("id", id, protocol::Code::UuidFormat)]))
+        };
+
+        let card = self.repository.find_by_id(id)?.ok_or(Error::NotFound)?;
+        self.with_cvv(card)
+    }
+
+    fn list(&self, filter: protocol::Filter) -> Result<protocol::Page, Error> {
+        let filter = self.validate_filter(filter).map_err(Error::Invalid)?;
+
+        let page = self.repository.list(&filter)?;
+        let items = page.items.into_iter().map(|card| self.with_cvv(card)).collect::<Result<_, _>>()?;
+        Ok(protocol::Page::new(items, page.next_token))
+    }
+}
+
+impl Service {
+    // Applies the non-empty fields of `changes` over `stored`, checking only those fields. A new
+    // expiry is bounded by `program`, the one that issued the card, when it still exists, and a new
+    // kind must be one it issues.
+    fn merge(&self, stored: protocol::Card, changes: protocol::Card, program: Option<protocol::Program>) -> Result<protocol::Card, Vec<protocol::ValidationError>> {
+        let mut errors = Vec::new();
+
+        macro_rules! validate_immutable_field {
+        ($field:tt, $field_str:expr) => {
+            if !changes.$field.is_empty() && changes.$field != stored.$field {
+                errors.push(invalid($field_str, &changes.$field, protocol::Code::Immutable))
+            }
+        }}
+
+        macro_rules! validate_changed_field_with_regex {
+        ($field:tt, $regex:expr, $field_str:expr) => {
+            let re = Regex::new($regex).unwrap();
+            let $field = match (changes.$field.is_empty(), re.is_match(&changes.$field)) {
+                (true, _) => stored.$field,
+                (false, true) => changes.$field,
+                (false, false) => { errors.push(invalid($field_str, &changes.$field, protocol::Code::PatternMismatch)); changes.$field }
+            };
+        }}
+
+        validate_immutable_field!(id, "id");
+        validate_immutable_field!(customer_id, "customer_id");
+        validate_immutable_field!(org_id, "org_id");
+        validate_immutable_field!(program_id, "program_id");
+        validate_immutable_field!(account_id, "account_id");
+        // a PAN that fails its check digit is a typo rather than an attempt to change the card
+        match (changes.pan.is_empty(), luhn::is_valid(&changes.pan)) {
+            (false, false) => errors.push(invalid("pan", &changes.pan, protocol::Code::PatternMismatch)),
+            _ => validate_immutable_field!(pan, "pan")
+        }
+        validate_immutable_field!(issuing_date, "issuing_date");
+        validate_immutable_field!(status, "status");
+        validate_immutable_field!(status_reason, "status_reason");
+        validate_immutable_field!(status_updated_at, "status_updated_at");
+        validate_immutable_field!(cvv, "cvv");
+        let printed_name = match changes.printed_name.is_empty() {
+            true => stored.printed_name,
+            false => match embossing::normalize(&changes.printed_name) {
+                Ok(name) => name,
+                Err(code) => { errors.push(invalid("printed_name", &changes.printed_name, code)); changes.printed_name }
+            }
+        };
+        validate_changed_field_with_regex!(password, PASSWORD_PATTERN, "password");
+        let expiration_date_changed = !changes.expiration_date.is_empty();
+        validate_changed_field_with_regex!(expiration_date, EXPIRATION_DATE_PATTERN, "expiration_date");
+
+        if let (true, Ok(expiry)) = (expiration_date_changed, Expiry::from(&expiration_date)) {
+            let now = self.time_service.now();
+            let in_range = match (&program, chrono::NaiveDateTime::parse_from_str(&stored.issuing_date, ISSUING_DATE_FORMAT)) {
+                (Some(program), Ok(issuing_date)) => within_validity(expiry, issuing_date, now, program),
+                _ => Expiry::of(now) <= expiry
+            };
+            if !in_range {
+                errors.push(invalid("expiration_date", &expiration_date, protocol::Code::OutOfRange))
+            }
+        }
+
+        let kind = match changes.kind.is_empty() {
+            true => stored.kind,
+            false => match Kind::from(changes.kind.as_str()) {
+                Ok(k) => {
+                    let k = k.to_string().unwrap();
+                    let issued = matches!(&program, Some(program) if program.kinds.contains(&k));
+                    if k != stored.kind && !issued {
+                        errors.push(invalid("kind", &changes.kind, protocol::Code::Unsupported))
+                    }
+                    k
+                },
+                Err(_) => { errors.push(invalid("kind", &changes.kind, protocol::Code::UnknownEnum)); changes.kind }
+            }
+        };
+
+        // the merchant lock is set once, on a card without one, and keeps the card recurring
+        let recurring = !matches!(Kind::from(&kind), Ok(Kind::Plastic) | Ok(Kind::Temporary));
+        let locked = !stored.merchant_id.is_empty() || !stored.mcc.is_empty();
+        if locked && !recurring {
+            errors.push(invalid("kind", &kind, protocol::Code::NotAllowed))
+        }
+
+        macro_rules! validate_merchant_lock_field {
+        ($field:tt, $regex:expr, $field_str:expr) => {
+            let $field = match (changes.$field.is_empty(), locked) {
+                (true, _) => stored.$field,
+                (false, true) => {
+                    if changes.$field != stored.$field {
+                        errors.push(invalid($field_str, &changes.$field, protocol::Code::Immutable))
+                    }
+                    stored.$field
+                },
+                (false, false) => {
+                    errors.extend(validate_merchant_field($field_str, &changes.$field, $regex, recurring));
+                    changes.$field
+                }
+            };
+        }}
+
+        validate_merchant_lock_field!(merchant_id, MERCHANT_ID_PATTERN, "merchant_id");
+        validate_merchant_lock_field!(mcc, MCC_PATTERN, "mcc");
+
+        if !errors.is_empty() {
+            return Err(errors)
+        }
+
+        Ok(protocol::Card{
+            printed_name,
+            password,
+            expiration_date,
+            kind,
+            merchant_id,
+            mcc,
+            ..stored
+        })
+    }
+}
+
+pub trait Updater {
+    fn update(&self, id: &str, version: u64, changes: protocol::Card) -> Result<protocol::Card, Error>;
+}
+
+impl Service {
+    // Reads the card the caller means to change, which must still be at the version it read.
+    fn find_version(&self, id: &str, version: u64) -> Result<protocol::Card, Error> {
+        let stored = self.find(id)?;
+        match stored.version == version {
+            true => Ok(stored),
+            false => Err(Error::VersionMismatch)
+        }
+    }
+}
+
+impl Updater for Service {
+    fn update(&self, id: &str, version: u64, changes: protocol::Card) -> Result<protocol::Card, Error> {
+        let stored = self.find_version(id, version)?;
+        let expiration_date = stored.expiration_date.clone();
+        let password_changed = !changes.password.is_empty();
+        let program = match Uuid::parse_str(&stored.program_id) {
+            Ok(program_id) if !changes.expiration_date.is_empty() || !changes.kind.is_empty() => self.programs.find_by_id(program_id)?,
+            _ => None
+        };
+        let mut output = self.merge(stored, changes, program).map_err(Error::Invalid)?;
+
+        if password_changed {
+            output.password = self.password_hasher.hash(&output.password)?;
+            output.password_attempts = 0;
+        }
+
+        // the CVV depends on the expiry, so a new expiration date means a new code
+        if output.expiration_date != expiration_date {
+            output.cvv = self.cvv_generator.generate(&output.pan, &output.expiration_date)?;
+        }
+        output.version = version + 1;
+
+        self.repository.update(&output)?;
+
+        Ok(output)
+    }
 }
 
-impl Entity {
-    fn to_protocol(&self) -> protocol::Card {
-        protocol::Card{
-            id: self.id.to_string(),
-            customer_id: self.customer_id.to_string(),
-            org_id: self.org_id.to_string(),
-            program_id: self.program_id.to_string(),
-            account_id: self.program_id.to_string(),
-            printed_name: self.printed_name.to_string(),
-            password: self.password.to_string(),
-            expiration_date: self.expiration_date.to_string(),
-            issuing_date: self.issuing_date.to_string(),
-            pan: self.pan.to_string(),
-            kind: self.kind.to_string().unwrap(),
-            status: self.status.to_string().unwrap(),
-            cvv: self.cvv.to_string()
+pub trait Lifecycle {
+    fn block(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error>;
+    fn unblock(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error>;
+    fn cancel(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error>;
+}
+
+impl Service {
+    fn transit(&self, id: &str, version: u64, transition: Transition, change: protocol::StatusChange) -> Result<protocol::Card, Error> {
+        let reason = match (change.reason.is_empty(), Reason::from(change.reason.as_str())) {
+            (_, Ok(r)) => r,
+            (true, Err(_)) => return Err(Error::Invalid(vec![invalid("reason", "", protocol::Code::Required)])),
+            (false, Err(_)) => return Err(Error::Invalid(vec![invalid("reason", &change.reason, protocol::Code::UnknownEnum)]))
+        };
+
+        let stored = self.find_version(id, version)?;
+        let status = match Status::from(stored.status.as_str()).ok().and_then(|s| s.transit(&transition)) {
+            Some(s) => s,
+            None => return Err(Error::Disallowed(protocol::TransitionError::new(stored.status, transition.to_string().unwrap())))
+        };
+
+        let password_attempts = match transition {
+            Transition::Unblock => 0,
+            _ => stored.password_attempts,
+        };
+        let output = protocol::Card{
+            status: status.to_string().unwrap(),
+            status_reason: reason.to_string().unwrap(),
+            status_updated_at: self.time_service.now().to_string(),
+            password_attempts,
+            version: version + 1,
+            ..stored
+        };
+
+        self.repository.update(&output)?;
+
+        Ok(output)
+    }
+}
+
+impl Lifecycle for Service {
+    fn block(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error> {
+        self.transit(id, version, Transition::Block, change)
+    }
+
+    fn unblock(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error> {
+        self.transit(id, version, Transition::Unblock, change)
+    }
+
+    fn cancel(&self, id: &str, version: u64, change: protocol::StatusChange) -> Result<protocol::Card, Error> {
+        self.transit(id, version, Transition::Cancel, change)
+    }
+}
+
+pub trait Expirer {
+    // Moves every enabled or blocked card whose time ran out to EXPIRED, returning how many.
+    fn expire(&self) -> Result<usize, Error>;
+}
+
+// Why the card ran out of time by `now`, if it did: a temporary card lives the time to live of its
+// program from its issuing date, and every card stops at the end of its expiration month.
+fn expiry_reason(card: &protocol::Card, program: Option<&protocol::Program>, now: chrono::NaiveDateTime) -> Option<Reason> {
+    let issuing_date = chrono::NaiveDateTime::parse_from_str(&card.issuing_date, ISSUING_DATE_FORMAT);
+    match (Kind::from(&card.kind), program, issuing_date) {
+        (Ok(Kind::Temporary), Some(program), Ok(issuing_date)) if program.temporary_ttl_hours > 0
+            && issuing_date + chrono::Duration::hours(program.temporary_ttl_hours as i64) <= now => return Some(Reason::TimeToLive),
+        _ => ()
+    };
+
+    match Expiry::from(&card.expiration_date) {
+        Ok(expiry) if expiry < Expiry::of(now) => Some(Reason::ExpirationDate),
+        _ => None
+    }
+}
+
+impl Expirer for Service {
+    fn expire(&self) -> Result<usize, Error> {
+        let now = self.time_service.now();
+        let mut programs: HashMap<String, Option<protocol::Program>> = HashMap::new();
+        let mut expired = 0;
+
+        for status in [Status::Enabled, Status::Blocked].iter() {
+            let mut filter = protocol::Filter{status: status.to_string().unwrap(), limit: MAX_PAGE_SIZE, ..Default::default()};
+            loop {
+                let page = self.repository.list(&filter)?;
+                for card in page.items {
+                    if !programs.contains_key(&card.program_id) {
+                        let program = match Uuid::parse_str(&card.program_id) {
+                            Ok(id) => self.programs.find_by_id(id)?,
+                            Err(_) => None
+                        };
+                        programs.insert(card.program_id.clone(), program);
+                    }
+                    let reason = match expiry_reason(&card, programs[&card.program_id].as_ref(), now) {
+                        Some(reason) => reason,
+                        None => continue
+                    };
+                    match self.transit(&card.id, card.version, Transition::Expire, protocol::StatusChange{reason: reason.to_string().unwrap()}) {
+                        Ok(_) => expired += 1,
+                        // changed since it was listed, the next sweep looks at it again
+                        Err(Error::VersionMismatch) | Err(Error::NotFound) | Err(Error::Disallowed(_)) => (),
+                        Err(err) => return Err(err)
+                    }
+                }
+                match page.next_token.is_empty() {
+                    true => break,
+                    false => filter.next_token = page.next_token
+                }
+            }
+        }
+
+        Ok(expired)
+    }
+}
+
+pub trait Verifier {
+    fn verify_password(&self, id: &str, attempt: protocol::PasswordAttempt) -> Result<protocol::PasswordVerification, Error>;
+}
+
+impl Service {
+    // Counts one more attempt on the enabled card before its password is compared, so every guess is on record,
+    // concurrent ones included, whatever it turns out to be.
+    fn count_password_attempt(&self, id: &str) -> Result<protocol::Card, Error> {
+        retry_on_conflict(|| {
+            let stored = self.find(id)?;
+            match Status::from(stored.status.as_str()) {
+                Ok(Status::Enabled) => (),
+                _ => return Err(Error::Disallowed(protocol::TransitionError::new(stored.status, String::from("VERIFY_PASSWORD"))))
+            };
+
+            let output = protocol::Card{password_attempts: stored.password_attempts + 1, version: stored.version + 1, ..stored};
+            self.repository.update(&output)?;
+
+            Ok(output)
+        })
+    }
+
+    // Writes what the counted attempt ended in: the right password clears the count, the last wrong one blocks the card.
+    fn settle_password_attempt(&self, id: &str, valid: bool) -> Result<(), Error> {
+        retry_on_conflict(|| {
+            let stored = self.find(id)?;
+            let version = stored.version + 1;
+            let output = match (valid, Status::from(stored.status.as_str())) {
+                (true, Ok(Status::Enabled)) => protocol::Card{password_attempts: 0, version, ..stored},
+                (false, Ok(Status::Enabled)) => protocol::Card{
+                    status: Status::Blocked.to_string().unwrap(),
+                    status_reason: Reason::PasswordAttempts.to_string().unwrap(),
+                    status_updated_at: self.time_service.now().to_string(),
+                    version,
+                    ..stored
+                },
+                // already blocked by a concurrent attempt
+                (false, _) => return Ok(()),
+                (true, _) => return Err(Error::Disallowed(protocol::TransitionError::new(stored.status, String::from("VERIFY_PASSWORD"))))
+            };
+
+            self.repository.update(&output)
+        })
+    }
+}
+
+impl Verifier for Service {
+    // Every attempt is counted on the card before the password is compared, and only a match clears the count.
+    // The card gets blocked once MAX_PASSWORD_ATTEMPTS wrong ones are counted.
+    fn verify_password(&self, id: &str, attempt: protocol::PasswordAttempt) -> Result<protocol::PasswordVerification, Error> {
+        match (attempt.password.is_empty(), Regex::new(PASSWORD_PATTERN).unwrap().is_match(&attempt.password)) {
+            (false, true) => (),
+            (true, _) => return Err(Error::Invalid(vec![invalid("password", "", protocol::Code::Required)])),
+            (false, false) => return Err(Error::Invalid(vec![invalid("password", &attempt.password, protocol::Code::PatternMismatch)]))
+        };
+
+        let counted = self.count_password_attempt(id)?;
+        // concurrent attempts used up the count before this one, which is not compared at all
+        if counted.password_attempts > MAX_PASSWORD_ATTEMPTS {
+            self.settle_password_attempt(id, false)?;
+            return Ok(protocol::PasswordVerification::new(false, 0))
+        }
+
+        let valid = self.password_hasher.verify(&attempt.password, &counted.password)?;
+        let remaining_attempts = match valid {
+            true => MAX_PASSWORD_ATTEMPTS,
+            false => MAX_PASSWORD_ATTEMPTS - counted.password_attempts,
+        };
+        if valid || remaining_attempts == 0 {
+            self.settle_password_attempt(id, valid)?;
+        }
+
+        Ok(protocol::PasswordVerification::new(valid, remaining_attempts))
+    }
+}
+
+pub trait MerchantChecker {
+    fn check_merchant(&self, id: &str, check: protocol::MerchantCheck) -> Result<protocol::MerchantVerification, Error>;
+}
+
+impl MerchantChecker for Service {
+    // Only recurring cards are locked: a locked card allows the merchant that matches every field
+    // of its lock, and an unlocked one allows any. The check changes nothing unless `lock` asks to
+    // lock an unlocked card to the merchant, as its first charge does.
+    fn check_merchant(&self, id: &str, check: protocol::MerchantCheck) -> Result<protocol::MerchantVerification, Error> {
+        let mut errors = Vec::new();
+        for (field, value, pattern) in [("merchant_id", &check.merchant_id, MERCHANT_ID_PATTERN), ("mcc", &check.mcc, MCC_PATTERN)].iter() {
+            match (value.is_empty(), Regex::new(pattern).unwrap().is_match(value)) {
+                (false, true) => (),
+                (true, _) => errors.push(invalid(field, "", protocol::Code::Required)),
+                (false, false) => errors.push(invalid(field, value, protocol::Code::PatternMismatch))
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::Invalid(errors))
+        }
+
+        retry_on_conflict(|| {
+            let stored = self.find(id)?;
+            match Status::from(stored.status.as_str()) {
+                Ok(Status::Enabled) => (),
+                _ => return Err(Error::Disallowed(protocol::TransitionError::new(stored.status, String::from("CHECK_MERCHANT"))))
+            };
+
+            let locked = !stored.merchant_id.is_empty() || !stored.mcc.is_empty();
+            match (Kind::from(&stored.kind), locked) {
+                (Ok(Kind::Recurring), true) => {
+                    let allowed = (stored.merchant_id.is_empty() || stored.merchant_id == check.merchant_id)
+                        && (stored.mcc.is_empty() || stored.mcc == check.mcc);
+                    return Ok(protocol::MerchantVerification::new(allowed))
+                },
+                (Ok(Kind::Recurring), false) if check.lock => (),
+                _ => return Ok(protocol::MerchantVerification::new(true))
+            };
+
+            let output = protocol::Card{
+                merchant_id: check.merchant_id.clone(),
+                mcc: check.mcc.clone(),
+                version: stored.version + 1,
+                ..stored
+            };
+            // a version mismatch means a concurrent charge may have locked it, so it is checked again
+            self.repository.update(&output)?;
+
+            Ok(protocol::MerchantVerification::new(true))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol;
+    use super::*;
+    use crate::crypto::envelope::Cipher;
+    use crate::crypto::key::Local;
+    use crate::repository::{encrypted, memory};
+    use chrono::NaiveDate;
+
+    struct Mock {}
+
+    impl UuidGenerator for Mock {
+        fn generate(&self) -> Result<uuid::Uuid, Error> {
+            Ok(uuid::Uuid::default())
+        }
+    }
+
+    impl TimeService for Mock {
+        fn now(&self) -> chrono::NaiveDateTime {
+            NaiveDate::from_ymd(2021, 2, 16).and_hms(19, 20, 0)
+        }
+    }
+
+    impl PanGenerator for Mock {
+        fn generate(&self, _program: &protocol::Program) -> Result<String, Error> {
+            Ok(String::from("4012000033330026"))
+        }
+    }
+
+    impl CvvGenerator for Mock {
+        fn generate(&self, _pan: &str, _expiration_date: &str) -> Result<String, Error> {
+            Ok(String::from("945"))
+        }
+    }
+
+    impl PasswordHasher for Mock {
+        fn hash(&self, password: &str) -> Result<String, Error> {
+            Ok(format!("hashed:{}", password))
+        }
+
+        fn verify(&self, password: &str, hash: &str) -> Result<bool, Error> {
+            Ok(hash == format!("hashed:{}", password))
+        }
+    }
+
+    impl Repository for Mock {
+        fn save(&self, card: &protocol::Card) -> Result<(), Error> {
+            match card.printed_name.as_str() {
+                UNWRITABLE_NAME => Err(Error::Unavailable),
+                _ => Ok(()),
+            }
+        }
+
+        fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, Error> {
+            let stored = match id.to_string().as_str() {
+                STORED_ID => Ok(Some(a_stored_card())),
+                BLOCKED_ID => Ok(Some(protocol::Card{id: BLOCKED_ID.to_string(), status: "BLOCKED".to_string(), ..a_stored_card()})),
+                LOCKED_ID => Ok(Some(protocol::Card{id: LOCKED_ID.to_string(), status: "BLOCKED".to_string(), password_attempts: MAX_PASSWORD_ATTEMPTS, ..a_stored_card()})),
+                CANCELLED_ID => Ok(Some(protocol::Card{id: CANCELLED_ID.to_string(), status: "CANCELLED".to_string(), ..a_stored_card()})),
+                EXPIRED_ID => Ok(Some(protocol::Card{id: EXPIRED_ID.to_string(), status: "EXPIRED".to_string(), ..a_stored_card()})),
+                LAST_ATTEMPT_ID => Ok(Some(protocol::Card{id: LAST_ATTEMPT_ID.to_string(), password_attempts: MAX_PASSWORD_ATTEMPTS - 1, ..a_stored_card()})),
+                RECURRING_ID => Ok(Some(protocol::Card{id: RECURRING_ID.to_string(), kind: "RECURRING".to_string(), ..a_stored_card()})),
+                MERCHANT_LOCKED_ID => Ok(Some(protocol::Card{id: MERCHANT_LOCKED_ID.to_string(), kind: "RECURRING".to_string(), merchant_id: A_MERCHANT_ID.to_string(), mcc: "4899".to_string(), ..a_stored_card()})),
+                MCC_LOCKED_ID => Ok(Some(protocol::Card{id: MCC_LOCKED_ID.to_string(), kind: "RECURRING".to_string(), mcc: "4899".to_string(), ..a_stored_card()})),
+                PLASTIC_ONLY_ID => Ok(Some(protocol::Card{id: PLASTIC_ONLY_ID.to_string(), program_id: PLASTIC_PROGRAM_ID.to_string(), ..a_stored_card()})),
+                ORPHAN_ID => Ok(Some(protocol::Card{id: ORPHAN_ID.to_string(), program_id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..a_stored_card()})),
+                UNAVAILABLE_ID => Err(Error::Unavailable),
+                _ => Ok(None),
+            };
+            // cards are stored without their CVV
+            stored.map(|card| card.map(|card| protocol::Card{cvv: "".to_string(), ..card}))
+        }
+
+        fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, Error> {
+            match filter.account_id.as_str() {
+                UNAVAILABLE_ID => Err(Error::Unavailable),
+                // echoes the filter received so tests can assert how it was normalized
+                _ => Ok(protocol::Page::new(vec![protocol::Card{cvv: "".to_string(), ..a_stored_card()}], serde_json::to_string(filter).unwrap())),
+            }
+        }
+
+        fn update(&self, card: &protocol::Card) -> Result<(), Error> {
+            match card.printed_name.as_str() {
+                UNWRITABLE_NAME => Err(Error::Unavailable),
+                _ => Ok(()),
+            }
+        }
+
+        fn exists_pan(&self, _pan: &str) -> Result<bool, Error> {
+            Ok(false)
+        }
+    }
+
+    impl program::Repository for Mock {
+        fn save(&self, _program: &protocol::Program) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Program>, Error> {
+            match id.to_string().as_str() {
+                PROGRAM_ID => Ok(Some(a_program())),
+                PLASTIC_PROGRAM_ID => Ok(Some(protocol::Program{id: PLASTIC_PROGRAM_ID.to_string(), kinds: vec!["PLASTIC".to_string()], ..a_program()})),
+                INACTIVE_PROGRAM_ID => Ok(Some(protocol::Program{id: INACTIVE_PROGRAM_ID.to_string(), active: false, ..a_program()})),
+                UNAVAILABLE_ID => Err(Error::Unavailable),
+                _ => Ok(None),
+            }
+        }
+
+        fn list(&self) -> Result<Vec<protocol::Program>, Error> {
+            Ok(vec![a_program()])
+        }
+
+        fn update(&self, _program: &protocol::Program) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn delete(&self, _id: uuid::Uuid) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    // Infrastructure that always fails with the given error.
+    struct Failing(Error);
+
+    impl UuidGenerator for Failing {
+        fn generate(&self) -> Result<uuid::Uuid, Error> {
+            Err(self.0.clone())
+        }
+    }
+
+    impl PanGenerator for Failing {
+        fn generate(&self, _program: &protocol::Program) -> Result<String, Error> {
+            Err(self.0.clone())
+        }
+    }
+
+    impl program::Repository for Failing {
+        fn save(&self, _program: &protocol::Program) -> Result<(), Error> {
+            Err(self.0.clone())
+        }
+
+        fn find_by_id(&self, _id: uuid::Uuid) -> Result<Option<protocol::Program>, Error> {
+            Err(self.0.clone())
+        }
+
+        fn list(&self) -> Result<Vec<protocol::Program>, Error> {
+            Err(self.0.clone())
+        }
+
+        fn update(&self, _program: &protocol::Program) -> Result<(), Error> {
+            Err(self.0.clone())
+        }
+
+        fn delete(&self, _id: uuid::Uuid) -> Result<(), Error> {
+            Err(self.0.clone())
+        }
+    }
+
+    // Cards of the Mock whose every write loses to a concurrent one, counting the writes tried.
+    #[derive(Default)]
+    struct Contended(std::sync::atomic::AtomicUsize);
+
+    impl Repository for Contended {
+        fn save(&self, card: &protocol::Card) -> Result<(), Error> {
+            Repository::save(&Mock{}, card)
+        }
+
+        fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, Error> {
+            Repository::find_by_id(&Mock{}, id)
+        }
+
+        fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, Error> {
+            Repository::list(&Mock{}, filter)
+        }
+
+        fn update(&self, _card: &protocol::Card) -> Result<(), Error> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(Error::VersionMismatch)
+        }
+
+        fn exists_pan(&self, pan: &str) -> Result<bool, Error> {
+            Repository::exists_pan(&Mock{}, pan)
+        }
+    }
+
+    const STORED_ID: &str = "5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10";
+    const UNAVAILABLE_ID: &str = "0d1c4a8e-2f6b-4c1e-9a7d-8e5b3f2a1c90";
+    const UNWRITABLE_NAME: &str = "UNWRITABLE";
+    const BLOCKED_ID: &str = "3c9a6d2e-8b1f-4e7a-9c5d-2f4b6a8e1c3d";
+    const CANCELLED_ID: &str = "7e1b3d5f-9a2c-4b6e-8d0f-1a3c5e7b9d2f";
+    const EXPIRED_ID: &str = "2d4f6b8a-0c2e-4f6a-8b0d-2e4a6c8f0b1d";
+    const LAST_ATTEMPT_ID: &str = "4a2c6e8b-1d3f-4a5c-9e7b-3d5f7a9c1e2b";
+    const LOCKED_ID: &str = "8b4d2f6a-5c1e-4d3b-a7f9-6e2c4a8d0b1f";
+    const RECURRING_ID: &str = "1f3b5d7a-9c2e-4b4d-8f6a-0c2e4a6b8d1f";
+    const MERCHANT_LOCKED_ID: &str = "6c8e0a2b-4d6f-4a8c-9e0b-2d4f6a8c0e3b";
+    const MCC_LOCKED_ID: &str = "9a1c3e5b-7d9f-4c1e-a3b5-7f9b1d3e5a4c";
+    const PLASTIC_ONLY_ID: &str = "3e5a7c9b-1d3f-4e5a-b7c9-1d3f5a7c9e5b";
+    const ORPHAN_ID: &str = "5b7d9f1a-3c5e-4f7b-9d1f-3a5c7e9b1d6c";
+    const A_MERCHANT_ID: &str = "000000000012345";
+    const STORED_VERSION: u64 = 4;
+    const PROGRAM_ID: &str = "c0a4cc71-5c11-43cb-b74f-2b577012449f";
+    const PLASTIC_PROGRAM_ID: &str = "6d8e0f2a-4b6c-4d8e-a0f2-4b6c8d0e2f4a";
+    const INACTIVE_PROGRAM_ID: &str = "9e1f3a5c-7b9d-4f1a-b3c5-7d9f1b3d5f7a";
+
+    macro_rules! test_invalid_field {
+    ($name:ident, $input:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.create($input).unwrap_err();
+
+            assert_eq!(act, Error::Invalid(vec![$exp]));
+        }
+    }}
+
+    test_invalid_field!(test_invalid_customer_id, a_card_without_customer_id(), empty_error("customer_id"));
+    test_invalid_field!(test_invalid_org_id, a_card_without_org_id(), empty_error("org_id"));
+    test_invalid_field!(test_invalid_program_id, a_card_without_program_id(), empty_error("program_id"));
+    test_invalid_field!(test_invalid_printed_name, a_card_without_printed_name(), empty_error("printed_name"));
+    test_invalid_field!(test_invalid_password, a_card_without_password(), empty_error("password"));
+    test_invalid_field!(test_invalid_kind, a_card_without_kind(), empty_error("kind"));
+    test_invalid_field!(test_invalid_printed_name_invalid_characters_number, a_card_with_invalid_printed_name("R1CARDO"), invalid_error("printed_name", "R1CARDO", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_printed_name_too_long, a_card_with_invalid_printed_name("BARTHOLOMEW WOLFESCHLEGELSTEINHAUSEN"), invalid_error("printed_name", "BARTHOLOMEW WOLFESCHLEGELSTEINHAUSEN", protocol::Code::OutOfRange));
+    test_invalid_field!(test_invalid_printed_name_only_whitespace, a_card_with_invalid_printed_name("  "), invalid_error("printed_name", "  ", protocol::Code::Required));
+    test_invalid_field!(test_invalid_printed_name_invalid_characters_special_characters, a_card_with_invalid_printed_name("#RIC*RDO"), invalid_error("printed_name", "#RIC*RDO", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_password_with_letters, a_card_with_invalid_password("0912C8"), invalid_error("password", "0912C8", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_password_with_more_than_six_characters, a_card_with_invalid_password("091261128"), invalid_error("password", "091261128", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_cvv_supplied, a_card_with_cvv("451"), invalid_error("cvv", "451", protocol::Code::NotAllowed));
+    test_invalid_field!(test_invalid_cvv_supplied_with_letters, a_card_with_cvv("0B12"), invalid_error("cvv", "0B12", protocol::Code::NotAllowed));
+    test_invalid_field!(test_invalid_expiration_date_with_letters, a_card_with_invalid_expiration_date("ABCEFG"), invalid_error("expiration_date", "ABCEFG", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_expiration_date_with_invalid_month, a_card_with_invalid_expiration_date("1300"), invalid_error("expiration_date", "1300", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_expiration_date_with_month_zero, a_card_with_invalid_expiration_date("0000"), invalid_error("expiration_date", "0000", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_expiration_date_in_the_past, a_card_with_invalid_expiration_date("0121"), invalid_error("expiration_date", "0121", protocol::Code::OutOfRange));
+    test_invalid_field!(test_invalid_expiration_date_beyond_program_validity, a_card_with_invalid_expiration_date("0325"), invalid_error("expiration_date", "0325", protocol::Code::OutOfRange));
+    test_invalid_field!(test_invalid_merchant_id_of_plastic_card, protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), ..a_card_with_program(PROGRAM_ID, "PLASTIC")}, invalid_error("merchant_id", A_MERCHANT_ID, protocol::Code::NotAllowed));
+    test_invalid_field!(test_invalid_mcc_of_temporary_card, protocol::Card{mcc: "4899".to_string(), ..a_card_with_program(PROGRAM_ID, "TEMPORARY")}, invalid_error("mcc", "4899", protocol::Code::NotAllowed));
+    test_invalid_field!(test_invalid_merchant_id_too_long, protocol::Card{merchant_id: "0000000000123456".to_string(), ..a_card_with_program(PROGRAM_ID, "RECURRING")}, invalid_error("merchant_id", "0000000000123456", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_merchant_id_with_symbols, protocol::Card{merchant_id: "NETFLIX#1".to_string(), ..a_card_with_program(PROGRAM_ID, "RECURRING")}, invalid_error("merchant_id", "NETFLIX#1", protocol::Code::PatternMismatch));
+    test_invalid_field!(test_invalid_mcc_with_letters, protocol::Card{mcc: "48A9".to_string(), ..a_card_with_program(PROGRAM_ID, "RECURRING")}, invalid_error("mcc", "48A9", protocol::Code::PatternMismatch));
+
+    #[test]
+    fn create_collects_every_invalid_field() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let input = protocol::Card{
+            customer_id: "R1CARDO".to_string(),
+            printed_name: "R1CARDO".to_string(),
+            expiration_date: "1300".to_string(),
+            kind: "GOLD".to_string(),
+            cvv: "451".to_string(),
+            ..a_card_without_kind()
+        };
+
+        let act = svc.create(input).unwrap_err();
+
+        assert_eq!(act, Error::Invalid(vec![
+            invalid_error("customer_id", "R1CARDO", protocol::Code::UuidFormat),
+            invalid_error("printed_name", "R1CARDO", protocol::Code::PatternMismatch),
+            invalid_error("cvv", "451", protocol::Code::NotAllowed),
+            invalid_error("expiration_date", "1300", protocol::Code::PatternMismatch),
+            invalid_error("kind", "GOLD", protocol::Code::UnknownEnum),
+        ]));
+    }
+
+    #[test]
+    fn create() {
+        let pan_generator_mock = Box::new(Mock{});
+        let cvv_generator_mock = Box::new(Mock{});
+        let password_hasher_mock = Box::new(Mock{});
+        let repository_mock = Box::new(Mock{});
+        let uuid_generator_mock = Box::new(Mock{});
+        let time_service_mock = Box::new(Mock{});
+        let svc = Service::new(uuid_generator_mock, time_service_mock,
+                               pan_generator_mock, cvv_generator_mock, password_hasher_mock, repository_mock, Box::new(Mock{}));
+        let input = protocol::Card{
+            id: "".to_string(),
+            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
+            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
+            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO".to_string(),
+            password: "517412".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
+            issuing_date: "".to_string(),
+            pan: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
+            status: "".to_string(),
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
+        };
+
+        let act = svc.create(input).unwrap();
+
+        let exp = protocol::Card{
+            id: uuid::Uuid::default().to_string(),
+            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
+            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
+            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO".to_string(),
+            password: "hashed:517412".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
+            issuing_date: "2021-02-16 19:20:00".to_string(),
+            pan: "4012000033330026".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
+            status: "ENABLED".to_string(),
+            status_reason: "".to_string(),
+            status_updated_at: "2021-02-16 19:20:00".to_string(),
+            cvv: "945".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 1
+        };
+        assert_eq!(act, exp);
+    }
+
+    #[test]
+    fn create_derives_expiration_date_from_program_validity() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.create(a_card_without_expiration_date()).unwrap();
+
+        assert_eq!(act.expiration_date, "0225");
+    }
+
+    #[test]
+    fn create_accepts_expiration_date_within_program_validity() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.create(a_card_with_invalid_expiration_date("0221")).unwrap();
+
+        assert_eq!(act.expiration_date, "0221");
+    }
+
+    #[test]
+    fn create_recurring_card_locked_to_a_merchant() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let input = protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), mcc: "4899".to_string(), ..a_card_with_program(PROGRAM_ID, "RECURRING")};
+
+        let act = svc.create(input).unwrap();
+
+        assert_eq!(act.kind, "RECURRING");
+        assert_eq!(act.merchant_id, A_MERCHANT_ID);
+        assert_eq!(act.mcc, "4899");
+    }
+
+    #[test]
+    fn create_normalizes_printed_name() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.create(a_card_with_invalid_printed_name("Riçardo  O\u{2019}Neil")).unwrap();
+
+        assert_eq!(act.printed_name, "RICARDO O'NEIL");
+    }
+
+    macro_rules! test_create_failure {
+    ($name:ident, $uuid_generator:expr, $pan_generator:expr, $printed_name:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new($uuid_generator), Box::new(Mock{}), Box::new($pan_generator), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+            let input = protocol::Card{printed_name: $printed_name.to_string(), ..a_card_without_kind()};
+
+            let act = svc.create(protocol::Card{kind: "PLASTIC".to_string(), ..input});
+
+            assert_eq!(act, Err($exp));
+        }
+    }}
+
+    test_create_failure!(test_create_unavailable, Mock{}, Mock{}, UNWRITABLE_NAME, Error::Unavailable);
+    test_create_failure!(test_create_duplicate_pan, Mock{}, Failing(Error::DuplicatePan), "RICARDO", Error::DuplicatePan);
+    test_create_failure!(test_create_pan_generator_failure, Mock{}, Failing(Error::Generator), "RICARDO", Error::Generator);
+    test_create_failure!(test_create_uuid_generator_failure, Failing(Error::Generator), Mock{}, "RICARDO", Error::Generator);
+
+    test_invalid_field!(test_create_unknown_program, a_card_with_program("9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", "PLASTIC"), invalid_error("program_id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::UnknownReference));
+    test_invalid_field!(test_create_inactive_program, a_card_with_program(INACTIVE_PROGRAM_ID, "PLASTIC"), invalid_error("program_id", INACTIVE_PROGRAM_ID, protocol::Code::Inactive));
+    test_invalid_field!(test_create_kind_not_issued_by_program, a_card_with_program(PLASTIC_PROGRAM_ID, "recurring"), invalid_error("kind", "recurring", protocol::Code::Unsupported));
+
+    #[test]
+    fn create_programs_unavailable() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.create(a_card_with_program(UNAVAILABLE_ID, "PLASTIC"));
+
+        assert_eq!(act, Err(Error::Unavailable));
+    }
+
+    macro_rules! test_find {
+    ($name:ident, $id:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.find($id);
+
+            assert_eq!(act, $exp);
+        }
+    }}
+
+    test_find!(test_find_stored, STORED_ID, Ok(a_stored_card()));
+    test_find!(test_find_not_found, "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", Err(Error::NotFound));
+    test_find!(test_find_unavailable, UNAVAILABLE_ID, Err(Error::Unavailable));
+    test_find!(test_find_invalid_id, "R1CARDO", Err(Error::Invalid(vec![invalid_error("id", "R1CARDO", protocol::Code::UuidFormat)])));
+
+    macro_rules! test_list_invalid_filter {
+    ($name:ident, $input:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.list($input).unwrap_err();
+
+            assert_eq!(act, Error::Invalid(vec![$exp]));
+        }
+    }}
+
+    test_list_invalid_filter!(test_list_invalid_customer_id, protocol::Filter{customer_id: "R1CARDO".to_string(), ..Default::default()}, invalid_error("customer_id", "R1CARDO", protocol::Code::UuidFormat));
+    test_list_invalid_filter!(test_list_invalid_account_id, protocol::Filter{account_id: "123".to_string(), ..Default::default()}, invalid_error("account_id", "123", protocol::Code::UuidFormat));
+    test_list_invalid_filter!(test_list_invalid_program_id, protocol::Filter{program_id: "123".to_string(), ..Default::default()}, invalid_error("program_id", "123", protocol::Code::UuidFormat));
+    test_list_invalid_filter!(test_list_invalid_org_id, protocol::Filter{org_id: "123".to_string(), ..Default::default()}, invalid_error("org_id", "123", protocol::Code::UuidFormat));
+    test_list_invalid_filter!(test_list_invalid_kind, protocol::Filter{kind: "GOLD".to_string(), ..Default::default()}, invalid_error("kind", "GOLD", protocol::Code::UnknownEnum));
+    test_list_invalid_filter!(test_list_invalid_status, protocol::Filter{status: "LOST".to_string(), ..Default::default()}, invalid_error("status", "LOST", protocol::Code::UnknownEnum));
+    test_list_invalid_filter!(test_list_invalid_limit, protocol::Filter{limit: 101, ..Default::default()}, invalid_error("limit", "101", protocol::Code::OutOfRange));
+
+    #[test]
+    fn list() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let input = protocol::Filter{
+            customer_id: "A3643446-76FC-4516-8E43-BB6600CA118E".to_string(),
+            kind: "plastic".to_string(),
+            status: "Blocked".to_string(),
+            next_token: "eyJJRCI6eyJTIjoiMSJ9fQ".to_string(),
+            ..Default::default()
+        };
+        let exp = protocol::Filter{
+            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
+            kind: "PLASTIC".to_string(),
+            status: "BLOCKED".to_string(),
+            limit: DEFAULT_PAGE_SIZE,
+            next_token: "eyJJRCI6eyJTIjoiMSJ9fQ".to_string(),
+            ..Default::default()
+        };
+
+        let act = svc.list(input).unwrap();
+
+        assert_eq!(act, protocol::Page::new(vec![a_stored_card()], serde_json::to_string(&exp).unwrap()));
+    }
+
+    #[test]
+    fn list_unavailable() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.list(protocol::Filter{account_id: UNAVAILABLE_ID.to_string(), ..Default::default()});
+
+        assert_eq!(act, Err(Error::Unavailable));
+    }
+
+    macro_rules! test_update_invalid_field {
+    ($name:ident, $changes:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.update(STORED_ID, STORED_VERSION, $changes).unwrap_err();
+
+            assert_eq!(act, Error::Invalid(vec![$exp]));
+        }
+    }}
+
+    test_update_invalid_field!(test_update_id, protocol::Card{id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..Default::default()}, invalid_error("id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_customer_id, protocol::Card{customer_id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..Default::default()}, invalid_error("customer_id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_org_id, protocol::Card{org_id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..Default::default()}, invalid_error("org_id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_program_id, protocol::Card{program_id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..Default::default()}, invalid_error("program_id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_account_id, protocol::Card{account_id: "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d".to_string(), ..Default::default()}, invalid_error("account_id", "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_pan, protocol::Card{pan: "5214330278318136".to_string(), ..Default::default()}, invalid_error("pan", "5214330278318136", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_pan_with_invalid_check_digit, protocol::Card{pan: "4012000033330027".to_string(), ..Default::default()}, invalid_error("pan", "4012000033330027", protocol::Code::PatternMismatch));
+    test_update_invalid_field!(test_update_issuing_date, protocol::Card{issuing_date: "2021-03-16 19:20:00".to_string(), ..Default::default()}, invalid_error("issuing_date", "2021-03-16 19:20:00", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_invalid_printed_name, protocol::Card{printed_name: "R1CARDO".to_string(), ..Default::default()}, invalid_error("printed_name", "R1CARDO", protocol::Code::PatternMismatch));
+    test_update_invalid_field!(test_update_printed_name_too_long, protocol::Card{printed_name: "BARTHOLOMEW WOLFESCHLEGELSTEINHAUSEN".to_string(), ..Default::default()}, invalid_error("printed_name", "BARTHOLOMEW WOLFESCHLEGELSTEINHAUSEN", protocol::Code::OutOfRange));
+    test_update_invalid_field!(test_update_invalid_password, protocol::Card{password: "0912C8".to_string(), ..Default::default()}, invalid_error("password", "0912C8", protocol::Code::PatternMismatch));
+    test_update_invalid_field!(test_update_cvv, protocol::Card{cvv: "123".to_string(), ..Default::default()}, invalid_error("cvv", "123", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_invalid_expiration_date, protocol::Card{expiration_date: "1300".to_string(), ..Default::default()}, invalid_error("expiration_date", "1300", protocol::Code::PatternMismatch));
+    test_update_invalid_field!(test_update_expiration_date_in_the_past, protocol::Card{expiration_date: "0121".to_string(), ..Default::default()}, invalid_error("expiration_date", "0121", protocol::Code::OutOfRange));
+    test_update_invalid_field!(test_update_expiration_date_beyond_program_validity, protocol::Card{expiration_date: "0126".to_string(), ..Default::default()}, invalid_error("expiration_date", "0126", protocol::Code::OutOfRange));
+    test_update_invalid_field!(test_update_invalid_kind, protocol::Card{kind: "GOLD".to_string(), ..Default::default()}, invalid_error("kind", "GOLD", protocol::Code::UnknownEnum));
+    test_update_invalid_field!(test_update_status, protocol::Card{status: "BLOCKED".to_string(), ..Default::default()}, invalid_error("status", "BLOCKED", protocol::Code::Immutable));
+    test_update_invalid_field!(test_update_merchant_id_of_plastic_card, protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), ..Default::default()}, invalid_error("merchant_id", A_MERCHANT_ID, protocol::Code::NotAllowed));
+
+    macro_rules! test_update_merchant_lock {
+    ($name:ident, $id:expr, $changes:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.update($id, STORED_VERSION, $changes).map(|card| (card.kind, card.merchant_id, card.mcc));
+
+            assert_eq!(act, $exp);
+        }
+    }}
+
+    test_update_merchant_lock!(test_update_locks_recurring_card, RECURRING_ID, protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), mcc: "4899".to_string(), ..Default::default()}, Ok(("RECURRING".to_string(), A_MERCHANT_ID.to_string(), "4899".to_string())));
+    test_update_merchant_lock!(test_update_locks_card_made_recurring, STORED_ID, protocol::Card{kind: "RECURRING".to_string(), mcc: "4899".to_string(), ..Default::default()}, Ok(("RECURRING".to_string(), "".to_string(), "4899".to_string())));
+    test_update_merchant_lock!(test_update_same_merchant_lock, MERCHANT_LOCKED_ID, protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), ..Default::default()}, Ok(("RECURRING".to_string(), A_MERCHANT_ID.to_string(), "4899".to_string())));
+    test_update_merchant_lock!(test_update_invalid_mcc_lock, RECURRING_ID, protocol::Card{mcc: "489".to_string(), ..Default::default()}, Err(Error::Invalid(vec![invalid_error("mcc", "489", protocol::Code::PatternMismatch)])));
+    test_update_merchant_lock!(test_update_other_merchant_lock, MERCHANT_LOCKED_ID, protocol::Card{merchant_id: "000000000067890".to_string(), ..Default::default()}, Err(Error::Invalid(vec![invalid_error("merchant_id", "000000000067890", protocol::Code::Immutable)])));
+    test_update_merchant_lock!(test_update_adds_to_merchant_lock, MCC_LOCKED_ID, protocol::Card{merchant_id: A_MERCHANT_ID.to_string(), ..Default::default()}, Err(Error::Invalid(vec![invalid_error("merchant_id", A_MERCHANT_ID, protocol::Code::Immutable)])));
+    test_update_merchant_lock!(test_update_kind_of_locked_card, MERCHANT_LOCKED_ID, protocol::Card{kind: "plastic".to_string(), ..Default::default()}, Err(Error::Invalid(vec![invalid_error("kind", "PLASTIC", protocol::Code::NotAllowed)])));
+
+    macro_rules! test_update_kind {
+    ($name:ident, $id:expr, $kind:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.update($id, STORED_VERSION, protocol::Card{kind: $kind.to_string(), ..Default::default()}).map(|card| card.kind);
+
+            assert_eq!(act, $exp);
+        }
+    }}
+
+    test_update_kind!(test_update_kind_issued_by_program, STORED_ID, "temporary", Ok("TEMPORARY".to_string()));
+    test_update_kind!(test_update_kind_not_issued_by_program, PLASTIC_ONLY_ID, "TEMPORARY", Err(Error::Invalid(vec![invalid_error("kind", "TEMPORARY", protocol::Code::Unsupported)])));
+    test_update_kind!(test_update_same_kind_not_issued_by_program, PLASTIC_ONLY_ID, "PLASTIC", Ok("PLASTIC".to_string()));
+    test_update_kind!(test_update_kind_of_card_without_program, ORPHAN_ID, "RECURRING", Err(Error::Invalid(vec![invalid_error("kind", "RECURRING", protocol::Code::Unsupported)])));
+
+    #[test]
+    fn update_collects_every_invalid_field() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let changes = protocol::Card{
+            pan: "5214330278318136".to_string(),
+            password: "0912C8".to_string(),
+            kind: "GOLD".to_string(),
+            ..Default::default()
+        };
+
+        let act = svc.update(STORED_ID, STORED_VERSION, changes).unwrap_err();
+
+        assert_eq!(act, Error::Invalid(vec![
+            invalid_error("pan", "5214330278318136", protocol::Code::Immutable),
+            invalid_error("password", "0912C8", protocol::Code::PatternMismatch),
+            invalid_error("kind", "GOLD", protocol::Code::UnknownEnum),
+        ]));
+    }
+
+    #[test]
+    fn update() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let changes = protocol::Card{
+            id: STORED_ID.to_string(),
+            pan: "4012000033330026".to_string(),
+            printed_name: "Ricardo São Medeiros".to_string(),
+            password: "123456".to_string(),
+            kind: "temporary".to_string(),
+            ..Default::default()
+        };
+        let exp = protocol::Card{
+            printed_name: "RICARDO SAO MEDEIROS".to_string(),
+            password: "hashed:123456".to_string(),
+            kind: "TEMPORARY".to_string(),
+            version: STORED_VERSION + 1,
+            ..a_stored_card()
+        };
+
+        let act = svc.update(STORED_ID, STORED_VERSION, changes);
+
+        assert_eq!(act, Ok(exp));
+    }
+
+    #[test]
+    fn update_expiration_date_regenerates_cvv() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let changes = protocol::Card{expiration_date: "0824".to_string(), ..Default::default()};
+        let exp = protocol::Card{
+            expiration_date: "0824".to_string(),
+            cvv: "945".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: STORED_VERSION + 1,
+            ..a_stored_card()
+        };
+
+        let act = svc.update(STORED_ID, STORED_VERSION, changes);
+
+        assert_eq!(act, Ok(exp));
+    }
+
+    #[test]
+    fn update_not_found() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+        let act = svc.update("9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", STORED_VERSION, protocol::Card::default());
+
+        assert_eq!(act, Err(Error::NotFound));
+    }
+
+    #[test]
+    fn update_stale_version() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let changes = protocol::Card{printed_name: "RICARDO MEDEIROS".to_string(), ..Default::default()};
+
+        let act = svc.update(STORED_ID, STORED_VERSION - 1, changes);
+
+        assert_eq!(act, Err(Error::VersionMismatch));
+    }
+
+    #[test]
+    fn update_unavailable() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+        let changes = protocol::Card{printed_name: UNWRITABLE_NAME.to_string(), ..Default::default()};
+
+        let act = svc.update(STORED_ID, STORED_VERSION, changes);
+
+        assert_eq!(act, Err(Error::Unavailable));
+    }
+
+    macro_rules! test_transition {
+    ($name:ident, $method:ident, $id:expr, $reason:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.$method($id, STORED_VERSION, protocol::StatusChange{reason: $reason.to_string()});
+
+            assert_eq!(act, $exp);
+        }
+    }}
+
+    test_transition!(test_block_enabled, block, STORED_ID, "fraud", Ok(a_card_with_status(STORED_ID, "BLOCKED", "FRAUD")));
+    test_transition!(test_cancel_enabled, cancel, STORED_ID, "CUSTOMER_REQUEST", Ok(a_card_with_status(STORED_ID, "CANCELLED", "CUSTOMER_REQUEST")));
+    test_transition!(test_unblock_blocked, unblock, BLOCKED_ID, "ISSUER_REQUEST", Ok(a_card_with_status(BLOCKED_ID, "ENABLED", "ISSUER_REQUEST")));
+    test_transition!(test_cancel_blocked, cancel, BLOCKED_ID, "STOLEN", Ok(a_card_with_status(BLOCKED_ID, "CANCELLED", "STOLEN")));
+    test_transition!(test_unblock_enabled, unblock, STORED_ID, "LOST", Err(disallowed_error("ENABLED", "UNBLOCK")));
+    test_transition!(test_block_blocked, block, BLOCKED_ID, "LOST", Err(disallowed_error("BLOCKED", "BLOCK")));
+    test_transition!(test_block_cancelled, block, CANCELLED_ID, "LOST", Err(disallowed_error("CANCELLED", "BLOCK")));
+    test_transition!(test_unblock_cancelled, unblock, CANCELLED_ID, "LOST", Err(disallowed_error("CANCELLED", "UNBLOCK")));
+    test_transition!(test_cancel_cancelled, cancel, CANCELLED_ID, "LOST", Err(disallowed_error("CANCELLED", "CANCEL")));
+    test_transition!(test_unblock_expired, unblock, EXPIRED_ID, "ISSUER_REQUEST", Err(disallowed_error("EXPIRED", "UNBLOCK")));
+    test_transition!(test_cancel_expired, cancel, EXPIRED_ID, "LOST", Err(disallowed_error("EXPIRED", "CANCEL")));
+    test_transition!(test_block_invalid_reason, block, STORED_ID, "BORED", Err(Error::Invalid(vec![invalid_error("reason", "BORED", protocol::Code::UnknownEnum)])));
+    test_transition!(test_block_without_reason, block, STORED_ID, "", Err(Error::Invalid(vec![empty_error("reason")])));
+    test_transition!(test_block_not_found, block, "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", "LOST", Err(Error::NotFound));
+    test_transition!(test_block_invalid_id, block, "R1CARDO", "LOST", Err(Error::Invalid(vec![invalid_error("id", "R1CARDO", protocol::Code::UuidFormat)])));
+
+    macro_rules! test_verify_password {
+    ($name:ident, $id:expr, $password:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.verify_password($id, protocol::PasswordAttempt{password: $password.to_string()});
+
+            assert_eq!(act, $exp);
+        }
+    }}
+
+    test_verify_password!(test_verify_password_valid, STORED_ID, "517412", Ok(protocol::PasswordVerification::new(true, MAX_PASSWORD_ATTEMPTS)));
+    test_verify_password!(test_verify_password_wrong, STORED_ID, "517413", Ok(protocol::PasswordVerification::new(false, MAX_PASSWORD_ATTEMPTS - 1)));
+    test_verify_password!(test_verify_password_valid_resets_attempts, LAST_ATTEMPT_ID, "517412", Ok(protocol::PasswordVerification::new(true, MAX_PASSWORD_ATTEMPTS)));
+    test_verify_password!(test_verify_password_last_attempt, LAST_ATTEMPT_ID, "517413", Ok(protocol::PasswordVerification::new(false, 0)));
+    test_verify_password!(test_verify_password_blocked, BLOCKED_ID, "517412", Err(disallowed_error("BLOCKED", "VERIFY_PASSWORD")));
+    test_verify_password!(test_verify_password_cancelled, CANCELLED_ID, "517412", Err(disallowed_error("CANCELLED", "VERIFY_PASSWORD")));
+    test_verify_password!(test_verify_password_invalid, STORED_ID, "51741", Err(Error::Invalid(vec![invalid_error("password", "51741", protocol::Code::PatternMismatch)])));
+    test_verify_password!(test_verify_password_not_found, "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", "517412", Err(Error::NotFound));
+
+    #[test]
+    fn verify_password_gives_up_on_a_busy_card() {
+        let cards = Arc::new(Contended::default());
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(cards.clone()), Box::new(Mock{}));
+
+        // the right password is not told apart from a wrong one until the attempt is counted
+        let act = svc.verify_password(STORED_ID, protocol::PasswordAttempt{password: "517412".to_string()});
+
+        assert_eq!(act, Err(Error::Contention));
+        assert_eq!(cards.0.load(std::sync::atomic::Ordering::SeqCst), MAX_WRITE_ATTEMPTS);
+    }
+
+    macro_rules! test_check_merchant {
+    ($name:ident, $id:expr, $merchant_id:expr, $mcc:expr, $exp:expr) => {
+        #[test]
+        fn $name() {
+            let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
+
+            let act = svc.check_merchant($id, protocol::MerchantCheck{merchant_id: $merchant_id.to_string(), mcc: $mcc.to_string(), lock: false});
+
+            assert_eq!(act, $exp);
         }
+    }}
+
+    test_check_merchant!(test_check_merchant_of_plastic_card, STORED_ID, A_MERCHANT_ID, "4899", Ok(protocol::MerchantVerification::new(true)));
+    test_check_merchant!(test_check_merchant_of_unlocked_card, RECURRING_ID, A_MERCHANT_ID, "4899", Ok(protocol::MerchantVerification::new(true)));
+    test_check_merchant!(test_check_merchant_of_the_lock, MERCHANT_LOCKED_ID, A_MERCHANT_ID, "4899", Ok(protocol::MerchantVerification::new(true)));
+    test_check_merchant!(test_check_other_merchant, MERCHANT_LOCKED_ID, "000000000067890", "4899", Ok(protocol::MerchantVerification::new(false)));
+    test_check_merchant!(test_check_merchant_of_other_category, MERCHANT_LOCKED_ID, A_MERCHANT_ID, "5812", Ok(protocol::MerchantVerification::new(false)));
+    test_check_merchant!(test_check_any_merchant_of_the_locked_category, MCC_LOCKED_ID, "000000000067890", "4899", Ok(protocol::MerchantVerification::new(true)));
+    test_check_merchant!(test_check_merchant_of_other_locked_category, MCC_LOCKED_ID, A_MERCHANT_ID, "5812", Ok(protocol::MerchantVerification::new(false)));
+    test_check_merchant!(test_check_merchant_of_blocked_card, BLOCKED_ID, A_MERCHANT_ID, "4899", Err(disallowed_error("BLOCKED", "CHECK_MERCHANT")));
+    test_check_merchant!(test_check_merchant_invalid, STORED_ID, "", "48A9", Err(Error::Invalid(vec![empty_error("merchant_id"), invalid_error("mcc", "48A9", protocol::Code::PatternMismatch)])));
+    test_check_merchant!(test_check_merchant_not_found, "9b2d4c1e-7a3f-4e5b-8c6d-1f0e2a3b4c5d", A_MERCHANT_ID, "4899", Err(Error::NotFound));
+
+    #[test]
+    fn check_merchant_gives_up_on_a_busy_card() {
+        let cards = Arc::new(Contended::default());
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(cards.clone()), Box::new(Mock{}));
+
+        let act = svc.check_merchant(RECURRING_ID, protocol::MerchantCheck{merchant_id: A_MERCHANT_ID.to_string(), mcc: "4899".to_string(), lock: true});
+
+        assert_eq!(act, Err(Error::Contention));
+        assert_eq!(cards.0.load(std::sync::atomic::Ordering::SeqCst), MAX_WRITE_ATTEMPTS);
     }
-}
 
-pub trait PanGenerator {
-    fn generate(&self, program_id: uuid::Uuid) -> Result<String, Error>;
-}
+    #[test]
+    fn check_merchant_without_lock_writes_nothing() {
+        let cards = Arc::new(Contended::default());
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(cards.clone()), Box::new(Mock{}));
 
-pub trait UuidGenerator {
-    fn generate(&self) -> Result<uuid::Uuid, Error>;
-}
+        let act = svc.check_merchant(RECURRING_ID, protocol::MerchantCheck{merchant_id: A_MERCHANT_ID.to_string(), mcc: "4899".to_string(), lock: false});
 
-pub trait TimeService {
-    fn now(&self) -> chrono::NaiveDateTime;
-}
+        assert_eq!(act, Ok(protocol::MerchantVerification::new(true)));
+        assert_eq!(cards.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
 
-pub trait Repository {
-    fn save(&self, card: &protocol::Card) -> Option<Error>;
-}
+    // The memory repository behind the encrypted one, as wired by the composition root, since backends only take sealed cards.
+    fn a_memory_repository() -> encrypted::Repository {
+        let keys = "1:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f,index:1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+        encrypted::Repository::new(Box::new(memory::Repository::new()), Cipher::new(Box::new(Local::parse(keys).unwrap())))
+    }
 
-pub(crate) struct Service {
-    uuid_generator: Box<dyn UuidGenerator>,
-    time_service: Box<dyn TimeService>,
-    pan_generator: Box<dyn PanGenerator>,
-    repository: Box<dyn Repository>,
-}
+    #[test]
+    fn check_merchant_locks_with_the_memory_repository() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(a_memory_repository()), Box::new(Mock{}));
+        let created = svc.create(a_card_with_program(PROGRAM_ID, "RECURRING")).unwrap();
+        let check = |merchant_id: &str, lock: bool| svc.check_merchant(&created.id, protocol::MerchantCheck{merchant_id: merchant_id.to_string(), mcc: "4899".to_string(), lock});
 
-impl Service {
-    pub(crate) fn new(uuid_generator :Box<dyn UuidGenerator>, time_service :Box<dyn TimeService>,
-                      pan_generator :Box<dyn PanGenerator>, repository :Box<dyn Repository>) -> Service {
-        Service {
-            uuid_generator,
-            time_service,
-            pan_generator,
-            repository
-        }
+        assert_eq!(check("000000000067890", false), Ok(protocol::MerchantVerification::new(true)));
+        assert_eq!(svc.find(&created.id).unwrap().version, created.version);
+        assert_eq!(check(A_MERCHANT_ID, true), Ok(protocol::MerchantVerification::new(true)));
+        assert_eq!(check("000000000067890", true), Ok(protocol::MerchantVerification::new(false)));
+        assert_eq!(check(A_MERCHANT_ID, false), Ok(protocol::MerchantVerification::new(true)));
+
+        let locked = svc.find(&created.id).unwrap();
+        assert_eq!((locked.merchant_id.as_str(), locked.mcc.as_str()), (A_MERCHANT_ID, "4899"));
+        assert_eq!(locked.version, created.version + 1);
     }
 
-    fn validate(&self, card: protocol::Card) -> Result<Entity, protocol::ValidationError> {
-        macro_rules! validate_uuid_field {
-        ($field:tt, $field_str:expr) => {
-            let $field = match Uuid::parse_str(card.$field.as_str()) {
-                Ok(ci) => ci, // TODO: check how to evaluate field_str
-                Err(err) => return Err(protocol::ValidationError::new(String::from($field_str), card.$field.clone()))
-            };
-        }}
+    #[test]
+    fn block_stale_version() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
 
-        macro_rules! validate_str_field {
-        ($field:tt, $field_str:expr) => {
-            let $field = match card.$field.is_empty() { // TODO: the same above
-                true => return Err(protocol::ValidationError::new(String::from($field_str), card.$field.clone())),
-                false => card.$field
-            };
-        }}
+        let act = svc.block(STORED_ID, STORED_VERSION + 1, protocol::StatusChange{reason: "LOST".to_string()});
 
-        macro_rules! validate_str_field_with_regex {
-        ($field:tt, $regex:expr, $field_str:expr) => {
-            let re = Regex::new($regex).unwrap();
-            let $field = match re.is_match(&card.$field) {
-                true => card.$field,
-                false => return Err(protocol::ValidationError::new(String::from($field_str), card.$field.clone()))
-            };
-        }}
+        assert_eq!(act, Err(Error::VersionMismatch));
+    }
 
-        validate_uuid_field!(customer_id, "customer_id");
-        validate_uuid_field!(org_id, "org_id");
-        validate_uuid_field!(program_id, "program_id");
-        validate_uuid_field!(account_id, "account_id");
-        validate_str_field_with_regex!(printed_name, r"^[A-Z\s]+$", "printed_name");
-        validate_str_field_with_regex!(password, r"^\d{6}$", "password");
-        validate_str_field_with_regex!(cvv, r"^\d{3}\d?$", "cvv");
-        validate_str_field_with_regex!(expiration_date, r"^(0\d|1[0-2])\d{2}$", "expiration_date");
-
-        let kind = match Kind::from(card.kind.as_str()) {
-            Ok(k) => k,
-            Err(_) => return Err(protocol::ValidationError::new(String::from("kind"), card.kind))
-        };
+    #[test]
+    fn unblock_resets_password_attempts() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}));
 
-        Ok(Entity{
-            id: self.uuid_generator.generate().unwrap(),
-            customer_id,
-            org_id,
-            program_id,
-            account_id,
-            printed_name,
-            password,
-            expiration_date,
-            issuing_date: self.time_service.now(), //NaiveDateTime::parse_from_str("2020-04-12", "%Y-%m-%d").unwrap(),
-            pan: self.pan_generator.generate(program_id).unwrap(),
-            kind,
-            status: Status::Enabled,
-            cvv
-        })
+        let act = svc.unblock(LOCKED_ID, STORED_VERSION, protocol::StatusChange{reason: "CUSTOMER_REQUEST".to_string()}).unwrap();
+
+        assert_eq!(act.password_attempts, 0);
     }
-}
 
-pub trait Creator {
-    fn create(&self, dto: protocol::Card) -> Result<protocol::Card, protocol::ValidationError>;
-}
+    #[test]
+    fn verify_password_with_the_memory_repository() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(a_memory_repository()), Box::new(Mock{}));
+        let created = svc.create(protocol::Card{kind: "PLASTIC".to_string(), ..a_card_without_kind()}).unwrap();
+        let verify = |password: &str| svc.verify_password(&created.id, protocol::PasswordAttempt{password: password.to_string()});
 
-impl Creator for Service {
-    fn create(&self, input: protocol::Card) -> Result<protocol::Card, protocol::ValidationError> {
-        let entity = self.validate(input)?;
-        let output = entity.to_protocol();
-        self.repository.save(&output);
+        assert_eq!(verify("517413"), Ok(protocol::PasswordVerification::new(false, MAX_PASSWORD_ATTEMPTS - 1)));
+        assert_eq!(verify("321421"), Ok(protocol::PasswordVerification::new(true, MAX_PASSWORD_ATTEMPTS)));
+        assert_eq!(svc.find(&created.id).unwrap().password_attempts, 0);
+        assert_eq!(verify("517413"), Ok(protocol::PasswordVerification::new(false, 2)));
+        assert_eq!(verify("517413"), Ok(protocol::PasswordVerification::new(false, 1)));
+        assert_eq!(verify("517413"), Ok(protocol::PasswordVerification::new(false, 0)));
 
-        Ok(output)
+        let blocked = svc.find(&created.id).unwrap();
+        assert_eq!((blocked.status.as_str(), blocked.status_reason.as_str(), blocked.password_attempts), ("BLOCKED", "PASSWORD_ATTEMPTS", MAX_PASSWORD_ATTEMPTS));
+        assert_eq!(verify("321421"), Err(disallowed_error("BLOCKED", "VERIFY_PASSWORD")));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::protocol;
-    use super::*;
-    use chrono::Utc;
+    #[test]
+    fn verify_password_past_the_count_is_not_compared() {
+        let cards = Arc::new(a_memory_repository());
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(cards.clone()), Box::new(Mock{}));
+        // left enabled by a concurrent attempt that counted the last one but did not get to block it
+        let stored = protocol::Card{password_attempts: MAX_PASSWORD_ATTEMPTS, ..a_stored_card()};
+        Repository::save(&cards, &stored).unwrap();
 
-    struct Mock {}
+        let act = svc.verify_password(STORED_ID, protocol::PasswordAttempt{password: "517412".to_string()});
 
-    impl UuidGenerator for Mock {
-        fn generate(&self) -> Result<uuid::Uuid, Error> {
-            Ok(uuid::Uuid::default())
-        }
+        assert_eq!(act, Ok(protocol::PasswordVerification::new(false, 0)));
+        assert_eq!(svc.find(STORED_ID).unwrap().status, "BLOCKED");
     }
 
-    impl TimeService for Mock {
-        fn now(&self) -> chrono::NaiveDateTime {
-            Utc::now().naive_utc()
-        }
+    #[test]
+    fn lifecycle_with_the_memory_repository() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(a_memory_repository()), Box::new(Mock{}));
+        let created = svc.create(protocol::Card{kind: "PLASTIC".to_string(), ..a_card_without_kind()}).unwrap();
+
+        let blocked = svc.block(&created.id, created.version, protocol::StatusChange{reason: "CUSTOMER_REQUEST".to_string()}).unwrap();
+
+        assert_eq!(svc.find(&created.id).unwrap(), blocked);
+        assert_eq!(blocked.status, "BLOCKED");
+        assert_eq!(blocked.version, created.version + 1);
+        assert_eq!(svc.cancel(&created.id, created.version, protocol::StatusChange{reason: "LOST".to_string()}), Err(Error::VersionMismatch));
+        assert_eq!(svc.create(protocol::Card{kind: "PLASTIC".to_string(), ..a_card_without_kind()}), Err(Error::DuplicateId));
     }
 
-    impl PanGenerator for Mock {
-        fn generate(&self, program_id: uuid::Uuid) -> Result<String, Error> {
-            Ok(String::from("4012000033330026"))
+    fn a_card_with_status(id: &str, status: &str, reason: &str) -> protocol::Card {
+        protocol::Card{
+            id: id.to_string(),
+            status: status.to_string(),
+            status_reason: reason.to_string(),
+            status_updated_at: "2021-02-16 19:20:00".to_string(),
+            version: STORED_VERSION + 1,
+            ..a_stored_card()
         }
     }
 
-    impl Repository for Mock {
-        fn save(&self, card: &protocol::Card) -> Option<Error> {
-            None
-        }
+    fn disallowed_error(status: &str, transition: &str) -> Error {
+        Error::Disallowed(protocol::TransitionError::new(String::from(status), String::from(transition)))
     }
 
-    macro_rules! test_invalid_field {
-    ($name:ident, $input:expr, $exp:expr) => {
-        #[test]
-        fn $name() {
-            //FIXME: Add other parameters here
-            let svc = Service::new();
+    #[test]
+    fn expiry_from_mmyy() {
+        assert_eq!(Expiry::from("0724"), Ok(Expiry{year: 2024, month: 7}));
+        assert!(Expiry::from("1324").is_err());
+        assert!(Expiry::from("07245").is_err());
+        assert_eq!(Expiry{year: 2024, month: 7}.to_string(), "0724");
+    }
 
-            let act = svc.create($input).unwrap_err();
+    #[test]
+    fn expiry_plus_months_rolls_over_the_year() {
+        assert_eq!(Expiry{year: 2021, month: 11}.plus_months(3), Expiry{year: 2022, month: 2});
+        assert_eq!(Expiry{year: 2021, month: 2}.plus_months(48), Expiry{year: 2025, month: 2});
+        assert_eq!(Expiry{year: 2021, month: 12}.plus_months(0), Expiry{year: 2021, month: 12});
+    }
 
-            assert_eq!(act, $exp);
+    // Card saved straight into the repository, as issued at `issuing_date`.
+    fn a_card_to_expire(id: &str, pan: &str, kind: &str, status: &str, issuing_date: &str, expiration_date: &str) -> protocol::Card {
+        protocol::Card{
+            id: id.to_string(),
+            pan: pan.to_string(),
+            kind: kind.to_string(),
+            status: status.to_string(),
+            issuing_date: issuing_date.to_string(),
+            expiration_date: expiration_date.to_string(),
+            version: 1,
+            ..a_stored_card()
         }
-    }}
+    }
 
-    test_invalid_field!(test_invalid_customer_id, a_card_without_customer_id(), empty_error("customer_id"));
-    test_invalid_field!(test_invalid_org_id, a_card_without_org_id(), empty_error("org_id"));
-    test_invalid_field!(test_invalid_program_id, a_card_without_program_id(), empty_error("program_id"));
-    test_invalid_field!(test_invalid_printed_name, a_card_without_printed_name(), empty_error("printed_name"));
-    test_invalid_field!(test_invalid_password, a_card_without_password(), invalid_error("password", ""));
-    test_invalid_field!(test_invalid_kind, a_card_without_kind(), empty_error("kind"));
-    test_invalid_field!(test_invalid_cvv, a_card_without_cvv(), invalid_error("cvv", ""));
-    test_invalid_field!(test_invalid_printed_name_invalid_characters_number, a_card_with_invalid_printed_name("R1CARDO"), invalid_error("printed_name", "R1CARDO"));
-    test_invalid_field!(test_invalid_printed_name_invalid_characters_cedilha, a_card_with_invalid_printed_name("RIÇARDO"), invalid_error("printed_name", "RIÇARDO"));
-    test_invalid_field!(test_invalid_printed_name_invalid_characters_special_characters, a_card_with_invalid_printed_name("#RIC*RDO"), invalid_error("printed_name", "#RIC*RDO"));
-    test_invalid_field!(test_invalid_password_with_letters, a_card_with_invalid_password("0912C8"), invalid_error("password", "0912C8"));
-    test_invalid_field!(test_invalid_password_with_more_than_six_characters, a_card_with_invalid_password("091261128"), invalid_error("password", "091261128"));
-    test_invalid_field!(test_invalid_cvv_with_letters, a_card_with_invalid_cvv("0B12"), invalid_error("cvv", "0B12"));
-    test_invalid_field!(test_invalid_cvv_with_more_than_four_characters, a_card_with_invalid_cvv("61112"), invalid_error("cvv", "61112"));
-    test_invalid_field!(test_invalid_empty_expiration_date, a_card_without_expiration_date(), invalid_error("expiration_date", ""));
-    test_invalid_field!(test_invalid_expiration_date_with_letters, a_card_with_invalid_expiration_date("ABCEFG"), invalid_error("expiration_date", "ABCEFG"));
-    test_invalid_field!(test_invalid_expiration_date_with_invalid_month, a_card_with_invalid_expiration_date("1300"), invalid_error("expiration_date", "1300"));
-
-    // TODO: test valid creation
     #[test]
-    fn create() {
-        let pan_generator_mock = Box::new(Mock{});
-        let repository_mock = Box::new(Mock{});
-        let uuid_generator_mock = Box::new(Mock{});
-        let time_service_mock = Box::new(Mock{});
-        let svc = Service::new(uuid_generator_mock, time_service_mock,
-                               pan_generator_mock, repository_mock);
-        let exp = protocol::Card{
-            id: "".to_string(),
-            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
-            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
-            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
-            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
-            printed_name: "RICARDO".to_string(),
-            password: "517412".to_string(),
-            expiration_date: "0724".to_string(),
-            issuing_date: "".to_string(),
-            pan: "".to_string(),
-            kind: "PLASTIC".to_string(),
-            status: "".to_string(),
-            cvv: "451".to_string()
-        };
-        let input = protocol::Card{
-            id: "".to_string(),
-            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
-            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
-            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
-            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
-            printed_name: "RICARDO".to_string(),
-            password: "517412".to_string(),
-            expiration_date: "0724".to_string(),
-            issuing_date: "".to_string(),
-            pan: "".to_string(),
-            kind: "PLASTIC".to_string(),
-            status: "".to_string(),
-            cvv: "451".to_string()
+    fn expire_cards_whose_time_ran_out() {
+        let cards = Arc::new(a_memory_repository());
+        let programs = memory::Repository::new();
+        program::Repository::save(&programs, &a_program()).unwrap();
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(cards.clone()), Box::new(programs));
+        // the clock of the service reads 2021-02-16 19:20, temporary cards live 72 hours
+        let stored = vec![
+            a_card_to_expire("00000000-0000-4000-8000-000000000001", "4012000000000001", "TEMPORARY", "ENABLED", "2021-02-13 19:20:00", "0224"),
+            a_card_to_expire("00000000-0000-4000-8000-000000000002", "4012000000000002", "TEMPORARY", "ENABLED", "2021-02-13 19:20:01", "0224"),
+            a_card_to_expire("00000000-0000-4000-8000-000000000003", "4012000000000003", "PLASTIC", "BLOCKED", "2017-01-16 10:00:00", "0121"),
+            a_card_to_expire("00000000-0000-4000-8000-000000000004", "4012000000000004", "PLASTIC", "ENABLED", "2017-02-16 10:00:00", "0221"),
+            a_card_to_expire("00000000-0000-4000-8000-000000000005", "4012000000000005", "PLASTIC", "CANCELLED", "2017-01-16 10:00:00", "0121"),
+        ];
+        for card in &stored {
+            Repository::save(&cards, card).unwrap();
+        }
+
+        let act = svc.expire();
+
+        assert_eq!(act, Ok(2));
+        let status = |card: &protocol::Card| {
+            let found = svc.find(&card.id).unwrap();
+            (found.status, found.status_reason, found.version)
         };
+        assert_eq!(status(&stored[0]), ("EXPIRED".to_string(), "TIME_TO_LIVE".to_string(), 2));
+        assert_eq!(status(&stored[1]), ("ENABLED".to_string(), "".to_string(), 1));
+        assert_eq!(status(&stored[2]), ("EXPIRED".to_string(), "EXPIRATION_DATE".to_string(), 2));
+        assert_eq!(status(&stored[3]), ("ENABLED".to_string(), "".to_string(), 1));
+        assert_eq!(status(&stored[4]), ("CANCELLED".to_string(), "".to_string(), 1));
+        assert_eq!(svc.expire(), Ok(0));
+    }
 
-        let act = svc.create(input).unwrap_err();
+    #[test]
+    fn expire_unavailable() {
+        let svc = Service::new(Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Mock{}), Box::new(Failing(Error::Unavailable)));
 
-        // FIXME: fix assertion here
-        assert!(true);
+        assert_eq!(svc.expire(), Err(Error::Unavailable));
     }
 
     // TODO: check if it is possible to extract these functions to a macro
@@ -302,17 +1876,24 @@ mod tests {
         protocol::Card{
             id: "".to_string(),
             customer_id: "".to_string(),
-            org_id: "".to_string(),
-            program_id: "".to_string(),
-            account_id: "".to_string(),
-            printed_name: "".to_string(),
-            password: "".to_string(),
-            expiration_date: "".to_string(),
+            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
+            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO MEDEIROS".to_string(),
+            password: "321421".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
-            kind: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -321,16 +1902,23 @@ mod tests {
             id: "".to_string(),
             customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
             org_id: "".to_string(),
-            program_id: "".to_string(),
-            account_id: "".to_string(),
-            printed_name: "".to_string(),
-            password: "".to_string(),
-            expiration_date: "".to_string(),
+            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO MEDEIROS".to_string(),
+            password: "321421".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
-            kind: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -340,33 +1928,22 @@ mod tests {
             customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
             org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
             program_id: "".to_string(),
-            account_id: "".to_string(),
-            printed_name: "".to_string(),
-            password: "".to_string(),
-            expiration_date: "".to_string(),
-            issuing_date: "".to_string(),
-            pan: "".to_string(),
-            kind: "".to_string(),
-            status: "".to_string(),
-            cvv: "".to_string()
-        }
-    }
-
-    fn a_card_without_account_id() -> protocol::Card {
-        protocol::Card{
-            id: "".to_string(),
-            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
-            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
-            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
-            account_id: "".to_string(),
-            printed_name: "".to_string(),
-            password: "".to_string(),
-            expiration_date: "".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO MEDEIROS".to_string(),
+            password: "321421".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
-            kind: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -378,13 +1955,20 @@ mod tests {
             program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "".to_string(),
-            password: "".to_string(),
-            expiration_date: "".to_string(),
+            password: "321421".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
-            kind: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -397,12 +1981,19 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO MEDEIROS".to_string(),
             password: "".to_string(),
-            expiration_date: "".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
-            kind: "".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -415,12 +2006,19 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO MEDEIROS".to_string(),
             password: "321421".to_string(),
+            password_attempts: 0,
             expiration_date: "".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "745".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -433,30 +2031,19 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO MEDEIROS".to_string(),
             password: "321421".to_string(),
+            password_attempts: 0,
             expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "".to_string(),
             status: "".to_string(),
-            cvv: "512".to_string()
-        }
-    }
-
-    fn a_card_without_cvv() -> protocol::Card {
-        protocol::Card{
-            id: "".to_string(),
-            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
-            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
-            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
-            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
-            printed_name: "RICARDO MEDEIROS".to_string(),
-            password: "321421".to_string(),
-            expiration_date: "0724".to_string(),
-            issuing_date: "".to_string(),
-            pan: "".to_string(),
-            kind: "PLASTIC".to_string(),
-            status: "".to_string(),
-            cvv: "".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -469,12 +2056,19 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: invalid_printed_name.to_string(),
             password: "321421".to_string(),
+            password_attempts: 0,
             expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "123".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -487,16 +2081,23 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO".to_string(),
             password: invalid_password.to_string(),
+            password_attempts: 0,
             expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "123".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
-    fn a_card_with_invalid_cvv(invalid_cvv: &str) -> protocol::Card {
+    fn a_card_with_cvv(cvv: &str) -> protocol::Card {
         protocol::Card{
             id: "".to_string(),
             customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
@@ -505,12 +2106,19 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO".to_string(),
             password: "072465".to_string(),
+            password_attempts: 0,
             expiration_date: "0724".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: invalid_cvv.to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: cvv.to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
         }
     }
 
@@ -523,20 +2131,70 @@ mod tests {
             account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
             printed_name: "RICARDO".to_string(),
             password: "517412".to_string(),
+            password_attempts: 0,
             expiration_date: invalid_expiration_date.to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "PLASTIC".to_string(),
             status: "".to_string(),
-            cvv: "451".to_string()
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 0
+        }
+    }
+
+    fn a_stored_card() -> protocol::Card {
+        protocol::Card{
+            id: STORED_ID.to_string(),
+            customer_id: "a3643446-76fc-4516-8e43-bb6600ca118e".to_string(),
+            org_id: "3ee15c70-b7b4-4b87-ba43-38eba70f98c4".to_string(),
+            program_id: "c0a4cc71-5c11-43cb-b74f-2b577012449f".to_string(),
+            account_id: "ba3df3ae-1da8-4b0a-be8c-e9f903d1f7de".to_string(),
+            printed_name: "RICARDO".to_string(),
+            password: "hashed:517412".to_string(),
+            password_attempts: 0,
+            expiration_date: "0724".to_string(),
+            issuing_date: "2021-02-16 19:20:00".to_string(),
+            pan: "4012000033330026".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
+            status: "ENABLED".to_string(),
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
+            cvv: "945".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: STORED_VERSION
+        }
+    }
+
+    fn a_card_with_program(program_id: &str, kind: &str) -> protocol::Card {
+        protocol::Card{program_id: program_id.to_string(), kind: kind.to_string(), ..a_card_without_kind()}
+    }
+
+    fn a_program() -> protocol::Program {
+        protocol::Program{
+            id: PROGRAM_ID.to_string(),
+            name: "GOLD".to_string(),
+            bin: "401200".to_string(),
+            pan_length: 16,
+            kinds: vec!["PLASTIC".to_string(), "RECURRING".to_string(), "TEMPORARY".to_string()],
+            validity_months: 48,
+            temporary_ttl_hours: 72,
+            funding: "CREDIT".to_string(),
+            active: true
         }
     }
 
     fn empty_error(field: &str) -> protocol::ValidationError {
-        protocol::ValidationError::new(String::from(field), String::from(""))
+        protocol::ValidationError::new(String::from(field), String::from(""), protocol::Code::Required)
     }
 
-    fn invalid_error(field: &str, inputted_value: &str) -> protocol::ValidationError {
-        protocol::ValidationError::new(String::from(field), String::from(inputted_value))
+    fn invalid_error(field: &str, inputted_value: &str, code: protocol::Code) -> protocol::ValidationError {
+        protocol::ValidationError::new(String::from(field), String::from(inputted_value), code)
     }
 }
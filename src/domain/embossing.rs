@@ -0,0 +1,151 @@
+// Name embossed on the card and written to track 1, see ISO/IEC 7813: uppercase letters, space
+// and the . - ' punctuation, at most 26 characters.
+
+use crate::protocol::Code;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+const MAX_LENGTH: usize = 26;
+
+// Connectives of Portuguese and Spanish names, the first thing dropped from a long name.
+const PARTICLES: [&str; 7] = ["DA", "DAS", "DE", "DEL", "DO", "DOS", "E"];
+
+// Uppercases and transliterates the name, collapses its whitespace and abbreviates it when it
+// does not fit the card. Fails with PatternMismatch for characters that have no embossable form
+// and with OutOfRange when not even the abbreviated name fits.
+pub(crate) fn normalize(name: &str) -> Result<String, Code> {
+    let mut transliterated = String::with_capacity(name.len());
+    for c in name.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'A'..='Z' | '.' | '-' | '\'' => transliterated.push(c),
+            'a'..='z' => transliterated.push(c.to_ascii_uppercase()),
+            c if c.is_whitespace() => transliterated.push(' '),
+            c => transliterated.push_str(transliterate(c).ok_or(Code::PatternMismatch)?),
+        }
+    }
+
+    let words: Vec<&str> = transliterated.split_whitespace().collect();
+    match words.is_empty() {
+        true => Err(Code::Required),
+        false => abbreviate(words),
+    }
+}
+
+// Letters with no decomposition into a base letter, and typographic forms of the punctuation.
+fn transliterate(c: char) -> Option<&'static str> {
+    match c {
+        'ß' => Some("SS"),
+        'Æ' | 'æ' => Some("AE"),
+        'Œ' | 'œ' => Some("OE"),
+        'Ø' | 'ø' => Some("O"),
+        'Ł' | 'ł' => Some("L"),
+        'Đ' | 'đ' | 'Ð' | 'ð' => Some("D"),
+        'Þ' | 'þ' => Some("TH"),
+        'ı' => Some("I"),
+        '\u{2018}' | '\u{2019}' | '`' => Some("'"),
+        '\u{2010}' | '\u{2011}' | '\u{2013}' | '\u{2014}' => Some("-"),
+        _ => None,
+    }
+}
+
+// Keeps the first and last names whole: drops the particles between them, then shortens the
+// middle names to their initials from the last one back until the name fits.
+fn abbreviate(mut words: Vec<&str>) -> Result<String, Code> {
+    if fits(&words) {
+        return Ok(words.join(" "));
+    }
+
+    let last = words.len() - 1;
+    words = words
+        .iter()
+        .enumerate()
+        .filter(|(i, word)| *i == 0 || *i == last || !PARTICLES.contains(word))
+        .map(|(_, word)| *word)
+        .collect();
+
+    for i in (1..words.len().saturating_sub(1)).rev() {
+        if fits(&words) {
+            break;
+        }
+        words[i] = &words[i][..1];
+    }
+
+    match fits(&words) {
+        true => Ok(words.join(" ")),
+        false => Err(Code::OutOfRange),
+    }
+}
+
+fn fits(words: &[&str]) -> bool {
+    words.iter().map(|word| word.len()).sum::<usize>() + words.len() - 1 <= MAX_LENGTH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_normalize {
+        ($name:ident, $input:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(normalize($input), $exp);
+            }
+        };
+    }
+
+    test_normalize!(
+        test_uppercase,
+        "Baker Mayfield",
+        Ok(String::from("BAKER MAYFIELD"))
+    );
+    test_normalize!(test_cedilla, "RIÇARDO", Ok(String::from("RICARDO")));
+    test_normalize!(test_tilde, "João Simões", Ok(String::from("JOAO SIMOES")));
+    test_normalize!(
+        test_no_decomposition,
+        "Æsa Østergård Łukasz",
+        Ok(String::from("AESA OSTERGARD LUKASZ"))
+    );
+    test_normalize!(test_sharp_s, "Weiß", Ok(String::from("WEISS")));
+    test_normalize!(test_apostrophe, "O'NEIL", Ok(String::from("O'NEIL")));
+    test_normalize!(
+        test_typographic_apostrophe,
+        "O\u{2019}Neil",
+        Ok(String::from("O'NEIL"))
+    );
+    test_normalize!(test_hyphen, "SMITH-JONES", Ok(String::from("SMITH-JONES")));
+    test_normalize!(test_period, "ST. CLAIR", Ok(String::from("ST. CLAIR")));
+    test_normalize!(
+        test_collapse_whitespace,
+        "  RICARDO \t\n MEDEIROS ",
+        Ok(String::from("RICARDO MEDEIROS"))
+    );
+    test_normalize!(test_digits, "R1CARDO", Err(Code::PatternMismatch));
+    test_normalize!(test_symbols, "#RIC*RDO", Err(Code::PatternMismatch));
+    test_normalize!(test_non_latin, "Сергей", Err(Code::PatternMismatch));
+    test_normalize!(test_only_whitespace, " \t ", Err(Code::Required));
+    test_normalize!(
+        test_exactly_max_length,
+        "ABCDEFGHIJKLM NOPQRSTUVWXY",
+        Ok(String::from("ABCDEFGHIJKLM NOPQRSTUVWXY"))
+    );
+    test_normalize!(
+        test_drop_particles,
+        "Ana Carolina dos Santos Lima",
+        Ok(String::from("ANA CAROLINA SANTOS LIMA"))
+    );
+    test_normalize!(
+        test_abbreviate_middle_names_from_the_last,
+        "Maria Aparecida da Conceição Santos Silva",
+        Ok(String::from("MARIA APARECIDA C S SILVA"))
+    );
+    test_normalize!(
+        test_abbreviate_every_middle_name,
+        "Bartholomew Maximilian Alexander Smith",
+        Ok(String::from("BARTHOLOMEW M A SMITH"))
+    );
+    test_normalize!(
+        test_too_long_to_abbreviate,
+        "Bartholomew Wolfeschlegelsteinhausen",
+        Err(Code::OutOfRange)
+    );
+}
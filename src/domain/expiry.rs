@@ -0,0 +1,187 @@
+use crate::domain::card::{self, Error};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Lease the replicas compete for, so one of them sweeps at a time.
+pub(crate) const LEASE: &str = "expiry";
+
+pub trait Lease: Send + Sync {
+    // Takes the lease for `holder` until `until`, or extends it when the holder already has it.
+    // Returns false while another holder has it unexpired at `now`. Times are seconds since the
+    // Unix epoch.
+    fn acquire(&self, name: &str, holder: &str, now: i64, until: i64) -> Result<bool, Error>;
+}
+
+// One backend holds the cards and the leases.
+impl<T: Lease + ?Sized> Lease for Arc<T> {
+    fn acquire(&self, name: &str, holder: &str, now: i64, until: i64) -> Result<bool, Error> {
+        (**self).acquire(name, holder, now, until)
+    }
+}
+
+// Expires the cards that ran out of time every `interval` seconds. Every replica runs one, but
+// only the holder of the lease sweeps: it keeps the lease by renewing it on each tick and another
+// replica takes over once it stops. A sweep that still overlaps another one is harmless, since
+// cards are expired through versioned updates.
+pub(crate) struct Scheduler {
+    expirer: Arc<dyn card::Expirer + Send + Sync>,
+    lease: Box<dyn Lease>,
+    time_service: Box<dyn card::TimeService>,
+    // identifies this replica as the lease holder
+    holder: String,
+    interval: u64,
+}
+
+impl Scheduler {
+    pub(crate) fn new(
+        expirer: Arc<dyn card::Expirer + Send + Sync>,
+        lease: Box<dyn Lease>,
+        time_service: Box<dyn card::TimeService>,
+        holder: String,
+        interval: u64,
+    ) -> Scheduler {
+        Scheduler {
+            expirer,
+            lease,
+            time_service,
+            holder,
+            interval,
+        }
+    }
+
+    // Sweeps once if this replica gets the lease, returning how many cards expired, or None when
+    // another replica holds it.
+    pub(crate) fn run_once(&self) -> Result<Option<usize>, Error> {
+        let now = self.time_service.now().timestamp();
+        // held for two ticks, so the holder renews it before anyone else can take it
+        let until = now + 2 * self.interval as i64;
+
+        match self.lease.acquire(LEASE, &self.holder, now, until)? {
+            true => self.expirer.expire().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    // Ticks on a thread of its own for as long as the process runs. A failed sweep is handed to
+    // `report` and retried on the next tick.
+    pub(crate) fn start(
+        self: Arc<Self>,
+        report: impl Fn(Error) + Send + 'static,
+    ) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            if let Err(err) = self.run_once() {
+                report(err);
+            }
+            thread::sleep(Duration::from_secs(self.interval));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::memory;
+    use chrono::NaiveDate;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Mutex};
+
+    // Counts the sweeps it is asked for.
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl card::Expirer for Counter {
+        fn expire(&self) -> Result<usize, Error> {
+            Ok(self.0.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+    }
+
+    // Clock the test moves forward by hand.
+    struct Clock(Arc<Mutex<chrono::NaiveDateTime>>);
+
+    impl card::TimeService for Clock {
+        fn now(&self) -> chrono::NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    // Lease of a backend that is down.
+    struct Down;
+
+    impl Lease for Down {
+        fn acquire(&self, _: &str, _: &str, _: i64, _: i64) -> Result<bool, Error> {
+            Err(Error::Unavailable)
+        }
+    }
+
+    const INTERVAL: u64 = 60;
+
+    fn a_time() -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd(2021, 2, 16).and_hms(19, 20, 0)
+    }
+
+    fn a_scheduler(
+        holder: &str,
+        expirer: Arc<Counter>,
+        lease: Arc<memory::Repository>,
+        time: Arc<Mutex<chrono::NaiveDateTime>>,
+    ) -> Scheduler {
+        Scheduler::new(
+            expirer,
+            Box::new(lease),
+            Box::new(Clock(time)),
+            String::from(holder),
+            INTERVAL,
+        )
+    }
+
+    #[test]
+    fn only_the_lease_holder_sweeps() {
+        let lease = Arc::new(memory::Repository::new());
+        let time = Arc::new(Mutex::new(a_time()));
+        let expirer = Arc::new(Counter::default());
+        let first = a_scheduler("first", expirer.clone(), lease.clone(), time.clone());
+        let second = a_scheduler("second", expirer.clone(), lease, time.clone());
+
+        assert_eq!(first.run_once(), Ok(Some(1)));
+        assert_eq!(second.run_once(), Ok(None));
+
+        *time.lock().unwrap() += chrono::Duration::seconds(INTERVAL as i64);
+        assert_eq!(first.run_once(), Ok(Some(2)));
+        assert_eq!(second.run_once(), Ok(None));
+    }
+
+    #[test]
+    fn another_replica_takes_over_an_abandoned_lease() {
+        let lease = Arc::new(memory::Repository::new());
+        let time = Arc::new(Mutex::new(a_time()));
+        let expirer = Arc::new(Counter::default());
+        let first = a_scheduler("first", expirer.clone(), lease.clone(), time.clone());
+        let second = a_scheduler("second", expirer, lease, time.clone());
+
+        assert_eq!(first.run_once(), Ok(Some(1)));
+
+        *time.lock().unwrap() += chrono::Duration::seconds(2 * INTERVAL as i64);
+        assert_eq!(second.run_once(), Ok(Some(2)));
+        assert_eq!(first.run_once(), Ok(None));
+    }
+
+    #[test]
+    fn start_reports_a_failed_sweep() {
+        let scheduler = Arc::new(Scheduler::new(
+            Arc::new(Counter::default()),
+            Box::new(Down),
+            Box::new(Clock(Arc::new(Mutex::new(a_time())))),
+            String::from("first"),
+            INTERVAL,
+        ));
+        let (sender, receiver) = mpsc::channel();
+
+        scheduler.start(move |err| sender.send(err).unwrap());
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Error::Unavailable)
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::domain::card::{self, Error};
+use crate::protocol;
+use std::sync::Arc;
+
+const MAX_KEY_LENGTH: usize = 255;
+// Times the key of a created card is written before the failure is returned.
+const MAX_COMPLETE_ATTEMPTS: usize = 3;
+
+// Key of a create request with the fingerprint of its body and the card it created, empty
+// while the request is still running.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Record {
+    pub(crate) key: String,
+    pub(crate) fingerprint: String,
+    pub(crate) card_id: String,
+    // seconds since the Unix epoch, the key is free again from then on
+    pub(crate) expires_at: i64,
+}
+
+pub trait Store: Send + Sync {
+    // Saves the record unless an unexpired one holds its key, which is returned instead.
+    fn claim(&self, record: &Record, now: i64) -> Result<Option<Record>, Error>;
+    // Records the card created under a claimed key, which is then kept until `expires_at`.
+    fn complete(&self, key: &str, card_id: &str, expires_at: i64) -> Result<(), Error>;
+    // Frees the key of a request that failed, so a retry creates the card.
+    fn release(&self, key: &str) -> Result<(), Error>;
+}
+
+// One store holds the cards and the keys, shared by both services.
+impl<T: Store + ?Sized> Store for Arc<T> {
+    fn claim(&self, record: &Record, now: i64) -> Result<Option<Record>, Error> {
+        (**self).claim(record, now)
+    }
+
+    fn complete(&self, key: &str, card_id: &str, expires_at: i64) -> Result<(), Error> {
+        (**self).complete(key, card_id, expires_at)
+    }
+
+    fn release(&self, key: &str) -> Result<(), Error> {
+        (**self).release(key)
+    }
+}
+
+pub trait Fingerprinter: Send + Sync {
+    fn fingerprint(&self, value: &str) -> Result<String, Error>;
+}
+
+pub trait Creator {
+    fn create(&self, key: &str, dto: protocol::Card) -> Result<protocol::Card, Error>;
+}
+
+// Creates a card once per Idempotency-Key, so a client retrying a timed out request gets the
+// card of the first attempt instead of a second card with a new PAN.
+pub(crate) struct Service {
+    creator: Arc<dyn card::Creator + Send + Sync>,
+    finder: Arc<dyn card::Finder + Send + Sync>,
+    store: Box<dyn Store>,
+    fingerprinter: Box<dyn Fingerprinter>,
+    time_service: Box<dyn card::TimeService>,
+    // seconds a key is kept
+    ttl: i64,
+    // seconds the key of a request still running is held
+    lease: i64,
+}
+
+impl Service {
+    pub(crate) fn new(
+        creator: Arc<dyn card::Creator + Send + Sync>,
+        finder: Arc<dyn card::Finder + Send + Sync>,
+        store: Box<dyn Store>,
+        fingerprinter: Box<dyn Fingerprinter>,
+        time_service: Box<dyn card::TimeService>,
+        ttl: i64,
+        lease: i64,
+    ) -> Service {
+        Service {
+            creator,
+            finder,
+            store,
+            fingerprinter,
+            time_service,
+            ttl,
+            lease,
+        }
+    }
+
+    // Keeps the key of the created card for the whole time to live. A failure left after the
+    // attempts is returned, since once the lease runs out a retry would create a second card.
+    fn complete(&self, key: &str, card_id: &str) -> Result<(), Error> {
+        let expires_at = self.time_service.now().timestamp() + self.ttl;
+        let mut result = Ok(());
+        for _ in 0..MAX_COMPLETE_ATTEMPTS {
+            result = self.store.complete(key, card_id, expires_at);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+impl Creator for Service {
+    // A retry with the same body replays the card as it is stored now, one with another body is
+    // rejected, and one arriving while the first attempt still runs is told to wait.
+    fn create(&self, key: &str, dto: protocol::Card) -> Result<protocol::Card, Error> {
+        if !valid_key(key) {
+            return Err(Error::Invalid(vec![protocol::ValidationError::new(
+                String::from("Idempotency-Key"),
+                String::from(key),
+                protocol::Code::PatternMismatch,
+            )]));
+        }
+        // the body holds the clear PIN, so only a keyed hash of it is kept
+        let body = serde_json::to_string(&dto.unmasked()).map_err(|_| Error::Generator)?;
+        let fingerprint = self.fingerprinter.fingerprint(&body)?;
+        let now = self.time_service.now().timestamp();
+        let record = Record {
+            key: String::from(key),
+            fingerprint: fingerprint.clone(),
+            card_id: String::new(),
+            // held only while the request runs, a crashed one frees the key when the lease ends
+            expires_at: now + self.lease,
+        };
+
+        match self.store.claim(&record, now)? {
+            None => match self.creator.create(dto) {
+                Ok(card) => {
+                    self.complete(key, &card.id)?;
+                    Ok(card)
+                }
+                Err(err) => {
+                    let _ = self.store.release(key);
+                    Err(err)
+                }
+            },
+            Some(held) if held.fingerprint != fingerprint => Err(Error::KeyReused),
+            Some(held) if held.card_id.is_empty() => Err(Error::InProgress),
+            Some(held) => self.finder.find(&held.card_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::TimeService;
+    use crate::repository::memory;
+    use chrono::NaiveDate;
+    use mockall::mock;
+    use mockall::predicate::eq;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    mock! {
+        Creator {}
+        impl card::Creator for Creator {
+            fn create(&self, dto: protocol::Card) -> Result<protocol::Card, Error>;
+        }
+    }
+
+    mock! {
+        Finder {}
+        impl card::Finder for Finder {
+            fn find(&self, id: &str) -> Result<protocol::Card, Error>;
+            fn list(&self, filter: protocol::Filter) -> Result<protocol::Page, Error>;
+        }
+    }
+
+    struct Mock {}
+
+    impl Fingerprinter for Mock {
+        fn fingerprint(&self, value: &str) -> Result<String, Error> {
+            Ok(format!("hashed:{}", value))
+        }
+    }
+
+    impl card::TimeService for Mock {
+        fn now(&self) -> chrono::NaiveDateTime {
+            NaiveDate::from_ymd(2021, 2, 16).and_hms(19, 20, 0)
+        }
+    }
+
+    const KEY: &str = "8e0b1c3a-create-card";
+    const TTL: i64 = 3600;
+    const LEASE: i64 = 60;
+
+    // Keys in memory, where completing one always fails, counting the attempts.
+    struct Incomplete {
+        keys: memory::Repository,
+        attempts: AtomicUsize,
+    }
+
+    impl Store for Incomplete {
+        fn claim(&self, record: &Record, now: i64) -> Result<Option<Record>, Error> {
+            self.keys.claim(record, now)
+        }
+
+        fn complete(&self, _key: &str, _card_id: &str, _expires_at: i64) -> Result<(), Error> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Unavailable)
+        }
+
+        fn release(&self, key: &str) -> Result<(), Error> {
+            self.keys.release(key)
+        }
+    }
+
+    fn a_service(creator: MockCreator, finder: MockFinder, store: impl Store + 'static) -> Service {
+        Service::new(
+            Arc::new(creator),
+            Arc::new(finder),
+            Box::new(store),
+            Box::new(Mock {}),
+            Box::new(Mock {}),
+            TTL,
+            LEASE,
+        )
+    }
+
+    fn held(store: &dyn Store) -> Record {
+        store
+            .claim(
+                &Record {
+                    key: String::from(KEY),
+                    ..Default::default()
+                },
+                0,
+            )
+            .unwrap()
+            .unwrap()
+    }
+
+    fn a_card(printed_name: &str) -> protocol::Card {
+        protocol::Card {
+            printed_name: String::from(printed_name),
+            kind: String::from("PLASTIC"),
+            ..Default::default()
+        }
+    }
+
+    fn a_created_card() -> protocol::Card {
+        protocol::Card {
+            id: String::from("5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10"),
+            version: 1,
+            ..a_card("RICARDO")
+        }
+    }
+
+    #[test]
+    fn create_once() {
+        let store = Arc::new(memory::Repository::new());
+        let mut creator = MockCreator::new();
+        creator
+            .expect_create()
+            .with(eq(a_card("RICARDO")))
+            .times(1)
+            .return_const(Ok(a_created_card()));
+        let mut finder = MockFinder::new();
+        finder
+            .expect_find()
+            .with(eq(a_created_card().id))
+            .times(1)
+            .return_const(Ok(a_created_card()));
+        let svc = a_service(creator, finder, store.clone());
+
+        let first = svc.create(KEY, a_card("RICARDO"));
+        let retry = svc.create(KEY, a_card("RICARDO"));
+
+        assert_eq!(first, Ok(a_created_card()));
+        assert_eq!(retry, Ok(a_created_card()));
+        let held = held(&store);
+        assert_eq!(held.card_id, a_created_card().id);
+        assert_eq!(held.expires_at, Mock {}.now().timestamp() + TTL);
+    }
+
+    #[test]
+    fn create_holds_the_key_for_the_lease_while_running() {
+        let store = Arc::new(memory::Repository::new());
+        let running = store.clone();
+        let mut creator = MockCreator::new();
+        creator.expect_create().times(1).returning(move |_| {
+            let held = held(&running);
+            assert_eq!(held.card_id, "");
+            assert_eq!(held.expires_at, Mock {}.now().timestamp() + LEASE);
+            Ok(a_created_card())
+        });
+        let svc = a_service(creator, MockFinder::new(), store);
+
+        assert_eq!(svc.create(KEY, a_card("RICARDO")), Ok(a_created_card()));
+    }
+
+    #[test]
+    fn create_returns_a_key_left_unfinished() {
+        let store = Arc::new(Incomplete {
+            keys: memory::Repository::new(),
+            attempts: AtomicUsize::new(0),
+        });
+        let mut creator = MockCreator::new();
+        creator
+            .expect_create()
+            .times(1)
+            .return_const(Ok(a_created_card()));
+        let svc = a_service(creator, MockFinder::new(), store.clone());
+
+        let act = svc.create(KEY, a_card("RICARDO"));
+
+        assert_eq!(act, Err(Error::Unavailable));
+        assert_eq!(store.attempts.load(Ordering::SeqCst), MAX_COMPLETE_ATTEMPTS);
+    }
+
+    #[test]
+    fn create_with_another_body() {
+        let mut creator = MockCreator::new();
+        creator
+            .expect_create()
+            .times(1)
+            .return_const(Ok(a_created_card()));
+        let svc = a_service(
+            creator,
+            MockFinder::new(),
+            Arc::new(memory::Repository::new()),
+        );
+        svc.create(KEY, a_card("RICARDO")).unwrap();
+
+        let act = svc.create(KEY, a_card("RICARDO MEDEIROS"));
+
+        assert_eq!(act, Err(Error::KeyReused));
+    }
+
+    #[test]
+    fn create_while_in_progress() {
+        let store = Arc::new(memory::Repository::new());
+        let body = serde_json::to_string(&a_card("RICARDO").unmasked()).unwrap();
+        store
+            .claim(
+                &Record {
+                    key: String::from(KEY),
+                    fingerprint: Mock {}.fingerprint(&body).unwrap(),
+                    card_id: String::new(),
+                    expires_at: i64::MAX,
+                },
+                0,
+            )
+            .unwrap();
+        let svc = a_service(MockCreator::new(), MockFinder::new(), store);
+
+        let act = svc.create(KEY, a_card("RICARDO"));
+
+        assert_eq!(act, Err(Error::InProgress));
+    }
+
+    #[test]
+    fn create_failure_releases_the_key() {
+        let mut creator = MockCreator::new();
+        let mut results = vec![Ok(a_created_card()), Err(Error::Unavailable)];
+        creator
+            .expect_create()
+            .times(2)
+            .returning(move |_| results.pop().unwrap());
+        let svc = a_service(
+            creator,
+            MockFinder::new(),
+            Arc::new(memory::Repository::new()),
+        );
+
+        assert_eq!(svc.create(KEY, a_card("RICARDO")), Err(Error::Unavailable));
+        assert_eq!(svc.create(KEY, a_card("RICARDO")), Ok(a_created_card()));
+    }
+
+    #[test]
+    fn create_with_invalid_key() {
+        let svc = a_service(
+            MockCreator::new(),
+            MockFinder::new(),
+            Arc::new(memory::Repository::new()),
+        );
+
+        for key in &["", "key with spaces", &"k".repeat(MAX_KEY_LENGTH + 1)] {
+            let act = svc.create(key, a_card("RICARDO"));
+
+            assert_eq!(
+                act,
+                Err(Error::Invalid(vec![protocol::ValidationError::new(
+                    String::from("Idempotency-Key"),
+                    key.to_string(),
+                    protocol::Code::PatternMismatch,
+                )]))
+            );
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Luhn (mod 10) checksum used by card numbers, see ISO/IEC 7812-1.
+
+pub(crate) fn check_digit(payload: &str) -> Option<u32> {
+    let sum = checksum(payload, true)?;
+
+    Some((10 - sum % 10) % 10)
+}
+
+pub(crate) fn is_valid(number: &str) -> bool {
+    match number.len() > 1 {
+        true => matches!(checksum(number, false), Some(sum) if sum % 10 == 0),
+        false => false,
+    }
+}
+
+// Doubles every second digit from the right, starting with the rightmost one when it is going to
+// be followed by a check digit.
+fn checksum(digits: &str, double_rightmost: bool) -> Option<u32> {
+    digits
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let digit = c.to_digit(10)?;
+            match (i % 2 == 0) == double_rightmost {
+                true if digit * 2 > 9 => Some(digit * 2 - 9),
+                true => Some(digit * 2),
+                false => Some(digit),
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_is_valid {
+        ($name:ident, $input:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(is_valid($input), $exp);
+            }
+        };
+    }
+
+    test_is_valid!(test_valid_visa, "4012000033330026", true);
+    test_is_valid!(test_valid_mastercard, "5555555555554444", true);
+    test_is_valid!(test_valid_discover, "6011000990139424", true);
+    test_is_valid!(test_valid_19_digits, "6212345678901234569", true);
+    test_is_valid!(test_valid_amex, "378282246310005", true);
+    test_is_valid!(test_invalid_check_digit, "4012000033330027", false);
+    test_is_valid!(test_invalid_letters, "40120000333300A6", false);
+    test_is_valid!(test_invalid_empty, "", false);
+    test_is_valid!(test_invalid_single_digit, "0", false);
+
+    #[test]
+    fn check_digit_completes_number() {
+        assert_eq!(check_digit("401200003333002"), Some(6));
+        assert_eq!(check_digit("555555555555444"), Some(4));
+        assert_eq!(check_digit("7992739871"), Some(3));
+    }
+
+    #[test]
+    fn check_digit_rejects_non_digits() {
+        assert_eq!(check_digit("4012X"), None);
+    }
+}
@@ -1 +1,6 @@
 pub(crate) mod card;
+pub(crate) mod embossing;
+pub(crate) mod expiry;
+pub(crate) mod idempotency;
+pub(crate) mod luhn;
+pub(crate) mod program;
@@ -0,0 +1,512 @@
+use crate::domain::card::{self, invalid, Error, Kind};
+use crate::protocol;
+use regex::Regex;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const BIN_PATTERN: &str = r"^(\d{6}|\d{8})$";
+const PAN_LENGTHS: [usize; 2] = [16, 19];
+const MAX_VALIDITY_MONTHS: u32 = 120;
+const MAX_TEMPORARY_TTL_HOURS: u32 = 90 * 24;
+
+enum Funding {
+    Credit,
+    Debit,
+    Prepaid,
+}
+
+impl Funding {
+    fn from(description: &str) -> Result<Funding, String> {
+        match description.to_uppercase().as_str() {
+            "CREDIT" => Ok(Funding::Credit),
+            "DEBIT" => Ok(Funding::Debit),
+            "PREPAID" => Ok(Funding::Prepaid),
+            _ => Err(format!("Unknown funding {}", description)),
+        }
+    }
+
+    fn to_string(&self) -> Result<String, String> {
+        match self {
+            Funding::Credit => Ok("CREDIT".to_string()),
+            Funding::Debit => Ok("DEBIT".to_string()),
+            Funding::Prepaid => Ok("PREPAID".to_string()),
+        }
+    }
+}
+
+pub trait Repository: Send + Sync {
+    fn save(&self, program: &protocol::Program) -> Result<(), Error>;
+    fn find_by_id(&self, id: Uuid) -> Result<Option<protocol::Program>, Error>;
+    // Every program, ordered by id.
+    fn list(&self) -> Result<Vec<protocol::Program>, Error>;
+    // Replaces the stored program, failing with NotFound when there is none.
+    fn update(&self, program: &protocol::Program) -> Result<(), Error>;
+    // Removes the stored program, failing with NotFound when there is none.
+    fn delete(&self, id: Uuid) -> Result<(), Error>;
+}
+
+// One repository is shared by the program service and the card service, which issues from it.
+impl<T: Repository + ?Sized> Repository for Arc<T> {
+    fn save(&self, program: &protocol::Program) -> Result<(), Error> {
+        (**self).save(program)
+    }
+
+    fn find_by_id(&self, id: Uuid) -> Result<Option<protocol::Program>, Error> {
+        (**self).find_by_id(id)
+    }
+
+    fn list(&self) -> Result<Vec<protocol::Program>, Error> {
+        (**self).list()
+    }
+
+    fn update(&self, program: &protocol::Program) -> Result<(), Error> {
+        (**self).update(program)
+    }
+
+    fn delete(&self, id: Uuid) -> Result<(), Error> {
+        (**self).delete(id)
+    }
+}
+
+pub(crate) struct Service {
+    uuid_generator: Box<dyn card::UuidGenerator>,
+    repository: Box<dyn Repository>,
+}
+
+impl Service {
+    pub(crate) fn new(
+        uuid_generator: Box<dyn card::UuidGenerator>,
+        repository: Box<dyn Repository>,
+    ) -> Service {
+        Service {
+            uuid_generator,
+            repository,
+        }
+    }
+
+    // Checks every field, giving back the kinds and funding by their upper case names.
+    fn validate(&self, program: protocol::Program) -> Result<protocol::Program, Error> {
+        let mut errors = Vec::new();
+
+        if program.name.trim().is_empty() {
+            errors.push(invalid("name", "", protocol::Code::Required));
+        }
+        match (
+            program.bin.is_empty(),
+            Regex::new(BIN_PATTERN).unwrap().is_match(&program.bin),
+        ) {
+            (false, true) => (),
+            (true, _) => errors.push(invalid("bin", "", protocol::Code::Required)),
+            (false, false) => errors.push(invalid(
+                "bin",
+                &program.bin,
+                protocol::Code::PatternMismatch,
+            )),
+        };
+        match program.pan_length {
+            0 => errors.push(invalid("pan_length", "", protocol::Code::Required)),
+            length if !PAN_LENGTHS.contains(&length) => errors.push(invalid(
+                "pan_length",
+                &length.to_string(),
+                protocol::Code::OutOfRange,
+            )),
+            _ => (),
+        };
+
+        let mut kinds: Vec<String> = Vec::new();
+        if program.kinds.is_empty() {
+            errors.push(invalid("kinds", "", protocol::Code::Required));
+        }
+        for kind in &program.kinds {
+            match Kind::from(kind) {
+                Ok(kind) => {
+                    let kind = kind.to_string().unwrap();
+                    if !kinds.contains(&kind) {
+                        kinds.push(kind);
+                    }
+                }
+                Err(_) => errors.push(invalid("kinds", kind, protocol::Code::UnknownEnum)),
+            }
+        }
+
+        match program.validity_months {
+            0 => errors.push(invalid("validity_months", "", protocol::Code::Required)),
+            months if months > MAX_VALIDITY_MONTHS => errors.push(invalid(
+                "validity_months",
+                &months.to_string(),
+                protocol::Code::OutOfRange,
+            )),
+            _ => (),
+        };
+
+        // a time to live only makes sense, and is then needed, for temporary cards; it is only
+        // checked against kinds that are themselves valid
+        let kinds_valid =
+            !program.kinds.is_empty() && program.kinds.iter().all(|kind| Kind::from(kind).is_ok());
+        let temporary = Kind::Temporary.to_string().unwrap();
+        match (kinds.contains(&temporary), program.temporary_ttl_hours) {
+            _ if !kinds_valid => (),
+            (true, 0) => errors.push(invalid("temporary_ttl_hours", "", protocol::Code::Required)),
+            (true, hours) if hours > MAX_TEMPORARY_TTL_HOURS => errors.push(invalid(
+                "temporary_ttl_hours",
+                &hours.to_string(),
+                protocol::Code::OutOfRange,
+            )),
+            (false, hours) if hours > 0 => errors.push(invalid(
+                "temporary_ttl_hours",
+                &hours.to_string(),
+                protocol::Code::NotAllowed,
+            )),
+            _ => (),
+        };
+
+        let funding = match (program.funding.is_empty(), Funding::from(&program.funding)) {
+            (_, Ok(funding)) => funding.to_string().unwrap(),
+            (true, Err(_)) => {
+                errors.push(invalid("funding", "", protocol::Code::Required));
+                program.funding.clone()
+            }
+            (false, Err(_)) => {
+                errors.push(invalid(
+                    "funding",
+                    &program.funding,
+                    protocol::Code::UnknownEnum,
+                ));
+                program.funding.clone()
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(Error::Invalid(errors));
+        }
+
+        Ok(protocol::Program {
+            kinds,
+            funding,
+            ..program
+        })
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(id)
+        .map_err(|_| Error::Invalid(vec![invalid("id", id, protocol::Code::UuidFormat)]))
+}
+
+pub trait Creator {
+    fn create(&self, dto: protocol::Program) -> Result<protocol::Program, Error>;
+}
+
+impl Creator for Service {
+    fn create(&self, input: protocol::Program) -> Result<protocol::Program, Error> {
+        let output = protocol::Program {
+            id: self.uuid_generator.generate()?.to_string(),
+            ..self.validate(input)?
+        };
+        self.repository.save(&output)?;
+
+        Ok(output)
+    }
+}
+
+pub trait Finder {
+    fn find(&self, id: &str) -> Result<protocol::Program, Error>;
+    fn list(&self) -> Result<Vec<protocol::Program>, Error>;
+}
+
+impl Finder for Service {
+    fn find(&self, id: &str) -> Result<protocol::Program, Error> {
+        self.repository
+            .find_by_id(parse_id(id)?)?
+            .ok_or(Error::NotFound)
+    }
+
+    fn list(&self) -> Result<Vec<protocol::Program>, Error> {
+        self.repository.list()
+    }
+}
+
+pub trait Updater {
+    fn update(&self, id: &str, program: protocol::Program) -> Result<protocol::Program, Error>;
+}
+
+impl Updater for Service {
+    // Replaces every field but the id, so a program is switched off by sending it inactive.
+    fn update(&self, id: &str, input: protocol::Program) -> Result<protocol::Program, Error> {
+        let id = parse_id(id)?.to_string();
+        if !input.id.is_empty() && input.id != id {
+            return Err(Error::Invalid(vec![invalid(
+                "id",
+                &input.id,
+                protocol::Code::Immutable,
+            )]));
+        }
+        let output = protocol::Program {
+            id,
+            ..self.validate(input)?
+        };
+        self.repository.update(&output)?;
+
+        Ok(output)
+    }
+}
+
+pub trait Remover {
+    fn delete(&self, id: &str) -> Result<(), Error>;
+}
+
+impl Remover for Service {
+    // Cards already issued keep their program id, only new cards need the program.
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        self.repository.delete(parse_id(id)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::memory;
+
+    struct Mock {}
+
+    impl card::UuidGenerator for Mock {
+        fn generate(&self) -> Result<Uuid, Error> {
+            Ok(Uuid::parse_str(PROGRAM_ID).unwrap())
+        }
+    }
+
+    const PROGRAM_ID: &str = "35d86d4b-55e6-49c6-bc83-0b52d68b75f5";
+
+    fn a_service() -> Service {
+        Service::new(Box::new(Mock {}), Box::new(memory::Repository::new()))
+    }
+
+    fn a_program() -> protocol::Program {
+        protocol::Program {
+            name: String::from("Gold"),
+            bin: String::from("41234567"),
+            pan_length: 16,
+            kinds: vec![String::from("plastic"), String::from("Temporary")],
+            validity_months: 60,
+            temporary_ttl_hours: 72,
+            funding: String::from("credit"),
+            ..Default::default()
+        }
+    }
+
+    fn a_stored_program() -> protocol::Program {
+        protocol::Program {
+            id: String::from(PROGRAM_ID),
+            kinds: vec![String::from("PLASTIC"), String::from("TEMPORARY")],
+            funding: String::from("CREDIT"),
+            ..a_program()
+        }
+    }
+
+    macro_rules! test_invalid_field {
+        ($name:ident, $input:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                let act = a_service().create($input).unwrap_err();
+
+                assert_eq!(act, Error::Invalid(vec![$exp]));
+            }
+        };
+    }
+
+    test_invalid_field!(
+        test_invalid_name,
+        protocol::Program {
+            name: String::from(" "),
+            ..a_program()
+        },
+        invalid("name", "", protocol::Code::Required)
+    );
+    test_invalid_field!(
+        test_invalid_bin,
+        protocol::Program {
+            bin: String::from("4123"),
+            ..a_program()
+        },
+        invalid("bin", "4123", protocol::Code::PatternMismatch)
+    );
+    test_invalid_field!(
+        test_invalid_pan_length,
+        protocol::Program {
+            pan_length: 15,
+            ..a_program()
+        },
+        invalid("pan_length", "15", protocol::Code::OutOfRange)
+    );
+    test_invalid_field!(
+        test_invalid_kinds_empty,
+        protocol::Program {
+            kinds: vec![],
+            ..a_program()
+        },
+        invalid("kinds", "", protocol::Code::Required)
+    );
+    test_invalid_field!(
+        test_invalid_kinds_unknown,
+        protocol::Program {
+            kinds: vec![String::from("PLASTIC"), String::from("GOLD")],
+            ..a_program()
+        },
+        invalid("kinds", "GOLD", protocol::Code::UnknownEnum)
+    );
+    test_invalid_field!(
+        test_invalid_validity_months,
+        protocol::Program {
+            validity_months: 121,
+            ..a_program()
+        },
+        invalid("validity_months", "121", protocol::Code::OutOfRange)
+    );
+    test_invalid_field!(
+        test_invalid_temporary_ttl_hours_missing,
+        protocol::Program {
+            temporary_ttl_hours: 0,
+            ..a_program()
+        },
+        invalid("temporary_ttl_hours", "", protocol::Code::Required)
+    );
+    test_invalid_field!(
+        test_invalid_temporary_ttl_hours_too_long,
+        protocol::Program {
+            temporary_ttl_hours: 2161,
+            ..a_program()
+        },
+        invalid("temporary_ttl_hours", "2161", protocol::Code::OutOfRange)
+    );
+    test_invalid_field!(
+        test_invalid_temporary_ttl_hours_without_temporary_kind,
+        protocol::Program {
+            kinds: vec![String::from("PLASTIC")],
+            ..a_program()
+        },
+        invalid("temporary_ttl_hours", "72", protocol::Code::NotAllowed)
+    );
+    test_invalid_field!(
+        test_invalid_funding,
+        protocol::Program {
+            funding: String::from("LOAN"),
+            ..a_program()
+        },
+        invalid("funding", "LOAN", protocol::Code::UnknownEnum)
+    );
+
+    #[test]
+    fn create_collects_every_invalid_field() {
+        let act = a_service().create(protocol::Program::default());
+
+        assert_eq!(
+            act,
+            Err(Error::Invalid(vec![
+                invalid("name", "", protocol::Code::Required),
+                invalid("bin", "", protocol::Code::Required),
+                invalid("pan_length", "", protocol::Code::Required),
+                invalid("kinds", "", protocol::Code::Required),
+                invalid("validity_months", "", protocol::Code::Required),
+                invalid("funding", "", protocol::Code::Required),
+            ]))
+        );
+    }
+
+    #[test]
+    fn create_and_find() {
+        let svc = a_service();
+
+        let created = svc.create(a_program());
+
+        assert_eq!(created, Ok(a_stored_program()));
+        assert_eq!(svc.find(PROGRAM_ID), Ok(a_stored_program()));
+        assert_eq!(svc.list(), Ok(vec![a_stored_program()]));
+    }
+
+    #[test]
+    fn create_deduplicates_kinds() {
+        let act = a_service().create(protocol::Program {
+            kinds: vec![String::from("plastic"), String::from("PLASTIC")],
+            temporary_ttl_hours: 0,
+            ..a_program()
+        });
+
+        assert_eq!(act.unwrap().kinds, vec![String::from("PLASTIC")]);
+    }
+
+    #[test]
+    fn find_not_found() {
+        assert_eq!(a_service().find(PROGRAM_ID), Err(Error::NotFound));
+    }
+
+    #[test]
+    fn find_invalid_id() {
+        assert_eq!(
+            a_service().find("gold"),
+            Err(Error::Invalid(vec![invalid(
+                "id",
+                "gold",
+                protocol::Code::UuidFormat
+            )]))
+        );
+    }
+
+    #[test]
+    fn update_deactivates() {
+        let svc = a_service();
+        svc.create(a_program()).unwrap();
+        let exp = protocol::Program {
+            active: false,
+            ..a_stored_program()
+        };
+
+        let act = svc.update(
+            PROGRAM_ID,
+            protocol::Program {
+                active: false,
+                ..a_program()
+            },
+        );
+
+        assert_eq!(act, Ok(exp.clone()));
+        assert_eq!(svc.find(PROGRAM_ID), Ok(exp));
+    }
+
+    #[test]
+    fn update_another_id() {
+        let act = a_service().update(
+            PROGRAM_ID,
+            protocol::Program {
+                id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+                ..a_program()
+            },
+        );
+
+        assert_eq!(
+            act,
+            Err(Error::Invalid(vec![invalid(
+                "id",
+                "00c9e86a-8d55-4a95-884b-4a6faeb9289e",
+                protocol::Code::Immutable
+            )]))
+        );
+    }
+
+    #[test]
+    fn update_not_found() {
+        assert_eq!(
+            a_service().update(PROGRAM_ID, a_program()),
+            Err(Error::NotFound)
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let svc = a_service();
+        svc.create(a_program()).unwrap();
+
+        assert_eq!(svc.delete(PROGRAM_ID), Ok(()));
+        assert_eq!(svc.find(PROGRAM_ID), Err(Error::NotFound));
+        assert_eq!(svc.delete(PROGRAM_ID), Err(Error::NotFound));
+    }
+}
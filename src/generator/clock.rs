@@ -0,0 +1,10 @@
+use crate::domain::card;
+
+// Current time in UTC, the zone every date of a card is kept in.
+pub(crate) struct Utc;
+
+impl card::TimeService for Utc {
+    fn now(&self) -> chrono::NaiveDateTime {
+        chrono::Utc::now().naive_utc()
+    }
+}
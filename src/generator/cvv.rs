@@ -0,0 +1,169 @@
+use crate::domain::card;
+use des::cipher::generic_array::GenericArray;
+use des::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use des::Des;
+use std::fmt::Error;
+
+// Service code used for the value printed on the card (CVV2/CVC2).
+pub static PRINTED_SERVICE_CODE: &str = "000";
+
+// Card verification value as specified by Visa/Mastercard: PAN, expiry (YYMM) and service code
+// are encrypted under a double-length card verification key and decimalized.
+pub(crate) struct Generator {
+    key_a: Des,
+    key_b: Des,
+    service_code: String,
+}
+
+impl Generator {
+    pub(crate) fn new(cvk: &str, service_code: &str) -> Result<Generator, Error> {
+        let cvk = hex::decode(cvk).map_err(|_| Error)?;
+        let valid_service_code =
+            service_code.len() == 3 && service_code.chars().all(|c| c.is_ascii_digit());
+        if cvk.len() != 16 || !valid_service_code {
+            return Err(Error);
+        }
+
+        Ok(Generator {
+            key_a: Des::new_from_slice(&cvk[..8]).map_err(|_| Error)?,
+            key_b: Des::new_from_slice(&cvk[8..]).map_err(|_| Error)?,
+            service_code: String::from(service_code),
+        })
+    }
+
+    // American Express prints a four digit CID, every other brand a three digit code.
+    fn length(pan: &str) -> usize {
+        match pan.starts_with("34") || pan.starts_with("37") {
+            true => 4,
+            false => 3,
+        }
+    }
+}
+
+impl card::CvvGenerator for Generator {
+    fn generate(&self, pan: &str, expiration_date: &str) -> Result<String, card::Error> {
+        let digits = |value: &str| value.chars().all(|c| c.is_ascii_digit());
+        if !digits(pan) || pan.len() > 19 || !digits(expiration_date) || expiration_date.len() != 4
+        {
+            return Err(card::Error::Generator);
+        }
+
+        // expiration_date is MMYY, the algorithm takes YYMM
+        let data = format!(
+            "{:0<32}",
+            format!(
+                "{}{}{}{}",
+                pan,
+                &expiration_date[2..],
+                &expiration_date[..2],
+                self.service_code
+            )
+        );
+        let data = hex::decode(data).map_err(|_| card::Error::Generator)?;
+
+        let mut block = GenericArray::clone_from_slice(&data[..8]);
+        self.key_a.encrypt_block(&mut block);
+        block
+            .iter_mut()
+            .zip(&data[8..])
+            .for_each(|(byte, other)| *byte ^= other);
+        self.key_a.encrypt_block(&mut block);
+        self.key_b.decrypt_block(&mut block);
+        self.key_a.encrypt_block(&mut block);
+
+        let result = hex::encode_upper(block);
+        let decimals = result.chars().filter(|c| c.is_ascii_digit());
+        let letters = result
+            .chars()
+            .filter_map(|c| c.to_digit(16).filter(|d| *d > 9))
+            .map(|d| std::char::from_digit(d - 10, 10).unwrap());
+
+        Ok(decimals
+            .chain(letters)
+            .take(Generator::length(pan))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::CvvGenerator;
+
+    const CVK: &str = "0123456789ABCDEFFEDCBA9876543210";
+
+    macro_rules! test_generate {
+        ($name:ident, $pan:expr, $expiration_date:expr, $service_code:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                let generator = Generator::new(CVK, $service_code).unwrap();
+
+                let act = generator.generate($pan, $expiration_date);
+
+                assert_eq!(act, $exp);
+            }
+        };
+    }
+
+    test_generate!(
+        test_reference_vector,
+        "4123456789012345",
+        "0187",
+        "101",
+        Ok(String::from("561"))
+    );
+    test_generate!(
+        test_printed,
+        "4123456789012345",
+        "0187",
+        PRINTED_SERVICE_CODE,
+        Ok(String::from("636"))
+    );
+    test_generate!(
+        test_mastercard,
+        "5214330278318136",
+        "0724",
+        PRINTED_SERVICE_CODE,
+        Ok(String::from("855"))
+    );
+    test_generate!(
+        test_19_digits,
+        "6212345678901234569",
+        "0724",
+        PRINTED_SERVICE_CODE,
+        Ok(String::from("209"))
+    );
+    test_generate!(
+        test_amex,
+        "378282246310005",
+        "0724",
+        PRINTED_SERVICE_CODE,
+        Ok(String::from("8639"))
+    );
+    test_generate!(
+        test_invalid_pan,
+        "4123X56789012345",
+        "0187",
+        PRINTED_SERVICE_CODE,
+        Err(card::Error::Generator)
+    );
+    test_generate!(
+        test_invalid_expiration_date,
+        "4123456789012345",
+        "187",
+        PRINTED_SERVICE_CODE,
+        Err(card::Error::Generator)
+    );
+
+    #[test]
+    fn new_invalid_key() {
+        assert!(Generator::new("0123456789ABCDEF", PRINTED_SERVICE_CODE).is_err());
+        assert!(Generator::new("0123456789ABCDEFFEDCBA987654321G", PRINTED_SERVICE_CODE).is_err());
+    }
+
+    #[test]
+    fn new_invalid_service_code() {
+        assert!(Generator::new(CVK, "10").is_err());
+        assert!(Generator::new(CVK, "1A1").is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub(crate) mod clock;
+pub(crate) mod cvv;
+pub(crate) mod pan;
+pub(crate) mod uuid;
@@ -0,0 +1,184 @@
+use crate::domain::{card, luhn};
+use crate::protocol;
+use rand::Rng;
+use std::fmt;
+
+const MAX_ATTEMPTS: usize = 10;
+
+// Issuer identification number and PAN length assigned to a program.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Bin {
+    prefix: String,
+    length: usize,
+}
+
+impl Bin {
+    pub(crate) fn new(prefix: &str, length: usize) -> Result<Bin, fmt::Error> {
+        let digits = prefix.chars().all(|c| c.is_ascii_digit());
+
+        match (digits, prefix.len(), length) {
+            (true, 6, 16) | (true, 6, 19) | (true, 8, 16) | (true, 8, 19) => Ok(Bin {
+                prefix: String::from(prefix),
+                length,
+            }),
+            _ => Err(fmt::Error),
+        }
+    }
+}
+
+pub(crate) struct Generator {
+    repository: Box<dyn card::Repository>,
+}
+
+impl Generator {
+    pub(crate) fn new(repository: Box<dyn card::Repository>) -> Generator {
+        Generator { repository }
+    }
+
+    fn candidate(bin: &Bin) -> String {
+        let mut rng = rand::thread_rng();
+        let mut pan = bin.prefix.clone();
+        while pan.len() < bin.length - 1 {
+            pan.push(std::char::from_digit(rng.gen_range(0..10), 10).unwrap());
+        }
+        let check_digit = luhn::check_digit(&pan).unwrap();
+        pan.push(std::char::from_digit(check_digit, 10).unwrap());
+        debug_assert!(luhn::is_valid(&pan));
+
+        pan
+    }
+}
+
+impl card::PanGenerator for Generator {
+    fn generate(&self, program: &protocol::Program) -> Result<String, card::Error> {
+        let bin = Bin::new(&program.bin, program.pan_length).map_err(|_| card::Error::Generator)?;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let pan = Generator::candidate(&bin);
+            if !self.repository.exists_pan(&pan)? {
+                return Ok(pan);
+            }
+        }
+
+        Err(card::Error::DuplicatePan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::PanGenerator;
+    use mockall::mock;
+    use mockall::predicate::always;
+
+    mock! {
+            Repository {}
+            impl card::Repository for Repository {
+               fn save(&self, card: &protocol::Card) -> Result<(), card::Error>;
+               fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, card::Error>;
+               fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error>;
+               fn update(&self, card: &protocol::Card) -> Result<(), card::Error>;
+               fn exists_pan(&self, pan: &str) -> Result<bool, card::Error>;
+            }
+    }
+
+    macro_rules! test_bin {
+        ($name:ident, $prefix:expr, $length:expr, $valid:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(Bin::new($prefix, $length).is_ok(), $valid);
+            }
+        };
+    }
+
+    test_bin!(test_bin_six_digits, "521433", 16, true);
+    test_bin!(test_bin_eight_digits, "52143302", 19, true);
+    test_bin!(test_bin_invalid_length, "521433", 15, false);
+    test_bin!(test_bin_short_prefix, "5214", 16, false);
+    test_bin!(test_bin_letters, "5214AB", 16, false);
+
+    #[test]
+    fn generate_16_digits() {
+        let generator = a_generator(vec![false]);
+
+        let act = generator.generate(&a_program("521433", 16)).unwrap();
+
+        assert_eq!(act.len(), 16);
+        assert!(act.starts_with("521433"));
+        assert!(luhn::is_valid(&act));
+    }
+
+    #[test]
+    fn generate_19_digits() {
+        let generator = a_generator(vec![false]);
+
+        let act = generator.generate(&a_program("62123456", 19)).unwrap();
+
+        assert_eq!(act.len(), 19);
+        assert!(act.starts_with("62123456"));
+        assert!(luhn::is_valid(&act));
+    }
+
+    #[test]
+    fn generate_retries_existing_pan() {
+        let generator = a_generator(vec![true, true, false]);
+
+        let act = generator.generate(&a_program("521433", 16));
+
+        assert!(act.is_ok());
+    }
+
+    #[test]
+    fn generate_gives_up() {
+        let generator = a_generator(vec![true; MAX_ATTEMPTS]);
+
+        let act = generator.generate(&a_program("521433", 16));
+
+        assert_eq!(act, Err(card::Error::DuplicatePan));
+    }
+
+    #[test]
+    fn generate_invalid_bin() {
+        let generator = Generator::new(Box::new(MockRepository::new()));
+
+        let act = generator.generate(&a_program("5214", 16));
+
+        assert_eq!(act, Err(card::Error::Generator));
+    }
+
+    #[test]
+    fn generate_repository_unavailable() {
+        let mut repository = MockRepository::new();
+        repository
+            .expect_exists_pan()
+            .return_const(Err(card::Error::Unavailable));
+        let generator = Generator::new(Box::new(repository));
+
+        let act = generator.generate(&a_program("521433", 16));
+
+        assert_eq!(act, Err(card::Error::Unavailable));
+    }
+
+    fn a_generator(existing: Vec<bool>) -> Generator {
+        let mut repository = MockRepository::new();
+        let mut sequence = mockall::Sequence::new();
+        for exists in existing {
+            repository
+                .expect_exists_pan()
+                .with(always())
+                .times(1)
+                .in_sequence(&mut sequence)
+                .return_const(Ok(exists));
+        }
+
+        Generator::new(Box::new(repository))
+    }
+
+    fn a_program(bin: &str, pan_length: usize) -> protocol::Program {
+        protocol::Program {
+            bin: String::from(bin),
+            pan_length,
+            ..Default::default()
+        }
+    }
+}
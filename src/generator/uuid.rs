@@ -0,0 +1,24 @@
+use crate::domain::card;
+
+// Random (version 4) ids, unique without coordination between instances.
+pub(crate) struct V4;
+
+impl card::UuidGenerator for V4 {
+    fn generate(&self) -> Result<uuid::Uuid, card::Error> {
+        Ok(uuid::Uuid::new_v4())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::UuidGenerator;
+
+    #[test]
+    fn generate_random_ids() {
+        let act = V4.generate().unwrap();
+
+        assert_eq!(act.get_version_num(), 4);
+        assert_ne!(act, V4.generate().unwrap());
+    }
+}
@@ -1,72 +1,758 @@
-use crate::domain::card;
+use crate::domain::{card, idempotency};
+use crate::handler::error::{Error, Kind};
 use crate::protocol;
-use actix_web::{web, HttpResponse};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::sync::Arc;
 
+// A request with an Idempotency-Key creates its card once, retries get the same card back.
 pub async fn create(
-    service: web::Data<Box<dyn card::Creator>>,
+    service: web::Data<Arc<dyn card::Creator>>,
+    idempotent: web::Data<Arc<dyn idempotency::Creator>>,
+    req: HttpRequest,
     payload: web::Json<protocol::Card>,
-) -> HttpResponse {
+) -> Result<HttpResponse, Error> {
     let dto: protocol::Card = payload.into_inner();
+    let created = match req.headers().get(IDEMPOTENCY_KEY) {
+        Some(key) => idempotent.create(&String::from_utf8_lossy(key.as_bytes()), dto),
+        None => service.create(dto),
+    };
 
-    match service.create(dto) {
-        Ok(card) => HttpResponse::Ok().json(card),
-        Err(err) => HttpResponse::BadRequest().json(err),
+    match created {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), SCOPE)),
     }
 }
 
+pub async fn find(
+    service: web::Data<Arc<dyn card::Finder>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let instance = instance(&id, "");
+    // an id that is not a UUID cannot name a card, so the path itself is malformed
+    if uuid::Uuid::parse_str(&id).is_err() {
+        let detail = format!("The card id {} is not a UUID", id);
+        return Err(Error::new(Kind::Malformed(detail), &instance));
+    }
+
+    match service.find(&id) {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), &instance)),
+    }
+}
+
+pub async fn list(
+    service: web::Data<Arc<dyn card::Finder>>,
+    filter: web::Query<protocol::Filter>,
+) -> Result<HttpResponse, Error> {
+    match service.list(filter.into_inner()) {
+        Ok(page) => Ok(HttpResponse::Ok().json(page)),
+        Err(err) => Err(Error::new(err.into(), SCOPE)),
+    }
+}
+
+pub async fn update(
+    service: web::Data<Arc<dyn card::Updater>>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    payload: web::Json<protocol::Card>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let instance = instance(&id, "");
+    let version = if_match(&req, &instance)?;
+
+    match service.update(&id, version, payload.into_inner()) {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), &instance)),
+    }
+}
+
+pub async fn block(
+    service: web::Data<Arc<dyn card::Lifecycle>>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    payload: web::Json<protocol::StatusChange>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let instance = instance(&id, "/block");
+    let version = if_match(&req, &instance)?;
+
+    match service.block(&id, version, payload.into_inner()) {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), &instance)),
+    }
+}
+
+pub async fn unblock(
+    service: web::Data<Arc<dyn card::Lifecycle>>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    payload: web::Json<protocol::StatusChange>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let instance = instance(&id, "/unblock");
+    let version = if_match(&req, &instance)?;
+
+    match service.unblock(&id, version, payload.into_inner()) {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), &instance)),
+    }
+}
+
+pub async fn cancel(
+    service: web::Data<Arc<dyn card::Lifecycle>>,
+    req: HttpRequest,
+    id: web::Path<String>,
+    payload: web::Json<protocol::StatusChange>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    let instance = instance(&id, "/cancel");
+    let version = if_match(&req, &instance)?;
+
+    match service.cancel(&id, version, payload.into_inner()) {
+        Ok(card) => Ok(with_etag(card)),
+        Err(err) => Err(Error::new(err.into(), &instance)),
+    }
+}
+
+pub async fn verify_password(
+    service: web::Data<Arc<dyn card::Verifier>>,
+    id: web::Path<String>,
+    payload: web::Json<protocol::PasswordAttempt>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+
+    match service.verify_password(&id, payload.into_inner()) {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(err) => Err(Error::new(err.into(), &instance(&id, "/password/verify"))),
+    }
+}
+
+pub async fn check_merchant(
+    service: web::Data<Arc<dyn card::MerchantChecker>>,
+    id: web::Path<String>,
+    payload: web::Json<protocol::MerchantCheck>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+
+    match service.check_merchant(&id, payload.into_inner()) {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(err) => Err(Error::new(err.into(), &instance(&id, "/merchant/check"))),
+    }
+}
+
+// The card version is its entity tag, which clients send back in If-Match to change it.
+fn with_etag(card: protocol::Card) -> HttpResponse {
+    HttpResponse::Ok()
+        .header(header::ETAG, format!("\"{}\"", card.version))
+        .json(card)
+}
+
+// Version named by the If-Match header. Only a single strong tag can match, so `*`, weak and
+// listed tags fail the precondition.
+fn if_match(req: &HttpRequest, instance: &str) -> Result<u64, Error> {
+    let value = req
+        .headers()
+        .get(header::IF_MATCH)
+        .ok_or_else(|| Error::new(Kind::PreconditionRequired, instance))?;
+
+    value
+        .to_str()
+        .ok()
+        .and_then(|tag| {
+            tag.trim()
+                .strip_prefix('"')?
+                .strip_suffix('"')?
+                .parse()
+                .ok()
+        })
+        .ok_or_else(|| Error::new(Kind::PreconditionFailed, instance))
+}
+
+// Path of the card resource the failed request was made to.
+fn instance(id: &str, action: &str) -> String {
+    format!("{}/{}{}", SCOPE, id, action)
+}
+
 pub static SCOPE: &str = "/cards";
+static IDEMPOTENCY_KEY: &str = "Idempotency-Key";
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::card::Creator;
-    use crate::protocol;
-    use crate::protocol::{Card, ValidationError};
-    use actix_web::web::{Data, Json};
+    use crate::domain::card::{
+        Creator, Error, Finder, Lifecycle, MerchantChecker, Updater, Verifier,
+    };
+    use crate::domain::idempotency;
+    use crate::protocol::{
+        Card, Code, Filter, MerchantCheck, MerchantVerification, Page, PasswordAttempt,
+        PasswordVerification, Problem, StatusChange, TransitionError, ValidationError,
+    };
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use actix_web::web::{Data, Json, Path, Query};
+    use actix_web::{HttpRequest, ResponseError};
     use mockall::mock;
     use mockall::predicate::eq;
     use std::str;
+    use std::sync::Arc;
 
     mock! {
             Creator {}
             impl Creator for Creator {
-               fn create(&self, card: crate::protocol::Card) -> Result<crate::protocol::Card, protocol::ValidationError>;
+               fn create(&self, card: crate::protocol::Card) -> Result<crate::protocol::Card, Error>;
+            }
+    }
+
+    mock! {
+            Idempotent {}
+            impl idempotency::Creator for Idempotent {
+               fn create(&self, key: &str, card: crate::protocol::Card) -> Result<crate::protocol::Card, Error>;
+            }
+    }
+
+    mock! {
+            Finder {}
+            impl Finder for Finder {
+               fn find(&self, id: &str) -> Result<crate::protocol::Card, Error>;
+               fn list(&self, filter: crate::protocol::Filter) -> Result<crate::protocol::Page, Error>;
+            }
+    }
+
+    mock! {
+            Updater {}
+            impl Updater for Updater {
+               fn update(&self, id: &str, version: u64, changes: crate::protocol::Card) -> Result<crate::protocol::Card, Error>;
+            }
+    }
+
+    mock! {
+            Lifecycle {}
+            impl Lifecycle for Lifecycle {
+               fn block(&self, id: &str, version: u64, change: crate::protocol::StatusChange) -> Result<crate::protocol::Card, Error>;
+               fn unblock(&self, id: &str, version: u64, change: crate::protocol::StatusChange) -> Result<crate::protocol::Card, Error>;
+               fn cancel(&self, id: &str, version: u64, change: crate::protocol::StatusChange) -> Result<crate::protocol::Card, Error>;
+            }
+    }
+
+    mock! {
+            Verifier {}
+            impl Verifier for Verifier {
+               fn verify_password(&self, id: &str, attempt: crate::protocol::PasswordAttempt) -> Result<crate::protocol::PasswordVerification, Error>;
+            }
+    }
+
+    mock! {
+            MerchantChecker {}
+            impl MerchantChecker for MerchantChecker {
+               fn check_merchant(&self, id: &str, check: crate::protocol::MerchantCheck) -> Result<crate::protocol::MerchantVerification, Error>;
             }
     }
 
     #[actix_rt::test]
     async fn must_call_card_service_success() {
-        let exp: Result<Card, protocol::ValidationError> = Ok(a_persisted_card());
+        let exp: Result<Card, Error> = Ok(a_persisted_card());
         let act = call(&exp).await;
         let act = serde_json::from_str::<Card>(&act).expect("Failed to parse body into Card json");
-        let exp = exp.unwrap();
+        let exp = a_response_card();
 
         assert_eq!(exp, act)
     }
 
+    #[actix_rt::test]
+    async fn must_mask_sensitive_fields() {
+        let act = call(&Ok(a_persisted_card())).await;
+
+        assert!(!act.contains("password"));
+        assert!(!act.contains("0781"));
+        assert!(!act.contains("cvv"));
+        assert!(!act.contains("5214330278318136"));
+        assert!(!act.contains("0702"));
+    }
+
     #[actix_rt::test]
     async fn must_call_card_service_validation_error() {
-        let exp: Result<Card, protocol::ValidationError> = Err(a_validation_error());
+        let exp: Result<Card, Error> = Err(Error::Invalid(a_validation_errors()));
         let act = call(&exp).await;
-        let act = serde_json::from_str::<ValidationError>(&act)
-            .expect("Failed to parse body into ValidationError json");
-        let exp = exp.unwrap_err();
+        let act =
+            serde_json::from_str::<Problem>(&act).expect("Failed to parse body into Problem json");
 
-        assert_eq!(exp, act)
+        assert_eq!(act.status(), 422);
+        assert_eq!(act.instance(), "/cards");
+        assert_eq!(act.errors(), a_validation_errors())
     }
 
-    async fn call(exp: &Result<Card, protocol::ValidationError>) -> String {
+    async fn call(exp: &Result<Card, Error>) -> String {
         let mut mock = MockCreator::new();
         mock.expect_create()
             .with(eq(a_input_card()))
             .return_const(exp.clone());
-        let response = super::create(Data::new(Box::new(mock)), Json(a_input_card())).await;
+        let response = super::create(
+            Data::new(Arc::new(mock)),
+            Data::new(Arc::new(MockIdempotent::new())),
+            TestRequest::default().to_http_request(),
+            Json(a_input_card()),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
         let act = match response.body().as_ref() {
             Some(actix_web::body::Body::Bytes(bytes)) => bytes,
             _ => panic!("Response error"),
         };
         let act = str::from_utf8(act).expect("Failed to parse Body::Bytes into str");
 
-        return String::from(act);
+        String::from(act)
+    }
+
+    #[actix_rt::test]
+    async fn must_create_card_once_per_idempotency_key() {
+        let mut idempotent = MockIdempotent::new();
+        idempotent
+            .expect_create()
+            .with(eq(String::from("a-retried-request")), eq(a_input_card()))
+            .return_const(Ok(a_persisted_card()));
+        let response = super::create(
+            Data::new(Arc::new(MockCreator::new())),
+            Data::new(Arc::new(idempotent)),
+            TestRequest::default()
+                .header("Idempotency-Key", "a-retried-request")
+                .to_http_request(),
+            Json(a_input_card()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"3\"");
+    }
+
+    #[actix_rt::test]
+    async fn must_create_card_idempotency_key_reused() {
+        let mut idempotent = MockIdempotent::new();
+        idempotent
+            .expect_create()
+            .return_const(Err(Error::KeyReused));
+        let response = super::create(
+            Data::new(Arc::new(MockCreator::new())),
+            Data::new(Arc::new(idempotent)),
+            TestRequest::default()
+                .header("Idempotency-Key", "a-retried-request")
+                .to_http_request(),
+            Json(a_input_card()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(response.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_rt::test]
+    async fn must_find_card_success() {
+        let (status, act) = call_find(Ok(a_persisted_card())).await;
+        let act = serde_json::from_str::<Card>(&act).expect("Failed to parse body into Card json");
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(act, a_response_card())
+    }
+
+    #[actix_rt::test]
+    async fn must_find_card_with_etag() {
+        let mut mock = MockFinder::new();
+        mock.expect_find().return_const(Ok(a_persisted_card()));
+
+        let response = super::find(Data::new(Arc::new(mock)), Path::from(String::from(AN_ID)))
+            .await
+            .unwrap();
+
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"3\"");
+    }
+
+    #[actix_rt::test]
+    async fn must_find_card_invalid_id() {
+        let mut mock = MockFinder::new();
+        mock.expect_find().never();
+        let response = super::find(Data::new(Arc::new(mock)), Path::from(String::from("1234")))
+            .await
+            .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => bytes,
+            _ => panic!("Response error"),
+        };
+        let act =
+            serde_json::from_slice::<Problem>(act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(act.problem_type(), "/problems/malformed-request");
+        assert_eq!(act.instance(), "/cards/1234");
+    }
+
+    #[actix_rt::test]
+    async fn must_find_card_not_found() {
+        let (status, _) = call_find(Err(Error::NotFound)).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn must_find_card_unavailable() {
+        let (status, _) = call_find(Err(Error::Unavailable)).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    async fn call_find(exp: Result<Card, Error>) -> (StatusCode, String) {
+        let id = String::from("29ce6541-302b-405e-9dfe-549934d4e4b2");
+        let mut mock = MockFinder::new();
+        mock.expect_find().with(eq(id.clone())).return_const(exp);
+        let response = super::find(Data::new(Arc::new(mock)), Path::from(id))
+            .await
+            .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+
+        (response.status(), String::from(act))
+    }
+
+    #[actix_rt::test]
+    async fn must_list_cards_success() {
+        let exp = Page::new(
+            vec![a_persisted_card()],
+            String::from("eyJJRCI6eyJTIjoiMSJ9fQ"),
+        );
+        let (status, act) = call_list(Ok(exp.clone())).await;
+        let act = serde_json::from_str::<Page>(&act).expect("Failed to parse body into Page json");
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(act.items, vec![a_response_card()]);
+        assert_eq!(act.next_token, exp.next_token)
+    }
+
+    #[actix_rt::test]
+    async fn must_list_cards_invalid_filter() {
+        let (status, act) = call_list(Err(Error::Invalid(a_validation_errors()))).await;
+        let act =
+            serde_json::from_str::<Problem>(&act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(act.errors(), a_validation_errors())
+    }
+
+    #[actix_rt::test]
+    async fn must_list_cards_unavailable() {
+        let (status, _) = call_list(Err(Error::Unavailable)).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    async fn call_list(exp: Result<Page, Error>) -> (StatusCode, String) {
+        let filter = Filter {
+            account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
+            ..Default::default()
+        };
+        let mut mock = MockFinder::new();
+        mock.expect_list()
+            .with(eq(filter.clone()))
+            .return_const(exp);
+        let response = super::list(Data::new(Arc::new(mock)), Query(filter))
+            .await
+            .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+
+        (response.status(), String::from(act))
+    }
+
+    #[actix_rt::test]
+    async fn must_update_card_success() {
+        let (status, act) = call_update(Ok(a_persisted_card())).await;
+        let act = serde_json::from_str::<Card>(&act).expect("Failed to parse body into Card json");
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(act, a_response_card())
+    }
+
+    #[actix_rt::test]
+    async fn must_update_card_invalid_field() {
+        let (status, act) = call_update(Err(Error::Invalid(a_validation_errors()))).await;
+        let act =
+            serde_json::from_str::<Problem>(&act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(act.errors(), a_validation_errors())
+    }
+
+    #[actix_rt::test]
+    async fn must_update_card_not_found() {
+        let (status, _) = call_update(Err(Error::NotFound)).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn must_update_card_unavailable() {
+        let (status, _) = call_update(Err(Error::Unavailable)).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    async fn call_update(exp: Result<Card, Error>) -> (StatusCode, String) {
+        let id = String::from("29ce6541-302b-405e-9dfe-549934d4e4b2");
+        let changes = Card {
+            printed_name: String::from("BAKER MAYFIELD"),
+            ..Default::default()
+        };
+        let mut mock = MockUpdater::new();
+        mock.expect_update()
+            .with(eq(id.clone()), eq(3), eq(changes.clone()))
+            .return_const(exp);
+        let response = super::update(
+            Data::new(Arc::new(mock)),
+            if_match("\"3\""),
+            Path::from(id),
+            Json(changes),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+
+        (response.status(), String::from(act))
+    }
+
+    #[actix_rt::test]
+    async fn must_block_card_success() {
+        let mut mock = MockLifecycle::new();
+        mock.expect_block()
+            .with(eq(String::from(AN_ID)), eq(3), eq(a_status_change()))
+            .return_const(Ok(a_persisted_card()));
+        let response = super::block(
+            Data::new(Arc::new(mock)),
+            if_match("\"3\""),
+            Path::from(String::from(AN_ID)),
+            Json(a_status_change()),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn must_unblock_card_success() {
+        let mut mock = MockLifecycle::new();
+        mock.expect_unblock()
+            .with(eq(String::from(AN_ID)), eq(3), eq(a_status_change()))
+            .return_const(Ok(a_persisted_card()));
+        let response = super::unblock(
+            Data::new(Arc::new(mock)),
+            if_match("\"3\""),
+            Path::from(String::from(AN_ID)),
+            Json(a_status_change()),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn must_update_card_without_if_match() {
+        let response = super::update(
+            Data::new(Arc::new(MockUpdater::new())),
+            TestRequest::default().to_http_request(),
+            Path::from(String::from(AN_ID)),
+            Json(Card::default()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(response.status_code(), StatusCode::PRECONDITION_REQUIRED);
+    }
+
+    #[actix_rt::test]
+    async fn must_block_card_with_weak_if_match() {
+        let response = super::block(
+            Data::new(Arc::new(MockLifecycle::new())),
+            if_match("W/\"3\""),
+            Path::from(String::from(AN_ID)),
+            Json(a_status_change()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(response.status_code(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[actix_rt::test]
+    async fn must_block_card_stale_version() {
+        let mut mock = MockLifecycle::new();
+        mock.expect_block()
+            .with(eq(String::from(AN_ID)), eq(3), eq(a_status_change()))
+            .return_const(Err(Error::VersionMismatch));
+        let response = super::block(
+            Data::new(Arc::new(mock)),
+            if_match("\"3\""),
+            Path::from(String::from(AN_ID)),
+            Json(a_status_change()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(response.status_code(), StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[actix_rt::test]
+    async fn must_cancel_card_disallowed() {
+        let exp = TransitionError::new(String::from("CANCELLED"), String::from("CANCEL"));
+        let mut mock = MockLifecycle::new();
+        mock.expect_cancel()
+            .with(eq(String::from(AN_ID)), eq(3), eq(a_status_change()))
+            .return_const(Err(Error::Disallowed(exp.clone())));
+        let response = super::cancel(
+            Data::new(Arc::new(mock)),
+            if_match("\"3\""),
+            Path::from(String::from(AN_ID)),
+            Json(a_status_change()),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+        let act =
+            serde_json::from_str::<Problem>(act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(act.detail(), exp.to_string());
+        assert_eq!(act.instance(), format!("/cards/{}/cancel", AN_ID));
+    }
+
+    #[actix_rt::test]
+    async fn must_verify_password_success() {
+        let exp = PasswordVerification::new(false, 2);
+        let (status, act) = call_verify_password(Ok(exp.clone())).await;
+        let act = serde_json::from_str::<PasswordVerification>(&act)
+            .expect("Failed to parse body into PasswordVerification json");
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(act, exp);
+    }
+
+    #[actix_rt::test]
+    async fn must_verify_password_of_blocked_card() {
+        let exp = TransitionError::new(String::from("BLOCKED"), String::from("VERIFY_PASSWORD"));
+        let (status, act) = call_verify_password(Err(Error::Disallowed(exp.clone()))).await;
+        let act =
+            serde_json::from_str::<Problem>(&act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(act.detail(), exp.to_string());
+    }
+
+    async fn call_verify_password(
+        exp: Result<PasswordVerification, Error>,
+    ) -> (StatusCode, String) {
+        let attempt = PasswordAttempt {
+            password: String::from("078112"),
+        };
+        let mut mock = MockVerifier::new();
+        mock.expect_verify_password()
+            .with(eq(String::from(AN_ID)), eq(attempt.clone()))
+            .return_const(exp);
+        let response = super::verify_password(
+            Data::new(Arc::new(mock)),
+            Path::from(String::from(AN_ID)),
+            Json(attempt),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+
+        (response.status(), String::from(act))
+    }
+
+    #[actix_rt::test]
+    async fn must_check_merchant_success() {
+        let exp = MerchantVerification::new(false);
+        let (status, act) = call_check_merchant(Ok(exp.clone())).await;
+        let act = serde_json::from_str::<MerchantVerification>(&act)
+            .expect("Failed to parse body into MerchantVerification json");
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(act, exp);
+    }
+
+    #[actix_rt::test]
+    async fn must_check_merchant_of_cancelled_card() {
+        let exp = TransitionError::new(String::from("CANCELLED"), String::from("CHECK_MERCHANT"));
+        let (status, act) = call_check_merchant(Err(Error::Disallowed(exp.clone()))).await;
+        let act =
+            serde_json::from_str::<Problem>(&act).expect("Failed to parse body into Problem json");
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(act.detail(), exp.to_string());
+        assert_eq!(act.instance(), format!("/cards/{}/merchant/check", AN_ID));
+    }
+
+    async fn call_check_merchant(exp: Result<MerchantVerification, Error>) -> (StatusCode, String) {
+        let check = MerchantCheck {
+            merchant_id: String::from("000000000012345"),
+            mcc: String::from("4899"),
+            lock: true,
+        };
+        let mut mock = MockMerchantChecker::new();
+        mock.expect_check_merchant()
+            .with(eq(String::from(AN_ID)), eq(check.clone()))
+            .return_const(exp);
+        let response = super::check_merchant(
+            Data::new(Arc::new(mock)),
+            Path::from(String::from(AN_ID)),
+            Json(check),
+        )
+        .await
+        .unwrap_or_else(|err| err.error_response());
+        let act = match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str")
+            }
+            _ => "",
+        };
+
+        (response.status(), String::from(act))
+    }
+
+    const AN_ID: &str = "29ce6541-302b-405e-9dfe-549934d4e4b2";
+
+    fn if_match(tag: &str) -> HttpRequest {
+        TestRequest::default()
+            .header(header::IF_MATCH, tag)
+            .to_http_request()
+    }
+
+    fn a_status_change() -> StatusChange {
+        StatusChange {
+            reason: String::from("FRAUD"),
+        }
     }
 
     fn a_input_card() -> Card {
@@ -78,12 +764,19 @@ mod tests {
             account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
             printed_name: String::from("Baker Mayfield"),
             password: String::from("0781"),
+            password_attempts: 0,
             expiration_date: "".to_string(),
             issuing_date: "".to_string(),
             pan: "".to_string(),
+            pan_hash: "".to_string(),
             kind: "".to_string(),
             status: "".to_string(),
+            status_reason: "".to_string(),
+            status_updated_at: "".to_string(),
             cvv: "".to_string(),
+            merchant_id: String::new(),
+            mcc: String::new(),
+            version: 0,
         }
     }
 
@@ -96,19 +789,48 @@ mod tests {
             account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
             printed_name: String::from("Baker Mayfield"),
             password: String::from("0781"),
+            password_attempts: 0,
             expiration_date: String::from("0702"),
             issuing_date: String::from("1997-07-16T19:20+01:00"),
             pan: String::from("5214330278318136"),
+            pan_hash: "".to_string(),
             kind: String::from("PLASTIC"),
             status: String::from("ENABLED"),
+            status_reason: String::from(""),
+            status_updated_at: String::from("1997-07-16T19:20+01:00"),
             cvv: String::from("945"),
+            merchant_id: String::new(),
+            mcc: String::new(),
+            version: 3,
         }
     }
 
-    fn a_validation_error() -> ValidationError {
-        ValidationError::new(
-            String::from("a_invalid_field"),
-            String::from("a_invalid_value"),
-        )
+    // what clients get back from a_persisted_card
+    fn a_response_card() -> Card {
+        Card {
+            password: String::new(),
+            expiration_date: String::from("****"),
+            pan: String::from("521433******8136"),
+            cvv: String::new(),
+            merchant_id: String::new(),
+            mcc: String::new(),
+            version: 0,
+            ..a_persisted_card()
+        }
+    }
+
+    fn a_validation_errors() -> Vec<ValidationError> {
+        vec![
+            ValidationError::new(
+                String::from("a_invalid_field"),
+                String::from("a_invalid_value"),
+                Code::PatternMismatch,
+            ),
+            ValidationError::new(
+                String::from("a_required_field"),
+                String::new(),
+                Code::Required,
+            ),
+        ]
     }
 }
@@ -0,0 +1,330 @@
+use crate::domain::card;
+use crate::protocol;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use std::fmt;
+
+pub static CONTENT_TYPE: &str = "application/problem+json";
+static ABOUT_BLANK: &str = "about:blank";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Kind {
+    // the request could not be read: broken JSON, wrong types, bad query string
+    Malformed(String),
+    NotFound,
+    Conflict(protocol::TransitionError),
+    DuplicatePan,
+    DuplicateId,
+    // the Idempotency-Key of a request still running
+    InProgress,
+    // the card kept changing under a write retried by the service itself
+    Contention,
+    // an Idempotency-Key already used for another request body
+    KeyReused,
+    // the request was read but some fields failed validation
+    Invalid(Vec<protocol::ValidationError>),
+    // the If-Match header does not name the current version of the card
+    PreconditionFailed,
+    // a change was sent without the If-Match header
+    PreconditionRequired,
+    Internal,
+    Unavailable,
+}
+
+// Failure of a request, rendered as an RFC 7807 `application/problem+json` body.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Error {
+    kind: Kind,
+    instance: String,
+}
+
+impl Error {
+    pub fn new(kind: Kind, instance: &str) -> Error {
+        Error {
+            kind,
+            instance: String::from(instance),
+        }
+    }
+
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    pub(crate) fn malformed_json(
+        err: actix_web::error::JsonPayloadError,
+        req: &HttpRequest,
+    ) -> actix_web::Error {
+        Error::new(Kind::Malformed(err.to_string()), req.path()).into()
+    }
+
+    pub(crate) fn malformed_query(
+        err: actix_web::error::QueryPayloadError,
+        req: &HttpRequest,
+    ) -> actix_web::Error {
+        Error::new(Kind::Malformed(err.to_string()), req.path()).into()
+    }
+
+    fn problem(&self) -> protocol::Problem {
+        let status = self.status_code();
+        let reason = status.canonical_reason().unwrap_or_default();
+        let (problem_type, title, detail, errors) = match &self.kind {
+            Kind::Malformed(detail) => (
+                "/problems/malformed-request",
+                "Malformed request",
+                detail.clone(),
+                vec![],
+            ),
+            Kind::NotFound => (
+                ABOUT_BLANK,
+                reason,
+                String::from("Nothing was found at the requested path"),
+                vec![],
+            ),
+            Kind::Conflict(err) => (
+                "/problems/transition-not-allowed",
+                "Transition not allowed",
+                err.to_string(),
+                vec![],
+            ),
+            Kind::DuplicatePan => (
+                "/problems/duplicate-pan",
+                "Duplicate PAN",
+                String::from("No unused PAN is left for the card program"),
+                vec![],
+            ),
+            Kind::DuplicateId => (
+                "/problems/duplicate-id",
+                "Duplicate id",
+                String::from("A card with this id already exists"),
+                vec![],
+            ),
+            Kind::InProgress => (
+                "/problems/request-in-progress",
+                "Request in progress",
+                String::from("A request with this Idempotency-Key is still running, retry later"),
+                vec![],
+            ),
+            Kind::Contention => (
+                "/problems/concurrent-update",
+                "Concurrent update",
+                String::from("The card kept changing while the request ran, retry it"),
+                vec![],
+            ),
+            Kind::KeyReused => (
+                "/problems/idempotency-key-reused",
+                "Idempotency-Key reused",
+                String::from("The Idempotency-Key was already sent with another request body"),
+                vec![],
+            ),
+            Kind::Invalid(errors) => (
+                "/problems/validation-error",
+                "Invalid request",
+                format!("{} field(s) failed validation", errors.len()),
+                errors.clone(),
+            ),
+            Kind::PreconditionFailed => (
+                "/problems/version-mismatch",
+                "Version mismatch",
+                String::from("The card changed since it was read, fetch it again"),
+                vec![],
+            ),
+            Kind::PreconditionRequired => (
+                "/problems/missing-if-match",
+                "Missing If-Match",
+                String::from("Send the ETag of the card read last in the If-Match header"),
+                vec![],
+            ),
+            Kind::Internal => (
+                ABOUT_BLANK,
+                reason,
+                String::from("The request could not be completed"),
+                vec![],
+            ),
+            Kind::Unavailable => (
+                ABOUT_BLANK,
+                reason,
+                String::from("The storage is unavailable, try again later"),
+                vec![],
+            ),
+        };
+
+        protocol::Problem::new(
+            String::from(problem_type),
+            String::from(title),
+            status.as_u16(),
+            detail,
+            self.instance.clone(),
+            errors,
+        )
+    }
+}
+
+impl From<card::Error> for Kind {
+    fn from(err: card::Error) -> Kind {
+        match err {
+            card::Error::Invalid(errors) => Kind::Invalid(errors),
+            card::Error::NotFound => Kind::NotFound,
+            card::Error::Disallowed(err) => Kind::Conflict(err),
+            card::Error::DuplicatePan => Kind::DuplicatePan,
+            card::Error::DuplicateId => Kind::DuplicateId,
+            card::Error::VersionMismatch => Kind::PreconditionFailed,
+            card::Error::KeyReused => Kind::KeyReused,
+            card::Error::InProgress => Kind::InProgress,
+            card::Error::Contention => Kind::Contention,
+            card::Error::Unavailable => Kind::Unavailable,
+            card::Error::Generator => Kind::Internal,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = self.problem();
+        write!(f, "{}: {}", problem.title(), problem.detail())
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self.kind {
+            Kind::Malformed(_) => StatusCode::BAD_REQUEST,
+            Kind::NotFound => StatusCode::NOT_FOUND,
+            Kind::Conflict(_)
+            | Kind::DuplicatePan
+            | Kind::DuplicateId
+            | Kind::InProgress
+            | Kind::Contention => StatusCode::CONFLICT,
+            Kind::Invalid(_) | Kind::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Kind::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Kind::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Kind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            Kind::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type(CONTENT_TYPE)
+            .json(self.problem())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header;
+
+    macro_rules! test_status {
+        ($name:ident, $kind:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                let act = Error::new($kind, "/cards").error_response();
+
+                assert_eq!(act.status(), $exp);
+                assert_eq!(
+                    act.headers().get(header::CONTENT_TYPE).unwrap(),
+                    CONTENT_TYPE
+                );
+            }
+        };
+    }
+
+    test_status!(
+        test_malformed,
+        Kind::Malformed(String::from("EOF")),
+        StatusCode::BAD_REQUEST
+    );
+    test_status!(test_not_found, Kind::NotFound, StatusCode::NOT_FOUND);
+    test_status!(
+        test_conflict,
+        Kind::Conflict(protocol::TransitionError::new(
+            String::from("CANCELLED"),
+            String::from("BLOCK")
+        )),
+        StatusCode::CONFLICT
+    );
+    test_status!(test_duplicate_pan, Kind::DuplicatePan, StatusCode::CONFLICT);
+    test_status!(test_duplicate_id, Kind::DuplicateId, StatusCode::CONFLICT);
+    test_status!(
+        test_invalid,
+        Kind::Invalid(vec![]),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    test_status!(test_in_progress, Kind::InProgress, StatusCode::CONFLICT);
+    test_status!(test_contention, Kind::Contention, StatusCode::CONFLICT);
+    test_status!(
+        test_key_reused,
+        Kind::KeyReused,
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    test_status!(
+        test_precondition_failed,
+        Kind::PreconditionFailed,
+        StatusCode::PRECONDITION_FAILED
+    );
+    test_status!(
+        test_precondition_required,
+        Kind::PreconditionRequired,
+        StatusCode::PRECONDITION_REQUIRED
+    );
+    test_status!(
+        test_internal,
+        Kind::Internal,
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+    test_status!(
+        test_unavailable,
+        Kind::Unavailable,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    #[test]
+    fn problem_with_field_errors() {
+        let errors = vec![protocol::ValidationError::new(
+            String::from("kind"),
+            String::from("GOLD"),
+            protocol::Code::UnknownEnum,
+        )];
+
+        let act = Error::new(Kind::Invalid(errors.clone()), "/cards").problem();
+
+        assert_eq!(act.problem_type(), "/problems/validation-error");
+        assert_eq!(act.title(), "Invalid request");
+        assert_eq!(act.status(), 422);
+        assert_eq!(act.detail(), "1 field(s) failed validation");
+        assert_eq!(act.instance(), "/cards");
+        assert_eq!(act.errors(), errors);
+    }
+
+    #[test]
+    fn problem_without_field_errors() {
+        let act = Error::new(
+            Kind::NotFound,
+            "/cards/5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10",
+        );
+        let json = serde_json::to_string(&act.problem()).unwrap();
+
+        assert_eq!(act.problem().problem_type(), "about:blank");
+        assert_eq!(act.problem().title(), "Not Found");
+        assert!(!json.contains("errors"));
+        assert!(json.contains("\"type\":\"about:blank\""));
+    }
+
+    #[test]
+    fn from_domain_error() {
+        assert_eq!(Kind::from(card::Error::NotFound), Kind::NotFound);
+        assert_eq!(Kind::from(card::Error::Unavailable), Kind::Unavailable);
+        assert_eq!(Kind::from(card::Error::DuplicatePan), Kind::DuplicatePan);
+        assert_eq!(Kind::from(card::Error::DuplicateId), Kind::DuplicateId);
+        assert_eq!(Kind::from(card::Error::Generator), Kind::Internal);
+        assert_eq!(
+            Kind::from(card::Error::VersionMismatch),
+            Kind::PreconditionFailed
+        );
+        assert_eq!(Kind::from(card::Error::Contention), Kind::Contention);
+        assert_eq!(
+            Kind::from(card::Error::Invalid(vec![])),
+            Kind::Invalid(vec![])
+        );
+    }
+}
@@ -1,2 +1,4 @@
 pub mod card;
+pub mod error;
+pub mod program;
 pub mod status;
@@ -0,0 +1,218 @@
+use crate::domain::program;
+use crate::handler::error::Error;
+use crate::protocol;
+use actix_web::{web, HttpResponse};
+use std::sync::Arc;
+
+pub async fn create(
+    service: web::Data<Arc<dyn program::Creator>>,
+    payload: web::Json<protocol::Program>,
+) -> Result<HttpResponse, Error> {
+    match service.create(payload.into_inner()) {
+        Ok(program) => Ok(HttpResponse::Ok().json(program)),
+        Err(err) => Err(Error::new(err.into(), SCOPE)),
+    }
+}
+
+pub async fn find(
+    service: web::Data<Arc<dyn program::Finder>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+
+    match service.find(&id) {
+        Ok(program) => Ok(HttpResponse::Ok().json(program)),
+        Err(err) => Err(Error::new(err.into(), &instance(&id))),
+    }
+}
+
+pub async fn list(service: web::Data<Arc<dyn program::Finder>>) -> Result<HttpResponse, Error> {
+    match service.list() {
+        Ok(programs) => Ok(HttpResponse::Ok().json(programs)),
+        Err(err) => Err(Error::new(err.into(), SCOPE)),
+    }
+}
+
+pub async fn update(
+    service: web::Data<Arc<dyn program::Updater>>,
+    id: web::Path<String>,
+    payload: web::Json<protocol::Program>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+
+    match service.update(&id, payload.into_inner()) {
+        Ok(program) => Ok(HttpResponse::Ok().json(program)),
+        Err(err) => Err(Error::new(err.into(), &instance(&id))),
+    }
+}
+
+pub async fn delete(
+    service: web::Data<Arc<dyn program::Remover>>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+
+    match service.delete(&id) {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Err(Error::new(err.into(), &instance(&id))),
+    }
+}
+
+// Path of the program resource the failed request was made to.
+fn instance(id: &str) -> String {
+    format!("{}/{}", SCOPE, id)
+}
+
+pub static SCOPE: &str = "/programs";
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::card::Error;
+    use crate::domain::program::{Creator, Finder, Remover, Updater};
+    use crate::protocol::{Problem, Program};
+    use actix_web::http::StatusCode;
+    use actix_web::web::{Data, Json, Path};
+    use actix_web::ResponseError;
+    use mockall::mock;
+    use mockall::predicate::eq;
+    use std::str;
+    use std::sync::Arc;
+
+    mock! {
+            Creator {}
+            impl Creator for Creator {
+               fn create(&self, program: crate::protocol::Program) -> Result<crate::protocol::Program, Error>;
+            }
+    }
+
+    mock! {
+            Finder {}
+            impl Finder for Finder {
+               fn find(&self, id: &str) -> Result<crate::protocol::Program, Error>;
+               fn list(&self) -> Result<Vec<crate::protocol::Program>, Error>;
+            }
+    }
+
+    mock! {
+            Updater {}
+            impl Updater for Updater {
+               fn update(&self, id: &str, program: crate::protocol::Program) -> Result<crate::protocol::Program, Error>;
+            }
+    }
+
+    mock! {
+            Remover {}
+            impl Remover for Remover {
+               fn delete(&self, id: &str) -> Result<(), Error>;
+            }
+    }
+
+    const AN_ID: &str = "35d86d4b-55e6-49c6-bc83-0b52d68b75f5";
+
+    fn a_program() -> Program {
+        Program {
+            id: String::from(AN_ID),
+            name: String::from("Gold"),
+            bin: String::from("41234567"),
+            pan_length: 16,
+            kinds: vec![String::from("PLASTIC")],
+            validity_months: 60,
+            temporary_ttl_hours: 0,
+            funding: String::from("CREDIT"),
+            active: true,
+        }
+    }
+
+    fn body(response: &actix_web::HttpResponse) -> String {
+        match response.body().as_ref() {
+            Some(actix_web::body::Body::Bytes(bytes)) => {
+                String::from(str::from_utf8(bytes).expect("Failed to parse Body::Bytes into str"))
+            }
+            _ => String::new(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn must_create_program() {
+        let input = Program {
+            id: String::new(),
+            ..a_program()
+        };
+        let mut mock = MockCreator::new();
+        mock.expect_create()
+            .with(eq(input.clone()))
+            .return_const(Ok(a_program()));
+
+        let response = super::create(Data::new(Arc::new(mock)), Json(input))
+            .await
+            .unwrap();
+        let act = serde_json::from_str::<Program>(&body(&response))
+            .expect("Failed to parse body into Program json");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(act, a_program());
+    }
+
+    #[actix_rt::test]
+    async fn must_find_program_not_found() {
+        let mut mock = MockFinder::new();
+        mock.expect_find()
+            .with(eq(String::from(AN_ID)))
+            .return_const(Err(Error::NotFound));
+
+        let response = super::find(Data::new(Arc::new(mock)), Path::from(String::from(AN_ID)))
+            .await
+            .unwrap_err()
+            .error_response();
+        let act = serde_json::from_str::<Problem>(&body(&response))
+            .expect("Failed to parse body into Problem json");
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(act.instance(), format!("/programs/{}", AN_ID));
+    }
+
+    #[actix_rt::test]
+    async fn must_list_programs() {
+        let mut mock = MockFinder::new();
+        mock.expect_list().return_const(Ok(vec![a_program()]));
+
+        let response = super::list(Data::new(Arc::new(mock))).await.unwrap();
+        let act = serde_json::from_str::<Vec<Program>>(&body(&response))
+            .expect("Failed to parse body into Program list json");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(act, vec![a_program()]);
+    }
+
+    #[actix_rt::test]
+    async fn must_update_program_unavailable() {
+        let mut mock = MockUpdater::new();
+        mock.expect_update()
+            .with(eq(String::from(AN_ID)), eq(a_program()))
+            .return_const(Err(Error::Unavailable));
+
+        let response = super::update(
+            Data::new(Arc::new(mock)),
+            Path::from(String::from(AN_ID)),
+            Json(a_program()),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(response.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_rt::test]
+    async fn must_delete_program() {
+        let mut mock = MockRemover::new();
+        mock.expect_delete()
+            .with(eq(String::from(AN_ID)))
+            .return_const(Ok(()));
+
+        let response = super::delete(Data::new(Arc::new(mock)), Path::from(String::from(AN_ID)))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -1,4 +1,8 @@
 pub mod config;
+pub mod crypto;
 pub mod domain;
+pub mod generator;
 pub mod handler;
 pub mod protocol;
+pub mod repository;
+pub mod settings;
@@ -1,8 +1,12 @@
+use crate::protocol::mask;
 use indoc::indoc;
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+// Serialization, Display and Debug mask the PAN and expiration date and leave the CVV and
+// password out; `unmasked` gives the clear values to the internal callers that need them.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default)]
 pub struct Card {
     #[serde(default)]
     pub(crate) id: String,
@@ -16,20 +20,46 @@ pub struct Card {
     pub(crate) account_id: String,
     #[serde(default)]
     pub(crate) printed_name: String,
-    #[serde(default)]
+    // Salted hash of the PIN, never sent back to clients.
+    #[serde(default, skip_serializing)]
     pub(crate) password: String,
-    #[serde(default)]
+    // Failed verifications since the last successful one.
+    #[serde(skip)]
+    pub(crate) password_attempts: u32,
+    #[serde(default, serialize_with = "mask::serialize_expiration_date")]
     pub(crate) expiration_date: String,
     #[serde(default)]
     pub(crate) issuing_date: String,
-    #[serde(default)]
+    #[serde(default, serialize_with = "mask::serialize_pan")]
     pub(crate) pan: String,
+    // Keyed hash of the PAN used for uniqueness lookups when the PAN is stored encrypted.
+    #[serde(skip)]
+    pub(crate) pan_hash: String,
     #[serde(default)]
     pub(crate) kind: String,
     #[serde(default)]
     pub(crate) status: String,
     #[serde(default)]
+    pub(crate) status_reason: String,
+    #[serde(default)]
+    pub(crate) status_updated_at: String,
+    #[serde(default, skip_serializing)]
     pub(crate) cvv: String,
+    // Merchant a recurring card is locked to, by id and/or category code. Empty until the card is
+    // locked at creation or on its first charge.
+    #[serde(default)]
+    pub(crate) merchant_id: String,
+    #[serde(default)]
+    pub(crate) mcc: String,
+    // Incremented by every write and sent as the ETag, so clients update the card they read.
+    #[serde(default, skip_serializing)]
+    pub(crate) version: u64,
+}
+
+impl Card {
+    pub(crate) fn unmasked(&self) -> Unmasked<'_> {
+        Unmasked(self)
+    }
 }
 
 impl fmt::Display for Card {
@@ -44,13 +74,15 @@ impl fmt::Display for Card {
                         program_id: {},
                         account_id: {},
                         printed_name: {},
-                        password: {},
                         expiration_date: {},
                         issuing_date: {},
                         pan: {},
                         kind: {},
                         status: {},
-                        cvv: {},
+                        status_reason: {},
+                        status_updated_at: {},
+                        merchant_id: {},
+                        mcc: {},
                     }}
                   "},
             self.id,
@@ -59,13 +91,147 @@ impl fmt::Display for Card {
             self.program_id,
             self.account_id,
             self.printed_name,
-            self.password,
-            self.expiration_date,
+            mask::expiration_date(&self.expiration_date),
             self.issuing_date,
-            self.pan,
+            mask::pan(&self.pan),
             self.kind,
             self.status,
-            self.cvv
+            self.status_reason,
+            self.status_updated_at,
+            self.merchant_id,
+            self.mcc
         )
     }
 }
+
+impl fmt::Debug for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Card")
+            .field("id", &self.id)
+            .field("customer_id", &self.customer_id)
+            .field("org_id", &self.org_id)
+            .field("program_id", &self.program_id)
+            .field("account_id", &self.account_id)
+            .field("printed_name", &self.printed_name)
+            .field("password_attempts", &self.password_attempts)
+            .field(
+                "expiration_date",
+                &mask::expiration_date(&self.expiration_date),
+            )
+            .field("issuing_date", &self.issuing_date)
+            .field("pan", &mask::pan(&self.pan))
+            .field("kind", &self.kind)
+            .field("status", &self.status)
+            .field("status_reason", &self.status_reason)
+            .field("status_updated_at", &self.status_updated_at)
+            .field("merchant_id", &self.merchant_id)
+            .field("mcc", &self.mcc)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+// Privileged view serializing every value in clear, e.g. for card production. It must never
+// reach a client response or a log line.
+pub(crate) struct Unmasked<'a>(&'a Card);
+
+impl Serialize for Unmasked<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let card = self.0;
+        let mut state = serializer.serialize_struct("Card", 18)?;
+        state.serialize_field("id", &card.id)?;
+        state.serialize_field("customer_id", &card.customer_id)?;
+        state.serialize_field("org_id", &card.org_id)?;
+        state.serialize_field("program_id", &card.program_id)?;
+        state.serialize_field("account_id", &card.account_id)?;
+        state.serialize_field("printed_name", &card.printed_name)?;
+        state.serialize_field("password", &card.password)?;
+        state.serialize_field("expiration_date", &card.expiration_date)?;
+        state.serialize_field("issuing_date", &card.issuing_date)?;
+        state.serialize_field("pan", &card.pan)?;
+        state.serialize_field("kind", &card.kind)?;
+        state.serialize_field("status", &card.status)?;
+        state.serialize_field("status_reason", &card.status_reason)?;
+        state.serialize_field("status_updated_at", &card.status_updated_at)?;
+        state.serialize_field("cvv", &card.cvv)?;
+        state.serialize_field("merchant_id", &card.merchant_id)?;
+        state.serialize_field("mcc", &card.mcc)?;
+        state.serialize_field("version", &card.version)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_masks_sensitive_fields() {
+        let act = format!("{}", a_card());
+
+        assert!(act.contains("pan: 521433******8136"));
+        assert!(act.contains("expiration_date: ****"));
+        assert_sensitive_values_absent(&act);
+    }
+
+    #[test]
+    fn debug_masks_sensitive_fields() {
+        let act = format!("{:?}", a_card());
+
+        assert!(act.contains("pan: \"521433******8136\""));
+        assert!(act.contains("expiration_date: \"****\""));
+        assert_sensitive_values_absent(&act);
+    }
+
+    #[test]
+    fn serialize_masks_sensitive_fields() {
+        let act = serde_json::to_string(&a_card()).unwrap();
+
+        assert!(act.contains("\"pan\":\"521433******8136\""));
+        assert!(act.contains("\"expiration_date\":\"****\""));
+        assert!(!act.contains("\"cvv\""));
+        assert!(!act.contains("\"password\""));
+        assert_sensitive_values_absent(&act);
+    }
+
+    #[test]
+    fn serialize_unmasked() {
+        let card = a_card();
+
+        let act = serde_json::to_string(&card.unmasked()).unwrap();
+
+        assert_eq!(serde_json::from_str::<Card>(&act).unwrap(), card);
+    }
+
+    fn assert_sensitive_values_absent(act: &str) {
+        assert!(!act.contains("5214330278318136"));
+        assert!(!act.contains("0726"));
+        assert!(!act.contains("945"));
+        assert!(!act.contains("argon2"));
+    }
+
+    fn a_card() -> Card {
+        Card {
+            id: String::from("29ce6541-302b-405e-9dfe-549934d4e4b2"),
+            customer_id: String::from("df101f6e-6c3a-484b-94f9-16d134bde2e1"),
+            org_id: String::from("876ce143-6fcb-4c17-aaf1-f02c1d3654ce"),
+            program_id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+            account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
+            printed_name: String::from("BAKER MAYFIELD"),
+            password: String::from("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA"),
+            password_attempts: 0,
+            expiration_date: String::from("0726"),
+            issuing_date: String::from("2021-02-16 19:20:00"),
+            pan: String::from("5214330278318136"),
+            pan_hash: String::from(""),
+            kind: String::from("PLASTIC"),
+            status: String::from("ENABLED"),
+            status_reason: String::from(""),
+            status_updated_at: String::from("2021-02-16 19:20:00"),
+            cvv: String::from("945"),
+            merchant_id: String::from("NETFLIX01"),
+            mcc: String::from("4899"),
+            version: 3,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct Filter {
+    #[serde(default)]
+    pub(crate) customer_id: String,
+    #[serde(default)]
+    pub(crate) account_id: String,
+    #[serde(default)]
+    pub(crate) program_id: String,
+    #[serde(default)]
+    pub(crate) org_id: String,
+    #[serde(default)]
+    pub(crate) kind: String,
+    #[serde(default)]
+    pub(crate) status: String,
+    #[serde(default)]
+    pub(crate) limit: u32,
+    #[serde(default)]
+    pub(crate) next_token: String,
+}
@@ -0,0 +1,73 @@
+use serde::Serializer;
+
+static MASK: char = '*';
+const PAN_FIRST: usize = 6;
+const PAN_LAST: usize = 4;
+
+// PCI DSS allows at most the first six and last four digits of a PAN to be displayed.
+pub(crate) fn pan(pan: &str) -> String {
+    let length = pan.chars().count();
+    if length <= PAN_FIRST + PAN_LAST {
+        return MASK.to_string().repeat(length);
+    }
+
+    pan.chars()
+        .enumerate()
+        .map(|(i, c)| match i < PAN_FIRST || i >= length - PAN_LAST {
+            true => c,
+            false => MASK,
+        })
+        .collect()
+}
+
+pub(crate) fn expiration_date(expiration_date: &str) -> String {
+    MASK.to_string().repeat(expiration_date.chars().count())
+}
+
+pub(crate) fn serialize_pan<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&pan(value))
+}
+
+pub(crate) fn serialize_expiration_date<S: Serializer>(
+    value: &str,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&expiration_date(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_mask {
+        ($name:ident, $function:ident, $input:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!($function($input), $exp);
+            }
+        };
+    }
+
+    test_mask!(
+        test_pan_16_digits,
+        pan,
+        "5214330278318136",
+        "521433******8136"
+    );
+    test_mask!(
+        test_pan_19_digits,
+        pan,
+        "6212345678901234569",
+        "621234*********4569"
+    );
+    test_mask!(
+        test_pan_15_digits,
+        pan,
+        "378282246310005",
+        "378282*****0005"
+    );
+    test_mask!(test_pan_too_short, pan, "5214330278", "**********");
+    test_mask!(test_pan_empty, pan, "", "");
+    test_mask!(test_expiration_date, expiration_date, "0724", "****");
+    test_mask!(test_expiration_date_empty, expiration_date, "", "");
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct MerchantCheck {
+    #[serde(default)]
+    pub(crate) merchant_id: String,
+    #[serde(default)]
+    pub(crate) mcc: String,
+    // set by a charge, which locks an unlocked recurring card to this merchant
+    #[serde(default)]
+    pub(crate) lock: bool,
+}
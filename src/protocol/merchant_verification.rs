@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct MerchantVerification {
+    #[serde(default)]
+    allowed: bool,
+}
+
+impl MerchantVerification {
+    pub(crate) fn new(allowed: bool) -> MerchantVerification {
+        MerchantVerification { allowed }
+    }
+
+    pub fn allowed(&self) -> bool {
+        self.allowed
+    }
+}
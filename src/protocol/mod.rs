@@ -1,5 +1,26 @@
 pub use card::Card;
-pub use validation_error::ValidationError;
+pub use filter::Filter;
+pub use merchant_check::MerchantCheck;
+pub use merchant_verification::MerchantVerification;
+pub use page::Page;
+pub use password_attempt::PasswordAttempt;
+pub use password_verification::PasswordVerification;
+pub use problem::Problem;
+pub use program::Program;
+pub use status_change::StatusChange;
+pub use transition_error::TransitionError;
+pub use validation_error::{Code, ValidationError};
 
 mod card;
+mod filter;
+mod mask;
+mod merchant_check;
+mod merchant_verification;
+mod page;
+mod password_attempt;
+mod password_verification;
+mod problem;
+mod program;
+mod status_change;
+mod transition_error;
 mod validation_error;
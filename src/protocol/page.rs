@@ -0,0 +1,16 @@
+use crate::protocol::Card;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Page {
+    #[serde(default)]
+    pub(crate) items: Vec<Card>,
+    #[serde(default)]
+    pub(crate) next_token: String,
+}
+
+impl Page {
+    pub(crate) fn new(items: Vec<Card>, next_token: String) -> Page {
+        Page { items, next_token }
+    }
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct PasswordAttempt {
+    #[serde(default)]
+    pub(crate) password: String,
+}
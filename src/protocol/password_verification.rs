@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PasswordVerification {
+    #[serde(default)]
+    valid: bool,
+    #[serde(default)]
+    remaining_attempts: u32,
+}
+
+impl PasswordVerification {
+    pub(crate) fn new(valid: bool, remaining_attempts: u32) -> PasswordVerification {
+        PasswordVerification {
+            valid,
+            remaining_attempts,
+        }
+    }
+
+    pub fn valid(&self) -> bool {
+        self.valid
+    }
+
+    pub fn remaining_attempts(&self) -> u32 {
+        self.remaining_attempts
+    }
+}
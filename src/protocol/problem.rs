@@ -0,0 +1,63 @@
+use crate::protocol::ValidationError;
+use serde::{Deserialize, Serialize};
+
+// Error body as specified by RFC 7807, extended with the failures of each invalid field.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Problem {
+    #[serde(rename = "type", default)]
+    problem_type: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    status: u16,
+    #[serde(default)]
+    detail: String,
+    #[serde(default)]
+    instance: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ValidationError>,
+}
+
+impl Problem {
+    pub(crate) fn new(
+        problem_type: String,
+        title: String,
+        status: u16,
+        detail: String,
+        instance: String,
+        errors: Vec<ValidationError>,
+    ) -> Problem {
+        Problem {
+            problem_type,
+            title,
+            status,
+            detail,
+            instance,
+            errors,
+        }
+    }
+
+    pub fn problem_type(&self) -> String {
+        self.problem_type.clone()
+    }
+
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn detail(&self) -> String {
+        self.detail.clone()
+    }
+
+    pub fn instance(&self) -> String {
+        self.instance.clone()
+    }
+
+    pub fn errors(&self) -> Vec<ValidationError> {
+        self.errors.clone()
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+// Card program: the BIN range its PANs are issued from and the cards it may issue.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct Program {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    // first 6 or 8 digits shared by every PAN of the program
+    pub(crate) bin: String,
+    pub(crate) pan_length: usize,
+    // kinds of card the program issues
+    pub(crate) kinds: Vec<String>,
+    // months a card is valid from its issuing date
+    pub(crate) validity_months: u32,
+    // hours a temporary card lives from its issuing date, the program issues no other kind with one
+    pub(crate) temporary_ttl_hours: u32,
+    pub(crate) funding: String,
+    // an inactive program keeps its cards but issues no new ones
+    pub(crate) active: bool,
+}
+
+// Programs are created active unless the request says otherwise.
+impl Default for Program {
+    fn default() -> Program {
+        Program {
+            id: String::new(),
+            name: String::new(),
+            bin: String::new(),
+            pan_length: 0,
+            kinds: vec![],
+            validity_months: 0,
+            temporary_ttl_hours: 0,
+            funding: String::new(),
+            active: true,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct StatusChange {
+    #[serde(default)]
+    pub(crate) reason: String,
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransitionError {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    transition: String,
+}
+
+impl TransitionError {
+    pub(crate) fn new(status: String, transition: String) -> TransitionError {
+        TransitionError { status, transition }
+    }
+
+    pub fn status(&self) -> String {
+        self.status.clone()
+    }
+
+    pub fn transition(&self) -> String {
+        self.transition.clone()
+    }
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Transition \"{}\" is not allowed for status \"{}\"",
+            self.transition, self.status
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_and_format() {
+        let exp = "Transition \"UNBLOCK\" is not allowed for status \"CANCELLED\"";
+
+        let act = format!(
+            "{}",
+            TransitionError::new(String::from("CANCELLED"), String::from("UNBLOCK"))
+        );
+
+        assert_eq!(act, exp);
+    }
+}
@@ -1,19 +1,74 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+// Machine-readable reason of a validation failure.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Code {
+    Required,
+    PatternMismatch,
+    UnknownEnum,
+    UuidFormat,
+    Immutable,
+    OutOfRange,
+    NotAllowed,
+    UnknownReference,
+    Inactive,
+    Unsupported,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ValidationError {
     #[serde(default)]
     field_name: String,
     #[serde(default)]
     inputted_value: String,
+    code: Code,
+    #[serde(default)]
+    message: String,
 }
 
 impl ValidationError {
-    pub(crate) fn new(field_name: String, inputted_value: String) -> ValidationError {
+    pub(crate) fn new(field_name: String, inputted_value: String, code: Code) -> ValidationError {
+        let message = match code {
+            Code::Required => format!("Field \"{}\" is required", field_name),
+            Code::PatternMismatch => format!(
+                "Value \"{}\" does not match the format of field \"{}\"",
+                inputted_value, field_name
+            ),
+            Code::UnknownEnum => format!(
+                "Value \"{}\" is not an accepted value for field \"{}\"",
+                inputted_value, field_name
+            ),
+            Code::UuidFormat => format!(
+                "Value \"{}\" is not a valid UUID for field \"{}\"",
+                inputted_value, field_name
+            ),
+            Code::Immutable => format!("Field \"{}\" cannot be changed", field_name),
+            Code::OutOfRange => format!(
+                "Value \"{}\" is out of range for field \"{}\"",
+                inputted_value, field_name
+            ),
+            Code::NotAllowed => format!("Field \"{}\" cannot be supplied", field_name),
+            Code::UnknownReference => format!(
+                "Value \"{}\" of field \"{}\" does not exist",
+                inputted_value, field_name
+            ),
+            Code::Inactive => format!(
+                "Value \"{}\" of field \"{}\" is inactive",
+                inputted_value, field_name
+            ),
+            Code::Unsupported => format!(
+                "Value \"{}\" is not supported for field \"{}\"",
+                inputted_value, field_name
+            ),
+        };
+
         ValidationError {
             field_name,
             inputted_value,
+            code,
+            message,
         }
     }
 
@@ -24,18 +79,19 @@ impl ValidationError {
     pub fn inputted_value(&self) -> String {
         self.inputted_value.clone()
     }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
 }
 
 impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            format!(
-                "Value \"{}\" is not valid for field \"{}\"",
-                self.inputted_value, self.field_name
-            )
-        )
+        write!(f, "{}", self.message)
     }
 }
 
@@ -43,15 +99,83 @@ impl fmt::Display for ValidationError {
 mod tests {
     use super::*;
 
-    #[test]
-    fn new_and_format() {
-        let exp = "Value \"invalid_value\" is not valid for field \"invalid_field\"";
+    macro_rules! test_new_and_format {
+        ($name:ident, $code:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                let act = ValidationError::new(
+                    String::from("invalid_field"),
+                    String::from("invalid_value"),
+                    $code,
+                );
+
+                assert_eq!(format!("{}", act), $exp);
+                assert_eq!(act.message(), $exp);
+                assert_eq!(act.code(), $code);
+            }
+        };
+    }
+
+    test_new_and_format!(
+        new_and_format_required,
+        Code::Required,
+        "Field \"invalid_field\" is required"
+    );
+    test_new_and_format!(
+        new_and_format_pattern_mismatch,
+        Code::PatternMismatch,
+        "Value \"invalid_value\" does not match the format of field \"invalid_field\""
+    );
+    test_new_and_format!(
+        new_and_format_unknown_enum,
+        Code::UnknownEnum,
+        "Value \"invalid_value\" is not an accepted value for field \"invalid_field\""
+    );
+    test_new_and_format!(
+        new_and_format_uuid_format,
+        Code::UuidFormat,
+        "Value \"invalid_value\" is not a valid UUID for field \"invalid_field\""
+    );
+    test_new_and_format!(
+        new_and_format_immutable,
+        Code::Immutable,
+        "Field \"invalid_field\" cannot be changed"
+    );
+    test_new_and_format!(
+        new_and_format_out_of_range,
+        Code::OutOfRange,
+        "Value \"invalid_value\" is out of range for field \"invalid_field\""
+    );
+    test_new_and_format!(
+        new_and_format_not_allowed,
+        Code::NotAllowed,
+        "Field \"invalid_field\" cannot be supplied"
+    );
+    test_new_and_format!(
+        new_and_format_unknown_reference,
+        Code::UnknownReference,
+        "Value \"invalid_value\" of field \"invalid_field\" does not exist"
+    );
+    test_new_and_format!(
+        new_and_format_inactive,
+        Code::Inactive,
+        "Value \"invalid_value\" of field \"invalid_field\" is inactive"
+    );
+    test_new_and_format!(
+        new_and_format_unsupported,
+        Code::Unsupported,
+        "Value \"invalid_value\" is not supported for field \"invalid_field\""
+    );
 
-        let act = format!(
-            "{}",
-            ValidationError::new(String::from("invalid_field"), String::from("invalid_value"))
-        );
+    #[test]
+    fn serialize_code_in_snake_case() {
+        let act = serde_json::to_string(&ValidationError::new(
+            String::from("expiration_date"),
+            String::from("1300"),
+            Code::PatternMismatch,
+        ))
+        .unwrap();
 
-        assert_eq!(act, exp);
+        assert!(act.contains("\"code\":\"pattern_mismatch\""));
     }
 }
@@ -0,0 +1,477 @@
+// Behaviour every `card::Repository` backend, which is also the `idempotency::Store`, the
+// `program::Repository` and the `expiry::Lease`, must share. A backend instantiates the suite in its tests with
+// `conformance_tests!(<expression building a repository>)`, followed by attributes for every
+// test such as `ignore = "..."` when it needs external services.
+//
+// Cards, programs and leases get random ids, names and PANs, so the suite also runs against storage kept
+// between runs.
+use crate::domain::card::{self, Repository};
+use crate::domain::expiry::Lease;
+use crate::domain::idempotency::{self, Store};
+use crate::domain::program;
+use crate::protocol;
+use std::collections::HashSet;
+
+macro_rules! conformance_tests {
+    ($repository:expr $(, $attr:meta)*) => {
+        mod conformance {
+            use super::*;
+            use crate::repository::conformance;
+
+            #[test]
+            $(#[$attr])*
+            fn save_and_find() {
+                conformance::save_and_find(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn save_rejects_duplicate_id() {
+                conformance::save_rejects_duplicate_id(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn save_rejects_duplicate_pan() {
+                conformance::save_rejects_duplicate_pan(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn update_and_find() {
+                conformance::update_and_find(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn update_conflicts() {
+                conformance::update_conflicts(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn list_filtered() {
+                conformance::list_filtered(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn list_pages_in_a_stable_order() {
+                conformance::list_pages_in_a_stable_order(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn list_rejects_invalid_token() {
+                conformance::list_rejects_invalid_token(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn claim_and_complete_key() {
+                conformance::claim_and_complete_key(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn claim_expired_or_released_key() {
+                conformance::claim_expired_or_released_key(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn save_and_find_program() {
+                conformance::save_and_find_program(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn update_and_delete_program() {
+                conformance::update_and_delete_program(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn list_programs() {
+                conformance::list_programs(&$repository);
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn acquire_lease() {
+                conformance::acquire_lease(&$repository);
+            }
+        }
+    };
+}
+
+pub(crate) use conformance_tests;
+
+fn random_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn a_card() -> protocol::Card {
+    protocol::Card {
+        id: random_id(),
+        customer_id: random_id(),
+        org_id: String::from("876ce143-6fcb-4c17-aaf1-f02c1d3654ce"),
+        program_id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+        account_id: random_id(),
+        printed_name: String::from("BAKER MAYFIELD"),
+        password: String::from("sealed password"),
+        password_attempts: 0,
+        expiration_date: String::from("sealed expiration date"),
+        issuing_date: String::from("2021-02-16 19:20:00"),
+        pan: String::from("sealed pan"),
+        pan_hash: random_id(),
+        kind: String::from("PLASTIC"),
+        status: String::from("ENABLED"),
+        status_reason: String::new(),
+        status_updated_at: String::from("2021-02-16 19:20:00"),
+        cvv: String::from("945"),
+        merchant_id: String::new(),
+        mcc: String::new(),
+        version: 1,
+    }
+}
+
+fn id(card: &protocol::Card) -> uuid::Uuid {
+    uuid::Uuid::parse_str(&card.id).unwrap()
+}
+
+// The PAN hash is only written, as the lookup key of `exists_pan`, and the CVV is never stored.
+pub(crate) fn as_read(card: &protocol::Card) -> protocol::Card {
+    protocol::Card {
+        pan_hash: String::new(),
+        cvv: String::new(),
+        ..card.clone()
+    }
+}
+
+pub(crate) fn save_and_find<R: Repository>(repository: &R) {
+    let card = a_card();
+
+    assert_eq!(repository.save(&card), Ok(()));
+
+    assert_eq!(repository.find_by_id(id(&card)), Ok(Some(as_read(&card))));
+    assert_eq!(repository.find_by_id(uuid::Uuid::new_v4()), Ok(None));
+}
+
+pub(crate) fn save_rejects_duplicate_id<R: Repository>(repository: &R) {
+    let card = a_card();
+    repository.save(&card).unwrap();
+
+    let act = repository.save(&protocol::Card {
+        pan_hash: random_id(),
+        status: String::from("BLOCKED"),
+        ..card.clone()
+    });
+
+    assert_eq!(act, Err(card::Error::DuplicateId));
+    assert_eq!(repository.find_by_id(id(&card)), Ok(Some(as_read(&card))));
+}
+
+pub(crate) fn save_rejects_duplicate_pan<R: Repository>(repository: &R) {
+    let card = a_card();
+    let duplicate = protocol::Card {
+        pan_hash: card.pan_hash.clone(),
+        ..a_card()
+    };
+    assert_eq!(repository.exists_pan(&card.pan_hash), Ok(false));
+    repository.save(&card).unwrap();
+
+    let act = repository.save(&duplicate);
+
+    assert_eq!(act, Err(card::Error::DuplicatePan));
+    assert_eq!(repository.find_by_id(id(&duplicate)), Ok(None));
+    assert_eq!(repository.exists_pan(&card.pan_hash), Ok(true));
+}
+
+pub(crate) fn update_and_find<R: Repository>(repository: &R) {
+    let card = a_card();
+    repository.save(&card).unwrap();
+    let blocked = protocol::Card {
+        status: String::from("BLOCKED"),
+        status_reason: String::from("LOST"),
+        status_updated_at: String::from("2021-02-17 10:00:00"),
+        password_attempts: 2,
+        merchant_id: String::from("000000000012345"),
+        mcc: String::from("4899"),
+        version: 2,
+        ..card.clone()
+    };
+
+    assert_eq!(repository.update(&blocked), Ok(()));
+
+    assert_eq!(
+        repository.find_by_id(id(&card)),
+        Ok(Some(as_read(&blocked)))
+    );
+    assert_eq!(repository.exists_pan(&card.pan_hash), Ok(true));
+}
+
+pub(crate) fn update_conflicts<R: Repository>(repository: &R) {
+    let card = a_card();
+    let unknown = repository.update(&protocol::Card {
+        version: 2,
+        ..card.clone()
+    });
+    repository.save(&card).unwrap();
+    let stale = |version: u64| {
+        repository.update(&protocol::Card {
+            status: String::from("BLOCKED"),
+            version,
+            ..card.clone()
+        })
+    };
+
+    assert_eq!(unknown, Err(card::Error::NotFound));
+    assert_eq!(stale(1), Err(card::Error::VersionMismatch));
+    assert_eq!(stale(3), Err(card::Error::VersionMismatch));
+    assert_eq!(repository.find_by_id(id(&card)), Ok(Some(as_read(&card))));
+}
+
+pub(crate) fn list_filtered<R: Repository>(repository: &R) {
+    let enabled = a_card();
+    let blocked = protocol::Card {
+        customer_id: enabled.customer_id.clone(),
+        status: String::from("BLOCKED"),
+        ..a_card()
+    };
+    repository.save(&enabled).unwrap();
+    repository.save(&blocked).unwrap();
+    let list = |filter: protocol::Filter| {
+        repository
+            .list(&protocol::Filter {
+                limit: 20,
+                ..filter
+            })
+            .unwrap()
+            .items
+    };
+
+    assert_eq!(
+        list(protocol::Filter {
+            customer_id: enabled.customer_id.clone(),
+            status: String::from("BLOCKED"),
+            ..Default::default()
+        }),
+        vec![as_read(&blocked)]
+    );
+    assert_eq!(
+        list(protocol::Filter {
+            account_id: enabled.account_id.clone(),
+            ..Default::default()
+        }),
+        vec![as_read(&enabled)]
+    );
+    assert_eq!(
+        list(protocol::Filter {
+            customer_id: enabled.customer_id.clone(),
+            ..Default::default()
+        })
+        .len(),
+        2
+    );
+    assert!(list(protocol::Filter {
+        account_id: random_id(),
+        ..Default::default()
+    })
+    .is_empty());
+}
+
+// Pages never repeat or skip a card and walking them twice gives the same sequence.
+pub(crate) fn list_pages_in_a_stable_order<R: Repository>(repository: &R) {
+    let account_id = random_id();
+    let mut saved = HashSet::new();
+    for _ in 0..5 {
+        let card = protocol::Card {
+            account_id: account_id.clone(),
+            ..a_card()
+        };
+        repository.save(&card).unwrap();
+        saved.insert(card.id);
+    }
+    let walk = || {
+        let mut pages = vec![];
+        let mut filter = protocol::Filter {
+            account_id: account_id.clone(),
+            limit: 2,
+            ..Default::default()
+        };
+        loop {
+            let page = repository.list(&filter).unwrap();
+            pages.push(
+                page.items
+                    .iter()
+                    .map(|card| card.id.clone())
+                    .collect::<Vec<_>>(),
+            );
+            if page.next_token.is_empty() {
+                return pages;
+            }
+            filter.next_token = page.next_token;
+        }
+    };
+
+    let pages = walk();
+
+    let sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+    assert_eq!(sizes, vec![2, 2, 1]);
+    let listed: Vec<String> = pages.concat();
+    assert_eq!(listed.iter().cloned().collect::<HashSet<_>>(), saved);
+    assert_eq!(listed.len(), saved.len());
+    assert_eq!(walk(), pages);
+}
+
+pub(crate) fn list_rejects_invalid_token<R: Repository>(repository: &R) {
+    let act = repository.list(&protocol::Filter {
+        limit: 20,
+        next_token: String::from("not a token"),
+        ..Default::default()
+    });
+
+    assert_eq!(
+        act,
+        Err(card::Error::Invalid(vec![protocol::ValidationError::new(
+            String::from("next_token"),
+            String::from("not a token"),
+            protocol::Code::PatternMismatch,
+        )]))
+    );
+}
+
+fn a_record(expires_at: i64) -> idempotency::Record {
+    idempotency::Record {
+        key: random_id(),
+        fingerprint: String::from("fingerprint"),
+        card_id: String::new(),
+        expires_at,
+    }
+}
+
+pub(crate) fn claim_and_complete_key<S: Store>(store: &S) {
+    let record = a_record(1_000);
+    let card_id = random_id();
+
+    assert_eq!(store.claim(&record, 100), Ok(None));
+    assert_eq!(
+        store.claim(
+            &idempotency::Record {
+                fingerprint: String::from("another fingerprint"),
+                ..record.clone()
+            },
+            200
+        ),
+        Ok(Some(record.clone()))
+    );
+    assert_eq!(store.complete(&record.key, &card_id, 5_000), Ok(()));
+    assert_eq!(
+        store.claim(&record, 3_000),
+        Ok(Some(idempotency::Record {
+            card_id,
+            expires_at: 5_000,
+            ..record.clone()
+        }))
+    );
+}
+
+pub(crate) fn claim_expired_or_released_key<S: Store>(store: &S) {
+    let record = a_record(1_000);
+    let renewed = idempotency::Record {
+        expires_at: 2_000,
+        ..record.clone()
+    };
+    store.claim(&record, 100).unwrap();
+
+    assert_eq!(store.claim(&renewed, 1_000), Ok(None));
+    assert_eq!(store.claim(&record, 1_500), Ok(Some(renewed.clone())));
+    assert_eq!(store.release(&record.key), Ok(()));
+    assert_eq!(store.claim(&record, 1_500), Ok(None));
+    assert_eq!(store.complete(&random_id(), &random_id(), 2_000), Ok(()));
+}
+
+fn a_program() -> protocol::Program {
+    protocol::Program {
+        id: random_id(),
+        name: String::from("Gold"),
+        bin: String::from("41234567"),
+        pan_length: 16,
+        kinds: vec![String::from("PLASTIC"), String::from("TEMPORARY")],
+        validity_months: 60,
+        temporary_ttl_hours: 72,
+        funding: String::from("CREDIT"),
+        active: true,
+    }
+}
+
+fn program_id(program: &protocol::Program) -> uuid::Uuid {
+    uuid::Uuid::parse_str(&program.id).unwrap()
+}
+
+pub(crate) fn save_and_find_program<P: program::Repository>(programs: &P) {
+    let program = a_program();
+
+    assert_eq!(programs.save(&program), Ok(()));
+
+    assert_eq!(
+        programs.find_by_id(program_id(&program)),
+        Ok(Some(program.clone()))
+    );
+    assert_eq!(programs.find_by_id(uuid::Uuid::new_v4()), Ok(None));
+    assert_eq!(programs.save(&program), Err(card::Error::Unavailable));
+}
+
+pub(crate) fn update_and_delete_program<P: program::Repository>(programs: &P) {
+    let program = a_program();
+    let inactive = protocol::Program {
+        kinds: vec![String::from("RECURRING")],
+        active: false,
+        ..program.clone()
+    };
+    assert_eq!(programs.update(&program), Err(card::Error::NotFound));
+    programs.save(&program).unwrap();
+
+    assert_eq!(programs.update(&inactive), Ok(()));
+    assert_eq!(
+        programs.find_by_id(program_id(&program)),
+        Ok(Some(inactive))
+    );
+    assert_eq!(programs.delete(program_id(&program)), Ok(()));
+    assert_eq!(programs.find_by_id(program_id(&program)), Ok(None));
+    assert_eq!(
+        programs.delete(program_id(&program)),
+        Err(card::Error::NotFound)
+    );
+}
+
+pub(crate) fn list_programs<P: program::Repository>(programs: &P) {
+    let saved = vec![a_program(), a_program()];
+    for program in &saved {
+        programs.save(program).unwrap();
+    }
+
+    let act = programs.list().unwrap();
+
+    let ids: Vec<&String> = act.iter().map(|program| &program.id).collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(ids, sorted);
+    for program in &saved {
+        assert!(act.contains(program));
+    }
+}
+
+pub(crate) fn acquire_lease<L: Lease>(leases: &L) {
+    let name = random_id();
+
+    assert_eq!(leases.acquire(&name, "first", 1_000, 1_100), Ok(true));
+    assert_eq!(leases.acquire(&name, "second", 1_050, 1_150), Ok(false));
+    // the holder renews before it expires
+    assert_eq!(leases.acquire(&name, "first", 1_090, 1_200), Ok(true));
+    assert_eq!(leases.acquire(&name, "second", 1_150, 1_250), Ok(false));
+    // anyone takes it once expired
+    assert_eq!(leases.acquire(&name, "second", 1_200, 1_300), Ok(true));
+    assert_eq!(leases.acquire(&name, "first", 1_250, 1_350), Ok(false));
+}
@@ -0,0 +1,1023 @@
+use crate::domain::{card, expiry, idempotency, program};
+use crate::protocol;
+use crate::repository::pan_hash;
+use crate::settings;
+use rusoto_core::credential::DefaultCredentialsProvider;
+#[cfg(test)]
+use rusoto_core::credential::StaticProvider;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{Client, HttpClient, Region};
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+use tokio::runtime::{Builder, Runtime};
+
+#[cfg(test)]
+static TABLE: &str = "Cards";
+#[cfg(test)]
+static LOCAL_ENDPOINT: &str = "http://localhost:4566";
+#[cfg(test)]
+static LOCAL_REGION: &str = "us-east-1";
+static TARGET_PREFIX: &str = "DynamoDB_20120810";
+static CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+// Attributes with a global secondary index named `<attribute>-index`, in lookup preference.
+static INDEXED: [&str; 4] = ["AccountID", "CustomerID", "ProgramID", "OrgID"];
+// Prefix of the items locking a PAN hash to the card that holds it. DynamoDB has no unique
+// index, so a card is written together with its lock in one transaction.
+static PAN_LOCK_PREFIX: &str = "PAN#";
+// Prefix of the idempotency keys of create requests, expired by the table TTL on ExpiresAt.
+static IDEMPOTENCY_KEY_PREFIX: &str = "KEY#";
+// Prefix of the card programs, kept in the same table and out of the card indexes.
+static PROGRAM_PREFIX: &str = "PROGRAM#";
+// Prefix of the leases of background jobs, held by one replica at a time and expired by the
+// table TTL on ExpiresAt like the idempotency keys.
+static LEASE_PREFIX: &str = "LEASE#";
+static CONDITION_FAILED: &str = "ConditionalCheckFailed";
+
+pub(crate) struct Repository {
+    client: Client,
+    region: Region,
+    table: String,
+    runtime: Runtime,
+}
+
+impl Repository {
+    pub(crate) fn new(client: Client, region: Region, table: &str) -> Repository {
+        // The domain traits are synchronous, so requests are driven by a runtime owned by the
+        // repository instead of the actix one.
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .expect("Failed to build DynamoDB runtime");
+
+        Repository {
+            client,
+            region,
+            table: String::from(table),
+            runtime,
+        }
+    }
+
+    // Credentials come from the usual AWS chain: environment, profile or instance role.
+    pub(crate) fn connect(settings: &settings::Dynamo) -> Repository {
+        let dispatcher = HttpClient::new().expect("Failed to create DynamoDB dispatcher");
+        let credentials =
+            DefaultCredentialsProvider::new().expect("Failed to create AWS credentials provider");
+        let region = match settings.endpoint.is_empty() {
+            true => Region::from_str(&settings.region).expect("Invalid AWS region"),
+            false => Region::Custom {
+                name: settings.region.clone(),
+                endpoint: settings.endpoint.clone(),
+            },
+        };
+
+        Repository::new(
+            Client::new_with(credentials, dispatcher),
+            region,
+            &settings.table,
+        )
+    }
+
+    #[cfg(test)]
+    pub(crate) fn local() -> Repository {
+        let dispatcher = HttpClient::new().expect("Failed to create DynamoDB dispatcher");
+        let credentials = StaticProvider::new_minimal(String::from("local"), String::from("local"));
+        let region = Region::Custom {
+            name: String::from(LOCAL_REGION),
+            endpoint: String::from(LOCAL_ENDPOINT),
+        };
+
+        Repository::new(Client::new_with(credentials, dispatcher), region, TABLE)
+    }
+
+    fn call(&self, operation: &str, payload: Value) -> Result<Value, card::Error> {
+        self.dispatch(operation, payload)?
+            .map_err(|_| card::Error::Unavailable)
+    }
+
+    // Sends the request, returning the body of the error when DynamoDB rejects it so callers can
+    // tell a failed condition from an outage.
+    fn dispatch(
+        &self,
+        operation: &str,
+        payload: Value,
+    ) -> Result<Result<Value, Value>, card::Error> {
+        let mut request = SignedRequest::new("POST", "dynamodb", &self.region, "/");
+        request.set_content_type(String::from(CONTENT_TYPE));
+        request.add_header("x-amz-target", &format!("{}.{}", TARGET_PREFIX, operation));
+        request.set_payload(Some(payload.to_string()));
+
+        self.runtime.block_on(async {
+            let mut response = self
+                .client
+                .sign_and_dispatch(request)
+                .await
+                .map_err(|_| card::Error::Unavailable)?;
+            let response = response
+                .buffer()
+                .await
+                .map_err(|_| card::Error::Unavailable)?;
+
+            let body: Value =
+                serde_json::from_slice(&response.body).map_err(|_| card::Error::Unavailable)?;
+            match (
+                response.status.is_success(),
+                response.status.is_client_error(),
+            ) {
+                (true, _) => Ok(Ok(body)),
+                (false, true) => Ok(Err(body)),
+                (false, false) => Err(card::Error::Unavailable),
+            }
+        })
+    }
+}
+
+impl card::Repository for Repository {
+    fn save(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        let pan_hash = pan_hash(card)?;
+        let put = json!({
+            "TableName": self.table,
+            "Item": to_item(card),
+            "ConditionExpression": "attribute_not_exists(ID)",
+        });
+        let payload = json!({
+            "TransactItems": [
+                { "Put": put },
+                { "Put": {
+                    "TableName": self.table,
+                    "Item": { "ID": { "S": format!("{}{}", PAN_LOCK_PREFIX, pan_hash) } },
+                    "ConditionExpression": "attribute_not_exists(ID)",
+                } },
+            ],
+        });
+
+        match self.dispatch("TransactWriteItems", payload)? {
+            Ok(_) => Ok(()),
+            Err(rejection) => {
+                let reason = |n: usize| rejection["CancellationReasons"][n]["Code"].as_str();
+                match (reason(0), reason(1)) {
+                    (Some(card), _) if card == CONDITION_FAILED => Err(card::Error::DuplicateId),
+                    (Some(card), Some(lock))
+                        if card != CONDITION_FAILED && lock == CONDITION_FAILED =>
+                    {
+                        Err(card::Error::DuplicatePan)
+                    }
+                    _ => Err(card::Error::Unavailable),
+                }
+            }
+        }
+    }
+
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Key": { "ID": { "S": id.to_string() } },
+            "ConsistentRead": true,
+        });
+
+        match self.call("GetItem", payload)?.get("Item") {
+            Some(item) => from_item(item).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error> {
+        let (operation, payload) = list_request(&self.table, filter)?;
+        let response = self.call(operation, payload)?;
+
+        let items = match response["Items"].as_array() {
+            Some(items) => items.iter().map(from_item).collect::<Result<_, _>>()?,
+            None => vec![],
+        };
+        let next_token = match response.get("LastEvaluatedKey") {
+            Some(key) => base64::encode_config(key.to_string(), base64::URL_SAFE_NO_PAD),
+            None => String::new(),
+        };
+
+        Ok(protocol::Page::new(items, next_token))
+    }
+
+    fn update(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        pan_hash(card)?;
+        let previous = card
+            .version
+            .checked_sub(1)
+            .ok_or(card::Error::VersionMismatch)?;
+        // items written before versioning have no Version and count as version 0
+        let condition = match previous {
+            0 => {
+                "attribute_exists(ID) AND (attribute_not_exists(#Version) OR #Version = :Previous)"
+            }
+            _ => "#Version = :Previous",
+        };
+        let payload = json!({
+            "TableName": self.table,
+            "Item": to_item(card),
+            "ConditionExpression": condition,
+            "ExpressionAttributeNames": { "#Version": "Version" },
+            "ExpressionAttributeValues": { ":Previous": { "N": previous.to_string() } },
+        });
+
+        match self.dispatch("PutItem", payload)? {
+            Ok(_) => Ok(()),
+            Err(rejection) if condition_failed(&rejection) => {
+                let id = uuid::Uuid::parse_str(&card.id).map_err(|_| card::Error::NotFound)?;
+                match self.find_by_id(id)? {
+                    Some(_) => Err(card::Error::VersionMismatch),
+                    None => Err(card::Error::NotFound),
+                }
+            }
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+
+    fn exists_pan(&self, pan: &str) -> Result<bool, card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "IndexName": "PANHash-index",
+            "KeyConditionExpression": "#PANHash = :PANHash",
+            "ExpressionAttributeNames": { "#PANHash": "PANHash" },
+            "ExpressionAttributeValues": { ":PANHash": { "S": pan } },
+            "Select": "COUNT",
+            "Limit": 1,
+        });
+
+        match self.call("Query", payload)?["Count"].as_u64() {
+            Some(count) => Ok(count > 0),
+            None => Err(card::Error::Unavailable),
+        }
+    }
+}
+
+fn condition_failed(rejection: &Value) -> bool {
+    matches!(rejection["__type"].as_str(), Some(kind) if kind.ends_with(&format!("{}Exception", CONDITION_FAILED)))
+}
+
+fn key_id(key: &str) -> Value {
+    json!({ "ID": { "S": format!("{}{}", IDEMPOTENCY_KEY_PREFIX, key) } })
+}
+
+// The table TTL deletes expired keys only eventually, so the condition checks the expiry too.
+impl idempotency::Store for Repository {
+    fn claim(
+        &self,
+        record: &idempotency::Record,
+        now: i64,
+    ) -> Result<Option<idempotency::Record>, card::Error> {
+        let mut item = key_id(&record.key);
+        item["Fingerprint"] = json!({ "S": record.fingerprint });
+        item["CardID"] = json!({ "S": record.card_id });
+        item["ExpiresAt"] = json!({ "N": record.expires_at.to_string() });
+        let payload = json!({
+            "TableName": self.table,
+            "Item": item,
+            "ConditionExpression": "attribute_not_exists(ID) OR ExpiresAt <= :Now",
+            "ExpressionAttributeValues": { ":Now": { "N": now.to_string() } },
+        });
+
+        match self.dispatch("PutItem", payload)? {
+            Ok(_) => Ok(None),
+            Err(rejection) if condition_failed(&rejection) => {
+                let payload = json!({
+                    "TableName": self.table,
+                    "Key": key_id(&record.key),
+                    "ConsistentRead": true,
+                });
+                // a key deleted since the write failed is gone, the client retries
+                let held = &self.call("GetItem", payload)?["Item"];
+                let attribute = |name: &str| held[name]["S"].as_str().map(String::from);
+                match (
+                    attribute("Fingerprint"),
+                    attribute("CardID"),
+                    held["ExpiresAt"]["N"].as_str(),
+                ) {
+                    (Some(fingerprint), Some(card_id), Some(expires_at)) => {
+                        Ok(Some(idempotency::Record {
+                            key: record.key.clone(),
+                            fingerprint,
+                            card_id,
+                            expires_at: expires_at.parse().map_err(|_| card::Error::Unavailable)?,
+                        }))
+                    }
+                    _ => Err(card::Error::Unavailable),
+                }
+            }
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+
+    fn complete(&self, key: &str, card_id: &str, expires_at: i64) -> Result<(), card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Key": key_id(key),
+            "UpdateExpression": "SET CardID = :CardID, ExpiresAt = :ExpiresAt",
+            "ConditionExpression": "attribute_exists(ID)",
+            "ExpressionAttributeValues": {
+                ":CardID": { "S": card_id },
+                ":ExpiresAt": { "N": expires_at.to_string() },
+            },
+        });
+
+        // a released or expired key is not written back
+        match self.dispatch("UpdateItem", payload)? {
+            Ok(_) => Ok(()),
+            Err(rejection) if condition_failed(&rejection) => Ok(()),
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+
+    fn release(&self, key: &str) -> Result<(), card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Key": key_id(key),
+        });
+
+        self.call("DeleteItem", payload).map(|_| ())
+    }
+}
+
+fn program_key(id: uuid::Uuid) -> Value {
+    json!({ "ID": { "S": format!("{}{}", PROGRAM_PREFIX, id) } })
+}
+
+fn program_to_item(program: &protocol::Program) -> Value {
+    let kinds: Vec<Value> = program
+        .kinds
+        .iter()
+        .map(|kind| json!({ "S": kind }))
+        .collect();
+
+    json!({
+        "ID": { "S": format!("{}{}", PROGRAM_PREFIX, program.id) },
+        "Name": { "S": program.name },
+        "BIN": { "S": program.bin },
+        "PANLength": { "N": program.pan_length.to_string() },
+        "Kinds": { "L": kinds },
+        "ValidityMonths": { "N": program.validity_months.to_string() },
+        "TemporaryTTLHours": { "N": program.temporary_ttl_hours.to_string() },
+        "Funding": { "S": program.funding },
+        "Active": { "BOOL": program.active },
+    })
+}
+
+fn program_from_item(item: &Value) -> Result<protocol::Program, card::Error> {
+    let attribute = |name: &str| match item[name]["S"].as_str() {
+        Some(value) => Ok(String::from(value)),
+        None => Err(card::Error::Unavailable),
+    };
+    let number = |name: &str| match item[name]["N"].as_str().map(str::parse::<u64>) {
+        Some(Ok(value)) => Ok(value),
+        _ => Err(card::Error::Unavailable),
+    };
+    let kinds = match item["Kinds"]["L"].as_array() {
+        Some(kinds) => kinds
+            .iter()
+            .map(|kind| kind["S"].as_str().map(String::from))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(card::Error::Unavailable)?,
+        None => return Err(card::Error::Unavailable),
+    };
+
+    Ok(protocol::Program {
+        id: String::from(
+            attribute("ID")?
+                .strip_prefix(PROGRAM_PREFIX)
+                .ok_or(card::Error::Unavailable)?,
+        ),
+        name: attribute("Name")?,
+        bin: attribute("BIN")?,
+        pan_length: number("PANLength")? as usize,
+        kinds,
+        validity_months: number("ValidityMonths")? as u32,
+        // programs saved before temporary cards had a time to live have none
+        temporary_ttl_hours: match item["TemporaryTTLHours"].is_null() {
+            true => 0,
+            false => number("TemporaryTTLHours")? as u32,
+        },
+        funding: attribute("Funding")?,
+        active: item["Active"]["BOOL"]
+            .as_bool()
+            .ok_or(card::Error::Unavailable)?,
+    })
+}
+
+impl expiry::Lease for Repository {
+    fn acquire(&self, name: &str, holder: &str, now: i64, until: i64) -> Result<bool, card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Item": {
+                "ID": { "S": format!("{}{}", LEASE_PREFIX, name) },
+                "Holder": { "S": holder },
+                "ExpiresAt": { "N": until.to_string() },
+            },
+            "ConditionExpression":
+                "attribute_not_exists(ID) OR Holder = :Holder OR ExpiresAt <= :Now",
+            "ExpressionAttributeValues": {
+                ":Holder": { "S": holder },
+                ":Now": { "N": now.to_string() },
+            },
+        });
+
+        match self.dispatch("PutItem", payload)? {
+            Ok(_) => Ok(true),
+            Err(rejection) if condition_failed(&rejection) => Ok(false),
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+}
+
+impl program::Repository for Repository {
+    fn save(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Item": program_to_item(program),
+            "ConditionExpression": "attribute_not_exists(ID)",
+        });
+
+        self.call("PutItem", payload).map(|_| ())
+    }
+
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Program>, card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Key": program_key(id),
+            "ConsistentRead": true,
+        });
+
+        match self.call("GetItem", payload)?.get("Item") {
+            Some(item) => Ok(Some(program_from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Programs are few, so they are scanned out of the table a page at a time.
+    fn list(&self) -> Result<Vec<protocol::Program>, card::Error> {
+        let mut programs = vec![];
+        let mut payload = json!({
+            "TableName": self.table,
+            "FilterExpression": "begins_with(ID, :Prefix)",
+            "ExpressionAttributeValues": { ":Prefix": { "S": PROGRAM_PREFIX } },
+            "ConsistentRead": true,
+        });
+        loop {
+            let page = self.call("Scan", payload.clone())?;
+            for item in page["Items"].as_array().ok_or(card::Error::Unavailable)? {
+                programs.push(program_from_item(item)?);
+            }
+            match page.get("LastEvaluatedKey") {
+                Some(key) => payload["ExclusiveStartKey"] = key.clone(),
+                None => break,
+            }
+        }
+        programs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(programs)
+    }
+
+    fn update(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Item": program_to_item(program),
+            "ConditionExpression": "attribute_exists(ID)",
+        });
+
+        match self.dispatch("PutItem", payload)? {
+            Ok(_) => Ok(()),
+            Err(rejection) if condition_failed(&rejection) => Err(card::Error::NotFound),
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+
+    fn delete(&self, id: uuid::Uuid) -> Result<(), card::Error> {
+        let payload = json!({
+            "TableName": self.table,
+            "Key": program_key(id),
+            "ConditionExpression": "attribute_exists(ID)",
+        });
+
+        match self.dispatch("DeleteItem", payload)? {
+            Ok(_) => Ok(()),
+            Err(rejection) if condition_failed(&rejection) => Err(card::Error::NotFound),
+            Err(_) => Err(card::Error::Unavailable),
+        }
+    }
+}
+
+// Queries the index of the most selective id present in the filter, narrowing the remaining
+// fields with a filter expression, and falls back to a scan when no id is given.
+fn list_request(
+    table: &str,
+    filter: &protocol::Filter,
+) -> Result<(&'static str, Value), card::Error> {
+    let attributes = [
+        ("AccountID", &filter.account_id),
+        ("CustomerID", &filter.customer_id),
+        ("ProgramID", &filter.program_id),
+        ("OrgID", &filter.org_id),
+        ("Kind", &filter.kind),
+        ("Status", &filter.status),
+    ];
+    let mut payload = json!({ "TableName": table, "Limit": filter.limit });
+    let mut names = Map::new();
+    let mut values = Map::new();
+    let mut conditions = vec![];
+    let mut index = None;
+
+    for (attribute, value) in attributes.iter().filter(|(_, value)| !value.is_empty()) {
+        names.insert(format!("#{}", attribute), json!(attribute));
+        values.insert(format!(":{}", attribute), json!({ "S": value }));
+        let condition = format!("#{0} = :{0}", attribute);
+
+        match index.is_none() && INDEXED.contains(attribute) {
+            true => {
+                payload["IndexName"] = json!(format!("{}-index", attribute));
+                payload["KeyConditionExpression"] = json!(condition);
+                index = Some(attribute);
+            }
+            false => conditions.push(condition),
+        }
+    }
+
+    if !conditions.is_empty() {
+        payload["FilterExpression"] = json!(conditions.join(" AND "));
+    }
+    if !names.is_empty() {
+        payload["ExpressionAttributeNames"] = Value::Object(names);
+        payload["ExpressionAttributeValues"] = Value::Object(values);
+    }
+    if !filter.next_token.is_empty() {
+        let invalid_token = || {
+            card::Error::Invalid(vec![protocol::ValidationError::new(
+                String::from("next_token"),
+                filter.next_token.clone(),
+                protocol::Code::PatternMismatch,
+            )])
+        };
+        let key = base64::decode_config(&filter.next_token, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| invalid_token())?;
+        payload["ExclusiveStartKey"] = serde_json::from_slice(&key).map_err(|_| invalid_token())?;
+    }
+
+    match index {
+        Some(_) => Ok(("Query", payload)),
+        None => {
+            // PAN locks are only keyed by ID, so they stay out of the indexes but not of a scan
+            conditions.push(String::from("attribute_exists(CustomerID)"));
+            payload["FilterExpression"] = json!(conditions.join(" AND "));
+            Ok(("Scan", payload))
+        }
+    }
+}
+
+fn to_item(card: &protocol::Card) -> Value {
+    json!({
+        "ID": { "S": card.id },
+        "CustomerID": { "S": card.customer_id },
+        "OrgID": { "S": card.org_id },
+        "ProgramID": { "S": card.program_id },
+        "AccountID": { "S": card.account_id },
+        "PrintedName": { "S": card.printed_name },
+        "Password": { "S": card.password },
+        "PasswordAttempts": { "N": card.password_attempts.to_string() },
+        "ExpirationDate": { "S": card.expiration_date },
+        "IssuingDate": { "S": card.issuing_date },
+        "PAN": { "S": card.pan },
+        "PANHash": { "S": card.pan_hash },
+        "Kind": { "S": card.kind },
+        "Status": { "S": card.status },
+        "StatusReason": { "S": card.status_reason },
+        "StatusUpdatedAt": { "S": card.status_updated_at },
+        "Version": { "N": card.version.to_string() },
+        "MerchantID": { "S": card.merchant_id },
+        "MCC": { "S": card.mcc },
+    })
+}
+
+fn from_item(item: &Value) -> Result<protocol::Card, card::Error> {
+    let attribute = |name: &str| match item[name]["S"].as_str() {
+        Some(value) => Ok(String::from(value)),
+        None => Err(card::Error::Unavailable),
+    };
+    // Attributes added after the table went live, absent from older items.
+    let optional_attribute =
+        |name: &str| String::from(item[name]["S"].as_str().unwrap_or_default());
+
+    Ok(protocol::Card {
+        id: attribute("ID")?,
+        customer_id: attribute("CustomerID")?,
+        org_id: attribute("OrgID")?,
+        program_id: attribute("ProgramID")?,
+        account_id: attribute("AccountID")?,
+        printed_name: attribute("PrintedName")?,
+        password: attribute("Password")?,
+        password_attempts: item["PasswordAttempts"]["N"].as_str().map_or(Ok(0), |n| {
+            n.parse::<u32>().map_err(|_| card::Error::Unavailable)
+        })?,
+        expiration_date: attribute("ExpirationDate")?,
+        issuing_date: attribute("IssuingDate")?,
+        pan: attribute("PAN")?,
+        // only written, as the lookup key of `exists_pan`
+        pan_hash: String::new(),
+        kind: attribute("Kind")?,
+        status: attribute("Status")?,
+        status_reason: optional_attribute("StatusReason"),
+        status_updated_at: optional_attribute("StatusUpdatedAt"),
+        // never stored, derived from the PAN and expiry when needed
+        cvv: String::new(),
+        version: item["Version"]["N"].as_str().map_or(Ok(0), |n| {
+            n.parse::<u64>().map_err(|_| card::Error::Unavailable)
+        })?,
+        merchant_id: optional_attribute("MerchantID"),
+        mcc: optional_attribute("MCC"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::Repository as _;
+    use crate::repository::conformance::{as_read, conformance_tests};
+
+    conformance_tests!(
+        Repository::local(),
+        ignore = "requires localstack, see `make init`"
+    );
+
+    #[test]
+    fn to_item_maps_every_field() {
+        let act = to_item(&a_card("29ce6541-302b-405e-9dfe-549934d4e4b2"));
+
+        assert_eq!(act["ID"]["S"], "29ce6541-302b-405e-9dfe-549934d4e4b2");
+        assert_eq!(
+            act["CustomerID"]["S"],
+            "df101f6e-6c3a-484b-94f9-16d134bde2e1"
+        );
+        assert_eq!(act["OrgID"]["S"], "876ce143-6fcb-4c17-aaf1-f02c1d3654ce");
+        assert_eq!(
+            act["ProgramID"]["S"],
+            "00c9e86a-8d55-4a95-884b-4a6faeb9289e"
+        );
+        assert_eq!(
+            act["AccountID"]["S"],
+            "a2d46c49-262e-431d-8f1a-ff5b18b44982"
+        );
+        assert_eq!(act["PrintedName"]["S"], "BAKER MAYFIELD");
+        assert_eq!(act["Password"]["S"], "078112");
+        assert_eq!(act["ExpirationDate"]["S"], "0726");
+        assert_eq!(act["IssuingDate"]["S"], "2021-02-16 19:20:00");
+        assert_eq!(act["PAN"]["S"], "5214330278318136");
+        assert_eq!(act["Kind"]["S"], "PLASTIC");
+        assert_eq!(act["Status"]["S"], "ENABLED");
+        assert_eq!(act["StatusReason"]["S"], "ISSUER_REQUEST");
+        assert_eq!(act["StatusUpdatedAt"]["S"], "2021-02-17 10:00:00");
+        assert!(act.get("CVV").is_none());
+        assert_eq!(act["PasswordAttempts"]["N"], "0");
+        assert_eq!(act["PANHash"]["S"], "e3b0c44298fc1c14");
+        assert_eq!(act["Version"]["N"], "1");
+        assert_eq!(act["MerchantID"]["S"], "000000000012345");
+        assert_eq!(act["MCC"]["S"], "4899");
+        assert_eq!(act.as_object().unwrap().len(), 19);
+    }
+
+    #[test]
+    fn from_item_round_trip() {
+        let card = a_card("29ce6541-302b-405e-9dfe-549934d4e4b2");
+
+        let act = from_item(&to_item(&card));
+
+        assert_eq!(act, Ok(as_read(&card)));
+    }
+
+    #[test]
+    fn program_from_item_round_trip() {
+        let program = protocol::Program {
+            id: String::from("35d86d4b-55e6-49c6-bc83-0b52d68b75f5"),
+            name: String::from("Gold"),
+            bin: String::from("41234567"),
+            pan_length: 16,
+            kinds: vec![String::from("PLASTIC"), String::from("TEMPORARY")],
+            validity_months: 60,
+            temporary_ttl_hours: 72,
+            funding: String::from("CREDIT"),
+            active: false,
+        };
+
+        let item = program_to_item(&program);
+
+        assert_eq!(
+            item["ID"]["S"],
+            "PROGRAM#35d86d4b-55e6-49c6-bc83-0b52d68b75f5"
+        );
+        assert_eq!(program_from_item(&item), Ok(program));
+    }
+
+    #[test]
+    fn program_from_item_without_temporary_ttl() {
+        let mut item = program_to_item(&protocol::Program {
+            id: String::from("35d86d4b-55e6-49c6-bc83-0b52d68b75f5"),
+            temporary_ttl_hours: 72,
+            ..Default::default()
+        });
+        item.as_object_mut().unwrap().remove("TemporaryTTLHours");
+
+        assert_eq!(program_from_item(&item).unwrap().temporary_ttl_hours, 0);
+    }
+
+    #[test]
+    fn from_item_missing_attribute() {
+        let mut item = to_item(&a_card("29ce6541-302b-405e-9dfe-549934d4e4b2"));
+        item.as_object_mut().unwrap().remove("PAN");
+
+        let act = from_item(&item);
+
+        assert_eq!(act, Err(card::Error::Unavailable));
+    }
+
+    #[test]
+    fn list_request_by_index() {
+        let filter = protocol::Filter {
+            customer_id: String::from("df101f6e-6c3a-484b-94f9-16d134bde2e1"),
+            account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
+            status: String::from("BLOCKED"),
+            limit: 20,
+            ..Default::default()
+        };
+
+        let (operation, act) = list_request(TABLE, &filter).unwrap();
+
+        assert_eq!(operation, "Query");
+        assert_eq!(act["IndexName"], "AccountID-index");
+        assert_eq!(act["KeyConditionExpression"], "#AccountID = :AccountID");
+        assert_eq!(
+            act["FilterExpression"],
+            "#CustomerID = :CustomerID AND #Status = :Status"
+        );
+        assert_eq!(act["ExpressionAttributeNames"]["#Status"], "Status");
+        assert_eq!(act["ExpressionAttributeValues"][":Status"]["S"], "BLOCKED");
+        assert_eq!(act["Limit"], 20);
+        assert!(act.get("ExclusiveStartKey").is_none());
+    }
+
+    #[test]
+    fn list_request_without_index() {
+        let filter = protocol::Filter {
+            kind: String::from("PLASTIC"),
+            limit: 20,
+            next_token: base64::encode_config(r#"{"ID":{"S":"1"}}"#, base64::URL_SAFE_NO_PAD),
+            ..Default::default()
+        };
+
+        let (operation, act) = list_request(TABLE, &filter).unwrap();
+
+        assert_eq!(operation, "Scan");
+        assert!(act.get("IndexName").is_none());
+        assert_eq!(
+            act["FilterExpression"],
+            "#Kind = :Kind AND attribute_exists(CustomerID)"
+        );
+        assert_eq!(act["ExclusiveStartKey"], json!({ "ID": { "S": "1" } }));
+    }
+
+    #[test]
+    fn list_request_invalid_token() {
+        let filter = protocol::Filter {
+            next_token: String::from("not a token"),
+            ..Default::default()
+        };
+
+        let act = list_request(TABLE, &filter);
+
+        assert_eq!(
+            act,
+            Err(card::Error::Invalid(vec![protocol::ValidationError::new(
+                String::from("next_token"),
+                String::from("not a token"),
+                protocol::Code::PatternMismatch,
+            )]))
+        );
+    }
+
+    #[test]
+    fn from_item_without_status_history() {
+        let card = a_card("29ce6541-302b-405e-9dfe-549934d4e4b2");
+        let mut item = to_item(&card);
+        item.as_object_mut().unwrap().remove("StatusReason");
+        item.as_object_mut().unwrap().remove("StatusUpdatedAt");
+        item.as_object_mut().unwrap().remove("PasswordAttempts");
+        item.as_object_mut().unwrap().remove("Version");
+        item.as_object_mut().unwrap().remove("MerchantID");
+        item.as_object_mut().unwrap().remove("MCC");
+
+        let act = from_item(&item);
+
+        assert_eq!(
+            act,
+            Ok(protocol::Card {
+                status_reason: String::new(),
+                status_updated_at: String::new(),
+                version: 0,
+                merchant_id: String::new(),
+                mcc: String::new(),
+                ..as_read(&card)
+            })
+        );
+    }
+
+    #[test]
+    fn save_unreachable_endpoint() {
+        let dispatcher = HttpClient::new().unwrap();
+        let credentials = StaticProvider::new_minimal(String::from("local"), String::from("local"));
+        let region = Region::Custom {
+            name: String::from(LOCAL_REGION),
+            endpoint: String::from("http://127.0.0.1:1"),
+        };
+        let repository = Repository::new(Client::new_with(credentials, dispatcher), region, TABLE);
+
+        let act = repository.save(&a_new_card());
+
+        assert!(act.is_err());
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn save_and_read_back() {
+        let repository = Repository::local();
+        let card = a_new_card();
+
+        let act = repository.save(&card);
+
+        assert!(act.is_ok());
+        let id = uuid::Uuid::parse_str(&card.id).unwrap();
+        assert_eq!(repository.find_by_id(id), Ok(Some(as_read(&card))));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn find_unknown_id() {
+        let repository = Repository::local();
+
+        let act = repository.find_by_id(uuid::Uuid::new_v4());
+
+        assert_eq!(act, Ok(None));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn save_duplicated_id() {
+        let repository = Repository::local();
+        let card = a_new_card();
+        assert!(repository.save(&card).is_ok());
+
+        let act = repository.save(&card);
+
+        assert_eq!(act, Err(card::Error::DuplicateId));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn save_unknown_table() {
+        let mut repository = Repository::local();
+        repository.table = String::from("UnknownCards");
+
+        let act = repository.save(&a_new_card());
+
+        assert!(act.is_err());
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn update_and_read_back() {
+        let repository = Repository::local();
+        let mut card = a_new_card();
+        assert!(repository.save(&card).is_ok());
+        card.printed_name = String::from("RICARDO MEDEIROS");
+        card.version = 2;
+
+        let act = repository.update(&card);
+
+        assert!(act.is_ok());
+        let id = uuid::Uuid::parse_str(&card.id).unwrap();
+        assert_eq!(repository.find_by_id(id), Ok(Some(as_read(&card))));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn update_unknown_id() {
+        let repository = Repository::local();
+
+        let act = repository.update(&a_new_card());
+
+        assert!(act.is_err());
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn exists_pan() {
+        let repository = Repository::local();
+        let card = a_new_card();
+        assert_eq!(repository.exists_pan(&card.pan_hash), Ok(false));
+        assert!(repository.save(&card).is_ok());
+
+        let act = repository.exists_pan(&card.pan_hash);
+
+        assert_eq!(act, Ok(true));
+        assert_eq!(repository.exists_pan(&card.pan), Ok(false));
+    }
+
+    #[test]
+    fn refuse_a_card_without_pan_hash() {
+        let repository = Repository::local();
+        let card = protocol::Card {
+            pan_hash: String::new(),
+            ..a_new_card()
+        };
+
+        assert_eq!(repository.save(&card), Err(card::Error::Unavailable));
+        assert_eq!(repository.update(&card), Err(card::Error::Unavailable));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn list_paginated() {
+        let repository = Repository::local();
+        let account_id = uuid::Uuid::new_v4().to_string();
+        for _ in 0..3 {
+            let mut card = a_new_card();
+            card.account_id = account_id.clone();
+            assert!(repository.save(&card).is_ok());
+        }
+        let mut filter = protocol::Filter {
+            account_id,
+            limit: 2,
+            ..Default::default()
+        };
+
+        let first = repository.list(&filter).unwrap();
+        filter.next_token = first.next_token.clone();
+        let second = repository.list(&filter).unwrap();
+
+        assert_eq!(first.items.len(), 2);
+        assert!(!first.next_token.is_empty());
+        assert_eq!(second.items.len(), 1);
+        assert!(second.next_token.is_empty());
+        assert!(second.items.iter().all(|card| !first.items.contains(card)));
+    }
+
+    #[test]
+    #[ignore = "requires localstack, see `make init`"]
+    fn list_filtered() {
+        let repository = Repository::local();
+        let customer_id = uuid::Uuid::new_v4().to_string();
+        let mut blocked = a_new_card();
+        blocked.customer_id = customer_id.clone();
+        blocked.status = String::from("BLOCKED");
+        let mut enabled = a_new_card();
+        enabled.customer_id = customer_id.clone();
+        assert!(repository.save(&blocked).is_ok());
+        assert!(repository.save(&enabled).is_ok());
+        let filter = protocol::Filter {
+            customer_id,
+            status: String::from("BLOCKED"),
+            limit: 20,
+            ..Default::default()
+        };
+
+        let act = repository.list(&filter).unwrap();
+
+        assert_eq!(act.items, vec![as_read(&blocked)]);
+    }
+
+    // Card with a fresh id, PAN and PAN hash, as PANs already saved in the table are rejected.
+    fn a_new_card() -> protocol::Card {
+        protocol::Card {
+            pan: format!("{:016}", rand::random::<u64>() % 10_000_000_000_000_000),
+            pan_hash: uuid::Uuid::new_v4().to_string(),
+            ..a_card(&uuid::Uuid::new_v4().to_string())
+        }
+    }
+
+    fn a_card(id: &str) -> protocol::Card {
+        protocol::Card {
+            id: String::from(id),
+            customer_id: String::from("df101f6e-6c3a-484b-94f9-16d134bde2e1"),
+            org_id: String::from("876ce143-6fcb-4c17-aaf1-f02c1d3654ce"),
+            program_id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+            account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
+            printed_name: String::from("BAKER MAYFIELD"),
+            password: String::from("078112"),
+            password_attempts: 0,
+            expiration_date: String::from("0726"),
+            issuing_date: String::from("2021-02-16 19:20:00"),
+            pan: String::from("5214330278318136"),
+            pan_hash: String::from("e3b0c44298fc1c14"),
+            kind: String::from("PLASTIC"),
+            status: String::from("ENABLED"),
+            status_reason: String::from("ISSUER_REQUEST"),
+            status_updated_at: String::from("2021-02-17 10:00:00"),
+            cvv: String::from("945"),
+            merchant_id: String::from("000000000012345"),
+            mcc: String::from("4899"),
+            version: 1,
+        }
+    }
+}
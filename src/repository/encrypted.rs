@@ -0,0 +1,270 @@
+use crate::crypto::envelope::Cipher;
+use crate::domain::{card, luhn};
+use crate::protocol;
+use std::fmt;
+
+// Seals the sensitive fields of a card before they reach the wrapped repository and opens them
+// on the way back, so neither the domain nor the storage backends deal with encryption.
+pub(crate) struct Repository {
+    inner: Box<dyn card::Repository>,
+    cipher: Cipher,
+}
+
+impl Repository {
+    pub(crate) fn new(inner: Box<dyn card::Repository>, cipher: Cipher) -> Repository {
+        Repository { inner, cipher }
+    }
+
+    fn seal(&self, card: &protocol::Card) -> Result<protocol::Card, card::Error> {
+        Ok(protocol::Card {
+            password: unavailable(self.cipher.seal("password", &card.password))?,
+            expiration_date: unavailable(
+                self.cipher.seal("expiration_date", &card.expiration_date),
+            )?,
+            pan: unavailable(self.cipher.seal("pan", &card.pan))?,
+            pan_hash: unavailable(self.cipher.fingerprint(&card.pan))?,
+            // derived again on every read, so not even a sealed copy is kept
+            cvv: String::new(),
+            ..card.clone()
+        })
+    }
+
+    fn open(&self, card: protocol::Card) -> Result<protocol::Card, card::Error> {
+        Ok(protocol::Card {
+            password: unavailable(self.cipher.open("password", &card.password))?,
+            expiration_date: unavailable(
+                self.cipher.open("expiration_date", &card.expiration_date),
+            )?,
+            pan: unavailable(self.cipher.open("pan", &card.pan))?,
+            pan_hash: String::new(),
+            ..card
+        })
+    }
+}
+
+// A card that cannot be sealed or opened, such as one under a retired key, cannot be served.
+fn unavailable(result: Result<String, fmt::Error>) -> Result<String, card::Error> {
+    result.map_err(|_| card::Error::Unavailable)
+}
+
+impl card::Repository for Repository {
+    fn save(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        self.inner.save(&self.seal(card)?)
+    }
+
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, card::Error> {
+        match self.inner.find_by_id(id)? {
+            Some(card) => Ok(Some(self.open(card)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error> {
+        let page = self.inner.list(filter)?;
+        let items = page
+            .items
+            .into_iter()
+            .map(|card| self.open(card))
+            .collect::<Result<Vec<protocol::Card>, card::Error>>()?;
+
+        Ok(protocol::Page::new(items, page.next_token))
+    }
+
+    fn update(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        self.inner.update(&self.seal(card)?)
+    }
+
+    // A number that fails the Luhn check was never issued, so it is not looked up.
+    fn exists_pan(&self, pan: &str) -> Result<bool, card::Error> {
+        if !luhn::is_valid(pan) {
+            return Ok(false);
+        }
+        self.inner
+            .exists_pan(&unavailable(self.cipher.fingerprint(pan))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::key::Local;
+    use crate::domain::card::Repository as _;
+    use mockall::mock;
+    use mockall::predicate::eq;
+
+    mock! {
+            Repository {}
+            impl card::Repository for Repository {
+               fn save(&self, card: &protocol::Card) -> Result<(), card::Error>;
+               fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, card::Error>;
+               fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error>;
+               fn update(&self, card: &protocol::Card) -> Result<(), card::Error>;
+               fn exists_pan(&self, pan: &str) -> Result<bool, card::Error>;
+            }
+    }
+
+    const KEYS: &str = "1:000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f,\
+                        index:1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+    const ID: &str = "29ce6541-302b-405e-9dfe-549934d4e4b2";
+
+    fn a_cipher() -> Cipher {
+        Cipher::new(Box::new(Local::parse(KEYS).unwrap()))
+    }
+
+    fn is_sealed(card: &protocol::Card) -> bool {
+        let cipher = a_cipher();
+        let plain = a_card();
+
+        card.pan.starts_with("enc:1:")
+            && cipher.open("pan", &card.pan) == Ok(plain.pan.clone())
+            && cipher.open("password", &card.password) == Ok(plain.password.clone())
+            && cipher.open("expiration_date", &card.expiration_date)
+                == Ok(plain.expiration_date.clone())
+            && card.pan_hash == cipher.fingerprint(&plain.pan).unwrap()
+            && card.printed_name == plain.printed_name
+            && card.cvv.is_empty()
+            && !plain.cvv.is_empty()
+    }
+
+    #[test]
+    fn save_seals_sensitive_fields() {
+        let mut inner = MockRepository::new();
+        inner
+            .expect_save()
+            .withf(is_sealed)
+            .times(1)
+            .returning(|_| Ok(()));
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        assert_eq!(repository.save(&a_card()), Ok(()));
+    }
+
+    #[test]
+    fn update_seals_sensitive_fields() {
+        let mut inner = MockRepository::new();
+        inner
+            .expect_update()
+            .withf(is_sealed)
+            .times(1)
+            .returning(|_| Err(card::Error::Unavailable));
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        assert_eq!(repository.update(&a_card()), Err(card::Error::Unavailable));
+    }
+
+    #[test]
+    fn find_by_id_opens_sensitive_fields() {
+        let sealed = Repository::new(Box::new(MockRepository::new()), a_cipher())
+            .seal(&a_card())
+            .unwrap();
+        let mut inner = MockRepository::new();
+        inner
+            .expect_find_by_id()
+            .with(eq(uuid::Uuid::parse_str(ID).unwrap()))
+            .returning(move |_| Ok(Some(sealed.clone())));
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        let act = repository.find_by_id(uuid::Uuid::parse_str(ID).unwrap());
+
+        assert_eq!(act, Ok(Some(an_opened_card())));
+    }
+
+    #[test]
+    fn find_by_id_with_foreign_key() {
+        let other = Cipher::new(Box::new(
+            Local::parse(&KEYS.replace("1:00", "1:ff")).unwrap(),
+        ));
+        let sealed = Repository::new(Box::new(MockRepository::new()), other)
+            .seal(&a_card())
+            .unwrap();
+        let mut inner = MockRepository::new();
+        inner
+            .expect_find_by_id()
+            .returning(move |_| Ok(Some(sealed.clone())));
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        let act = repository.find_by_id(uuid::Uuid::parse_str(ID).unwrap());
+
+        assert_eq!(act, Err(card::Error::Unavailable));
+    }
+
+    #[test]
+    fn list_opens_every_item() {
+        let sealed = Repository::new(Box::new(MockRepository::new()), a_cipher())
+            .seal(&a_card())
+            .unwrap();
+        let mut inner = MockRepository::new();
+        inner.expect_list().returning(move |_| {
+            Ok(protocol::Page::new(
+                vec![sealed.clone(), sealed.clone()],
+                String::from("token"),
+            ))
+        });
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        let act = repository.list(&protocol::Filter::default());
+
+        assert_eq!(
+            act,
+            Ok(protocol::Page::new(
+                vec![an_opened_card(), an_opened_card()],
+                String::from("token")
+            ))
+        );
+    }
+
+    #[test]
+    fn exists_pan_by_fingerprint() {
+        let fingerprint = a_cipher().fingerprint("5214330278318136").unwrap();
+        let mut inner = MockRepository::new();
+        inner
+            .expect_exists_pan()
+            .with(eq(fingerprint))
+            .times(1)
+            .returning(|_| Ok(true));
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        assert_eq!(repository.exists_pan("5214330278318136"), Ok(true));
+    }
+
+    #[test]
+    fn exists_pan_skips_invalid_check_digit() {
+        let mut inner = MockRepository::new();
+        inner.expect_exists_pan().times(0);
+        let repository = Repository::new(Box::new(inner), a_cipher());
+
+        assert_eq!(repository.exists_pan("5214330278318137"), Ok(false));
+    }
+
+    fn an_opened_card() -> protocol::Card {
+        protocol::Card {
+            cvv: String::new(),
+            ..a_card()
+        }
+    }
+
+    fn a_card() -> protocol::Card {
+        protocol::Card {
+            id: ID.to_string(),
+            customer_id: "df101f6e-6c3a-484b-94f9-16d134bde2e1".to_string(),
+            org_id: "876ce143-6fcb-4c17-aaf1-f02c1d3654ce".to_string(),
+            program_id: "00c9e86a-8d55-4a95-884b-4a6faeb9289e".to_string(),
+            account_id: "a2d46c49-262e-431d-8f1a-ff5b18b44982".to_string(),
+            printed_name: "BAKER MAYFIELD".to_string(),
+            password: "078112".to_string(),
+            password_attempts: 0,
+            expiration_date: "0726".to_string(),
+            issuing_date: "2021-02-16 19:20:00".to_string(),
+            pan: "5214330278318136".to_string(),
+            pan_hash: "".to_string(),
+            kind: "PLASTIC".to_string(),
+            status: "ENABLED".to_string(),
+            status_reason: "".to_string(),
+            status_updated_at: "2021-02-16 19:20:00".to_string(),
+            cvv: "945".to_string(),
+            merchant_id: "".to_string(),
+            mcc: "".to_string(),
+            version: 1,
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use crate::domain::{card, expiry, idempotency, program};
+use crate::protocol;
+use crate::repository::pan_hash;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+// Cards kept in process memory, for local runs without containers and for tests. It enforces
+// what the persistent repositories do: unique ids and PANs, lookups by customer and account,
+// and updates only of cards that exist at the version before the written one. It keeps the
+// idempotency keys of create requests, the card programs and the leases of background jobs as
+// well.
+pub(crate) struct Repository {
+    state: RwLock<State>,
+}
+
+#[derive(Default)]
+struct State {
+    cards: BTreeMap<Uuid, protocol::Card>,
+    pans: HashMap<String, Uuid>,
+    customers: HashMap<String, BTreeSet<Uuid>>,
+    accounts: HashMap<String, BTreeSet<Uuid>>,
+    keys: HashMap<String, idempotency::Record>,
+    programs: BTreeMap<Uuid, protocol::Program>,
+    // holder and expiry of every lease by name
+    leases: HashMap<String, (String, i64)>,
+}
+
+impl Repository {
+    pub(crate) fn new() -> Repository {
+        Repository {
+            state: RwLock::new(State::default()),
+        }
+    }
+}
+
+// The hash is only written, as the lookup key of `exists_pan`.
+fn without_pan_hash(card: &protocol::Card) -> protocol::Card {
+    protocol::Card {
+        pan_hash: String::new(),
+        ..card.clone()
+    }
+}
+
+fn parse_id(card: &protocol::Card) -> Result<Uuid, card::Error> {
+    parse(&card.id)
+}
+
+fn parse(id: &str) -> Result<Uuid, card::Error> {
+    Uuid::parse_str(id).map_err(|_| {
+        card::Error::Invalid(vec![protocol::ValidationError::new(
+            String::from("id"),
+            String::from(id),
+            protocol::Code::UuidFormat,
+        )])
+    })
+}
+
+impl State {
+    fn pan_taken(&self, card: &protocol::Card, id: Uuid) -> bool {
+        matches!(self.pans.get(&card.pan_hash), Some(owner) if *owner != id)
+    }
+
+    fn index(&mut self, id: Uuid, card: protocol::Card) {
+        self.pans.insert(card.pan_hash.clone(), id);
+        self.customers
+            .entry(card.customer_id.clone())
+            .or_default()
+            .insert(id);
+        self.accounts
+            .entry(card.account_id.clone())
+            .or_default()
+            .insert(id);
+        // the CVV is derived from the PAN and expiry when needed, never stored
+        self.cards.insert(
+            id,
+            protocol::Card {
+                cvv: String::new(),
+                ..card
+            },
+        );
+    }
+
+    fn unindex(&mut self, id: Uuid) {
+        if let Some(card) = self.cards.remove(&id) {
+            self.pans.remove(&card.pan_hash);
+            if let Some(ids) = self.customers.get_mut(&card.customer_id) {
+                ids.remove(&id);
+            }
+            if let Some(ids) = self.accounts.get_mut(&card.account_id) {
+                ids.remove(&id);
+            }
+        }
+    }
+
+    // Ids to scan, narrowed by the account or customer index when the filter has one of them.
+    fn candidates(&self, filter: &protocol::Filter) -> Vec<Uuid> {
+        let indexed = match (filter.account_id.is_empty(), filter.customer_id.is_empty()) {
+            (false, _) => self.accounts.get(&filter.account_id),
+            (true, false) => self.customers.get(&filter.customer_id),
+            (true, true) => return self.cards.keys().cloned().collect(),
+        };
+
+        indexed.map_or(vec![], |ids| ids.iter().cloned().collect())
+    }
+}
+
+fn matches(card: &protocol::Card, filter: &protocol::Filter) -> bool {
+    [
+        (&filter.customer_id, &card.customer_id),
+        (&filter.account_id, &card.account_id),
+        (&filter.program_id, &card.program_id),
+        (&filter.org_id, &card.org_id),
+        (&filter.kind, &card.kind),
+        (&filter.status, &card.status),
+    ]
+    .iter()
+    .all(|(wanted, value)| wanted.is_empty() || wanted == value)
+}
+
+// The token is the id of the last card of the previous page.
+fn start_after(filter: &protocol::Filter) -> Result<Option<Uuid>, card::Error> {
+    if filter.next_token.is_empty() {
+        return Ok(None);
+    }
+    let invalid_token = || {
+        card::Error::Invalid(vec![protocol::ValidationError::new(
+            String::from("next_token"),
+            filter.next_token.clone(),
+            protocol::Code::PatternMismatch,
+        )])
+    };
+    let id = base64::decode_config(&filter.next_token, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid_token())?;
+    let id = String::from_utf8(id).map_err(|_| invalid_token())?;
+
+    Uuid::parse_str(&id).map(Some).map_err(|_| invalid_token())
+}
+
+impl card::Repository for Repository {
+    fn save(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        let id = parse_id(card)?;
+        pan_hash(card)?;
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        if state.cards.contains_key(&id) {
+            return Err(card::Error::DuplicateId);
+        }
+        if state.pan_taken(card, id) {
+            return Err(card::Error::DuplicatePan);
+        }
+        state.index(id, card.clone());
+
+        Ok(())
+    }
+
+    fn find_by_id(&self, id: Uuid) -> Result<Option<protocol::Card>, card::Error> {
+        let state = self.state.read().map_err(|_| card::Error::Unavailable)?;
+
+        Ok(state.cards.get(&id).map(without_pan_hash))
+    }
+
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error> {
+        let start = start_after(filter)?;
+        let state = self.state.read().map_err(|_| card::Error::Unavailable)?;
+        let limit = match filter.limit {
+            0 => usize::MAX,
+            limit => limit as usize,
+        };
+
+        let mut matching = state
+            .candidates(filter)
+            .into_iter()
+            .filter(|id| !matches!(start, Some(start) if *id <= start))
+            .filter_map(|id| state.cards.get(&id))
+            .filter(|card| matches(card, filter));
+        let items: Vec<protocol::Card> = matching
+            .by_ref()
+            .take(limit)
+            .map(without_pan_hash)
+            .collect();
+        let next_token = match (matching.next(), items.last()) {
+            (Some(_), Some(last)) => {
+                base64::encode_config(last.id.as_bytes(), base64::URL_SAFE_NO_PAD)
+            }
+            _ => String::new(),
+        };
+
+        Ok(protocol::Page::new(items, next_token))
+    }
+
+    fn update(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        let id = parse_id(card)?;
+        pan_hash(card)?;
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        match state.cards.get(&id) {
+            None => return Err(card::Error::NotFound),
+            Some(stored) if stored.version + 1 != card.version => {
+                return Err(card::Error::VersionMismatch)
+            }
+            Some(_) => (),
+        }
+        if state.pan_taken(card, id) {
+            return Err(card::Error::DuplicatePan);
+        }
+        state.unindex(id);
+        state.index(id, card.clone());
+
+        Ok(())
+    }
+
+    fn exists_pan(&self, pan: &str) -> Result<bool, card::Error> {
+        let state = self.state.read().map_err(|_| card::Error::Unavailable)?;
+
+        Ok(state.pans.contains_key(pan))
+    }
+}
+
+impl idempotency::Store for Repository {
+    fn claim(
+        &self,
+        record: &idempotency::Record,
+        now: i64,
+    ) -> Result<Option<idempotency::Record>, card::Error> {
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        state.keys.retain(|_, held| held.expires_at > now);
+        match state.keys.get(&record.key) {
+            Some(held) => Ok(Some(held.clone())),
+            None => {
+                state.keys.insert(record.key.clone(), record.clone());
+                Ok(None)
+            }
+        }
+    }
+
+    fn complete(&self, key: &str, card_id: &str, expires_at: i64) -> Result<(), card::Error> {
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        if let Some(held) = state.keys.get_mut(key) {
+            held.card_id = String::from(card_id);
+            held.expires_at = expires_at;
+        }
+        Ok(())
+    }
+
+    fn release(&self, key: &str) -> Result<(), card::Error> {
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        state.keys.remove(key);
+        Ok(())
+    }
+}
+
+impl expiry::Lease for Repository {
+    fn acquire(&self, name: &str, holder: &str, now: i64, until: i64) -> Result<bool, card::Error> {
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        match state.leases.get(name) {
+            Some((held_by, expires_at)) if held_by != holder && *expires_at > now => Ok(false),
+            _ => {
+                state
+                    .leases
+                    .insert(String::from(name), (String::from(holder), until));
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl program::Repository for Repository {
+    fn save(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        let id = parse(&program.id)?;
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        if state.programs.contains_key(&id) {
+            return Err(card::Error::Unavailable);
+        }
+        state.programs.insert(id, program.clone());
+
+        Ok(())
+    }
+
+    fn find_by_id(&self, id: Uuid) -> Result<Option<protocol::Program>, card::Error> {
+        let state = self.state.read().map_err(|_| card::Error::Unavailable)?;
+
+        Ok(state.programs.get(&id).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<protocol::Program>, card::Error> {
+        let state = self.state.read().map_err(|_| card::Error::Unavailable)?;
+
+        Ok(state.programs.values().cloned().collect())
+    }
+
+    fn update(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        let id = parse(&program.id)?;
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        match state.programs.get_mut(&id) {
+            Some(stored) => {
+                *stored = program.clone();
+                Ok(())
+            }
+            None => Err(card::Error::NotFound),
+        }
+    }
+
+    fn delete(&self, id: Uuid) -> Result<(), card::Error> {
+        let mut state = self.state.write().map_err(|_| card::Error::Unavailable)?;
+
+        match state.programs.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(card::Error::NotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::Repository as _;
+    use crate::repository::conformance::conformance_tests;
+    use std::sync::Arc;
+    use std::thread;
+
+    conformance_tests!(Repository::new());
+
+    const CUSTOMER_ID: &str = "29ce6541-302b-405e-9dfe-549934d4e4b2";
+    const ACCOUNT_ID: &str = "a2d46c49-262e-431d-8f1a-ff5b18b44982";
+
+    fn a_card(id: &str, pan: &str) -> protocol::Card {
+        protocol::Card {
+            id: String::from(id),
+            customer_id: String::from(CUSTOMER_ID),
+            org_id: String::from("876ce143-6fcb-4c17-aaf1-f02c1d3654ce"),
+            program_id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+            account_id: String::from(ACCOUNT_ID),
+            printed_name: String::from("BAKER MAYFIELD"),
+            pan: String::from(pan),
+            pan_hash: hash(pan),
+            kind: String::from("PLASTIC"),
+            status: String::from("ENABLED"),
+            ..Default::default()
+        }
+    }
+
+    // Stands in for the keyed hash the encrypted repository computes.
+    fn hash(pan: &str) -> String {
+        format!("hash of {}", pan)
+    }
+
+    fn an_id(n: u8) -> String {
+        format!("00000000-0000-4000-8000-0000000000{:02}", n)
+    }
+
+    #[test]
+    fn save_and_find() {
+        let repository = Repository::new();
+        let card = a_card(&an_id(1), "4012000033330026");
+
+        repository.save(&card).unwrap();
+
+        assert_eq!(
+            repository
+                .find_by_id(Uuid::parse_str(&an_id(1)).unwrap())
+                .unwrap(),
+            Some(without_pan_hash(&card))
+        );
+        assert_eq!(
+            repository
+                .find_by_id(Uuid::parse_str(&an_id(2)).unwrap())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn save_rejects_duplicate_id_and_pan() {
+        let repository = Repository::new();
+        repository
+            .save(&a_card(&an_id(1), "4012000033330026"))
+            .unwrap();
+
+        assert_eq!(
+            repository.save(&a_card(&an_id(1), "5555555555554444")),
+            Err(card::Error::DuplicateId)
+        );
+        assert_eq!(
+            repository.save(&a_card(&an_id(2), "4012000033330026")),
+            Err(card::Error::DuplicatePan)
+        );
+        assert!(repository.exists_pan(&hash("4012000033330026")).unwrap());
+        assert!(!repository.exists_pan(&hash("5555555555554444")).unwrap());
+    }
+
+    #[test]
+    fn lookup_by_pan_hash() {
+        let repository = Repository::new();
+        repository
+            .save(&a_card(&an_id(1), "4012000033330026"))
+            .unwrap();
+
+        assert!(repository.exists_pan(&hash("4012000033330026")).unwrap());
+        assert!(!repository.exists_pan("4012000033330026").unwrap());
+    }
+
+    #[test]
+    fn refuse_a_card_without_pan_hash() {
+        let repository = Repository::new();
+        let unsealed = protocol::Card {
+            pan_hash: String::new(),
+            ..a_card(&an_id(1), "4012000033330026")
+        };
+
+        assert_eq!(repository.save(&unsealed), Err(card::Error::Unavailable));
+        repository
+            .save(&a_card(&an_id(1), "4012000033330026"))
+            .unwrap();
+        assert_eq!(
+            repository.update(&protocol::Card {
+                version: 1,
+                ..unsealed
+            }),
+            Err(card::Error::Unavailable)
+        );
+        assert!(!repository.exists_pan("4012000033330026").unwrap());
+    }
+
+    #[test]
+    fn update_only_existing_cards() {
+        let repository = Repository::new();
+        repository
+            .save(&a_card(&an_id(1), "4012000033330026"))
+            .unwrap();
+        repository
+            .save(&a_card(&an_id(2), "5555555555554444"))
+            .unwrap();
+        let blocked = protocol::Card {
+            status: String::from("BLOCKED"),
+            version: 1,
+            ..a_card(&an_id(1), "4111111111111111")
+        };
+
+        assert_eq!(repository.update(&blocked), Ok(()));
+        assert_eq!(
+            repository.update(&a_card(&an_id(3), "4000056655665556")),
+            Err(card::Error::NotFound)
+        );
+        assert_eq!(
+            repository.update(&protocol::Card {
+                version: 2,
+                ..a_card(&an_id(1), "5555555555554444")
+            }),
+            Err(card::Error::DuplicatePan)
+        );
+        assert!(!repository.exists_pan(&hash("4012000033330026")).unwrap());
+        assert!(repository.exists_pan(&hash("4111111111111111")).unwrap());
+        assert_eq!(
+            repository
+                .find_by_id(Uuid::parse_str(&an_id(1)).unwrap())
+                .unwrap(),
+            Some(without_pan_hash(&blocked))
+        );
+    }
+
+    #[test]
+    fn list_by_customer_and_account() {
+        let repository = Repository::new();
+        repository
+            .save(&a_card(&an_id(1), "4012000033330026"))
+            .unwrap();
+        repository
+            .save(&protocol::Card {
+                account_id: String::from("b2d46c49-262e-431d-8f1a-ff5b18b44982"),
+                ..a_card(&an_id(2), "5555555555554444")
+            })
+            .unwrap();
+        repository
+            .save(&protocol::Card {
+                customer_id: String::from("39ce6541-302b-405e-9dfe-549934d4e4b2"),
+                ..a_card(&an_id(3), "4111111111111111")
+            })
+            .unwrap();
+
+        let ids = |filter: protocol::Filter| -> Vec<String> {
+            repository
+                .list(&filter)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|card| card.id)
+                .collect()
+        };
+
+        assert_eq!(
+            ids(protocol::Filter {
+                customer_id: String::from(CUSTOMER_ID),
+                ..Default::default()
+            }),
+            vec![an_id(1), an_id(2)]
+        );
+        assert_eq!(
+            ids(protocol::Filter {
+                account_id: String::from(ACCOUNT_ID),
+                ..Default::default()
+            }),
+            vec![an_id(1), an_id(3)]
+        );
+        assert_eq!(
+            ids(protocol::Filter {
+                account_id: String::from(ACCOUNT_ID),
+                customer_id: String::from(CUSTOMER_ID),
+                ..Default::default()
+            }),
+            vec![an_id(1)]
+        );
+        assert!(ids(protocol::Filter {
+            status: String::from("BLOCKED"),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn list_pages() {
+        let repository = Repository::new();
+        for (n, pan) in ["4012000033330026", "5555555555554444", "4111111111111111"]
+            .iter()
+            .enumerate()
+        {
+            repository.save(&a_card(&an_id(n as u8), pan)).unwrap();
+        }
+        let filter = protocol::Filter {
+            limit: 2,
+            ..Default::default()
+        };
+
+        let first = repository.list(&filter).unwrap();
+        let second = repository
+            .list(&protocol::Filter {
+                next_token: first.next_token.clone(),
+                ..filter.clone()
+            })
+            .unwrap();
+
+        assert_eq!(first.items.len(), 2);
+        assert!(!first.next_token.is_empty());
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].id, an_id(2));
+        assert!(second.next_token.is_empty());
+        assert_eq!(
+            repository.list(&protocol::Filter {
+                next_token: String::from("not a token"),
+                ..Default::default()
+            }),
+            Err(card::Error::Invalid(vec![protocol::ValidationError::new(
+                String::from("next_token"),
+                String::from("not a token"),
+                protocol::Code::PatternMismatch,
+            )]))
+        );
+    }
+
+    #[test]
+    fn concurrent_saves() {
+        let repository = Arc::new(Repository::new());
+
+        let handles: Vec<_> = (0..8u8)
+            .map(|n| {
+                let repository = repository.clone();
+                thread::spawn(move || repository.save(&a_card(&an_id(n), &format!("pan-{}", n))))
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert_eq!(
+            repository
+                .list(&protocol::Filter::default())
+                .unwrap()
+                .items
+                .len(),
+            8
+        );
+    }
+}
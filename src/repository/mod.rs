@@ -0,0 +1,19 @@
+use crate::domain::card;
+use crate::protocol;
+
+#[cfg(test)]
+pub(crate) mod conformance;
+pub(crate) mod dynamo;
+pub(crate) mod encrypted;
+pub(crate) mod memory;
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite;
+
+// The backends index a card by the keyed hash of its PAN, which the encrypted repository fills
+// in. A card without one was never sealed, so it is refused instead of being indexed by its PAN.
+pub(crate) fn pan_hash(card: &protocol::Card) -> Result<&str, card::Error> {
+    match card.pan_hash.is_empty() {
+        true => Err(card::Error::Unavailable),
+        false => Ok(&card.pan_hash),
+    }
+}
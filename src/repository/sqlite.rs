@@ -0,0 +1,647 @@
+use crate::domain::{card, expiry, idempotency, program};
+use crate::protocol;
+use crate::repository::pan_hash;
+use crate::settings;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use std::sync::Mutex;
+
+// Schema versions, applied in order and recorded in `user_version`. Never edit a released one,
+// append a new version instead.
+static MIGRATIONS: [&str; 9] = [
+    include_str!("../../migrations/sqlite/V1__create_cards.sql"),
+    include_str!("../../migrations/sqlite/V2__index_lookups.sql"),
+    include_str!("../../migrations/sqlite/V3__add_version.sql"),
+    include_str!("../../migrations/sqlite/V4__create_idempotency_keys.sql"),
+    include_str!("../../migrations/sqlite/V5__create_programs.sql"),
+    include_str!("../../migrations/sqlite/V6__add_program_temporary_ttl.sql"),
+    include_str!("../../migrations/sqlite/V7__create_leases.sql"),
+    include_str!("../../migrations/sqlite/V8__add_merchant_lock.sql"),
+    include_str!("../../migrations/sqlite/V9__drop_cvv.sql"),
+];
+static COLUMNS: &str = "id, customer_id, org_id, program_id, account_id, printed_name, password, \
+                        password_attempts, expiration_date, issuing_date, pan, pan_hash, kind, \
+                        status, status_reason, status_updated_at, version, merchant_id, mcc";
+static PROGRAM_COLUMNS: &str =
+    "id, name, bin, pan_length, kinds, validity_months, funding, active, temporary_ttl_hours";
+
+// Cards kept in an embedded SQLite database, for installations that cannot use AWS.
+pub(crate) struct Repository {
+    connection: Mutex<Connection>,
+}
+
+impl Repository {
+    pub(crate) fn open(settings: &settings::Sqlite) -> Result<Repository, String> {
+        let connection = Connection::open(&settings.path)
+            .map_err(|err| format!("Cannot open SQLite database {}: {}", settings.path, err))?;
+
+        Repository::new(connection)
+    }
+
+    fn new(mut connection: Connection) -> Result<Repository, String> {
+        migrate(&mut connection)
+            .map_err(|err| format!("Cannot migrate SQLite database: {}", err))?;
+
+        Ok(Repository {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn in_memory() -> Repository {
+        Repository::new(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn execute<T>(
+        &self,
+        operation: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, card::Error> {
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| card::Error::Unavailable)?;
+
+        operation(&mut connection).map_err(|err| match err {
+            rusqlite::Error::SqliteFailure(failure, Some(message))
+                if failure.code == ErrorCode::ConstraintViolation
+                    && message.contains("cards.pan_hash") =>
+            {
+                card::Error::DuplicatePan
+            }
+            rusqlite::Error::SqliteFailure(failure, Some(message))
+                if failure.code == ErrorCode::ConstraintViolation
+                    && message.contains("cards.id") =>
+            {
+                card::Error::DuplicateId
+            }
+            _ => card::Error::Unavailable,
+        })
+    }
+}
+
+// Applies the versions above the one recorded in the database, each in its own transaction.
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let current: usize = connection.pragma_query_value(None, "user_version", |row| {
+        row.get::<_, i64>(0).map(|version| version as usize)
+    })?;
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", &(version as i64 + 1))?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<protocol::Card> {
+    Ok(protocol::Card {
+        id: row.get(0)?,
+        customer_id: row.get(1)?,
+        org_id: row.get(2)?,
+        program_id: row.get(3)?,
+        account_id: row.get(4)?,
+        printed_name: row.get(5)?,
+        password: row.get(6)?,
+        password_attempts: row.get(7)?,
+        expiration_date: row.get(8)?,
+        issuing_date: row.get(9)?,
+        pan: row.get(10)?,
+        // only written, as the lookup key of `exists_pan`
+        pan_hash: String::new(),
+        kind: row.get(12)?,
+        status: row.get(13)?,
+        status_reason: row.get(14)?,
+        status_updated_at: row.get(15)?,
+        // never stored, derived from the PAN and expiry when needed
+        cvv: String::new(),
+        version: row.get::<_, i64>(16)? as u64,
+        merchant_id: row.get(17)?,
+        mcc: row.get(18)?,
+    })
+}
+
+fn program_from_row(row: &Row) -> rusqlite::Result<protocol::Program> {
+    let kinds: String = row.get(4)?;
+
+    Ok(protocol::Program {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        bin: row.get(2)?,
+        pan_length: row.get::<_, i64>(3)? as usize,
+        kinds: kinds
+            .split(',')
+            .filter(|kind| !kind.is_empty())
+            .map(String::from)
+            .collect(),
+        validity_months: row.get(5)?,
+        funding: row.get(6)?,
+        active: row.get(7)?,
+        temporary_ttl_hours: row.get(8)?,
+    })
+}
+
+// The token is the id of the last card of the previous page.
+fn start_after(filter: &protocol::Filter) -> Result<Option<String>, card::Error> {
+    if filter.next_token.is_empty() {
+        return Ok(None);
+    }
+    let invalid_token = || {
+        card::Error::Invalid(vec![protocol::ValidationError::new(
+            String::from("next_token"),
+            filter.next_token.clone(),
+            protocol::Code::PatternMismatch,
+        )])
+    };
+    let id = base64::decode_config(&filter.next_token, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid_token())?;
+    let id = String::from_utf8(id).map_err(|_| invalid_token())?;
+
+    uuid::Uuid::parse_str(&id).map_err(|_| invalid_token())?;
+    Ok(Some(id))
+}
+
+impl card::Repository for Repository {
+    fn save(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        let pan_hash = pan_hash(card)?;
+        self.execute(|connection| {
+            connection.execute(
+                &format!(
+                    "INSERT INTO cards ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, \
+                     ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+                    COLUMNS
+                ),
+                params![
+                    card.id,
+                    card.customer_id,
+                    card.org_id,
+                    card.program_id,
+                    card.account_id,
+                    card.printed_name,
+                    card.password,
+                    card.password_attempts,
+                    card.expiration_date,
+                    card.issuing_date,
+                    card.pan,
+                    pan_hash,
+                    card.kind,
+                    card.status,
+                    card.status_reason,
+                    card.status_updated_at,
+                    card.version as i64,
+                    card.merchant_id,
+                    card.mcc,
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Card>, card::Error> {
+        self.execute(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM cards WHERE id = ?1", COLUMNS),
+                    params![id.to_string()],
+                    from_row,
+                )
+                .optional()
+        })
+    }
+
+    fn list(&self, filter: &protocol::Filter) -> Result<protocol::Page, card::Error> {
+        let mut conditions = vec![];
+        let mut values = vec![];
+        let start = start_after(filter)?;
+        for (column, value) in [
+            ("account_id", &filter.account_id),
+            ("customer_id", &filter.customer_id),
+            ("program_id", &filter.program_id),
+            ("org_id", &filter.org_id),
+            ("kind", &filter.kind),
+            ("status", &filter.status),
+        ]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        {
+            values.push(value.to_string());
+            conditions.push(format!("{} = ?{}", column, values.len()));
+        }
+        if let Some(start) = start {
+            values.push(start);
+            conditions.push(format!("id > ?{}", values.len()));
+        }
+        let mut query = format!("SELECT {} FROM cards", COLUMNS);
+        if !conditions.is_empty() {
+            query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+        query.push_str(" ORDER BY id");
+        // one row past the page tells whether there is a next one
+        if filter.limit > 0 {
+            query.push_str(&format!(" LIMIT {}", filter.limit + 1));
+        }
+
+        let mut items = self.execute(|connection| {
+            let mut statement = connection.prepare(&query)?;
+            let rows = statement.query_map(values.iter(), from_row)?;
+            rows.collect::<rusqlite::Result<Vec<protocol::Card>>>()
+        })?;
+        let next_token = match filter.limit > 0 && items.len() > filter.limit as usize {
+            true => {
+                items.truncate(filter.limit as usize);
+                let last = &items[items.len() - 1];
+                base64::encode_config(last.id.as_bytes(), base64::URL_SAFE_NO_PAD)
+            }
+            false => String::new(),
+        };
+
+        Ok(protocol::Page::new(items, next_token))
+    }
+
+    // Rewrites the card in a transaction, so a status change is applied whole or not at all and
+    // a card missing is told apart from one at another version.
+    fn update(&self, card: &protocol::Card) -> Result<(), card::Error> {
+        let pan_hash = pan_hash(card)?;
+        let (updated, exists) = self.execute(|connection| {
+            let transaction = connection.transaction()?;
+            let updated = transaction.execute(
+                "UPDATE cards SET customer_id = ?2, org_id = ?3, program_id = ?4, \
+                 account_id = ?5, printed_name = ?6, password = ?7, password_attempts = ?8, \
+                 expiration_date = ?9, issuing_date = ?10, pan = ?11, pan_hash = ?12, \
+                 kind = ?13, status = ?14, status_reason = ?15, status_updated_at = ?16, \
+                 version = ?17, merchant_id = ?18, mcc = ?19 WHERE id = ?1 AND version + 1 = ?17",
+                params![
+                    card.id,
+                    card.customer_id,
+                    card.org_id,
+                    card.program_id,
+                    card.account_id,
+                    card.printed_name,
+                    card.password,
+                    card.password_attempts,
+                    card.expiration_date,
+                    card.issuing_date,
+                    card.pan,
+                    pan_hash,
+                    card.kind,
+                    card.status,
+                    card.status_reason,
+                    card.status_updated_at,
+                    card.version as i64,
+                    card.merchant_id,
+                    card.mcc,
+                ],
+            )?;
+            let exists = updated > 0
+                || transaction.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM cards WHERE id = ?1)",
+                    params![card.id],
+                    |row| row.get(0),
+                )?;
+            transaction.commit()?;
+            Ok((updated, exists))
+        })?;
+
+        match (updated, exists) {
+            (0, false) => Err(card::Error::NotFound),
+            (0, true) => Err(card::Error::VersionMismatch),
+            _ => Ok(()),
+        }
+    }
+
+    fn exists_pan(&self, pan: &str) -> Result<bool, card::Error> {
+        self.execute(|connection| {
+            connection.query_row(
+                "SELECT EXISTS (SELECT 1 FROM cards WHERE pan_hash = ?1)",
+                params![pan],
+                |row| row.get(0),
+            )
+        })
+    }
+}
+
+impl idempotency::Store for Repository {
+    // Expired keys are dropped first, so the insert only fails on a key still held.
+    fn claim(
+        &self,
+        record: &idempotency::Record,
+        now: i64,
+    ) -> Result<Option<idempotency::Record>, card::Error> {
+        self.execute(|connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "DELETE FROM idempotency_keys WHERE key = ?1 AND expires_at <= ?2",
+                params![record.key, now],
+            )?;
+            let inserted = transaction.execute(
+                "INSERT OR IGNORE INTO idempotency_keys (key, fingerprint, card_id, expires_at) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    record.key,
+                    record.fingerprint,
+                    record.card_id,
+                    record.expires_at
+                ],
+            )?;
+            let held = match inserted {
+                0 => Some(transaction.query_row(
+                    "SELECT key, fingerprint, card_id, expires_at FROM idempotency_keys \
+                     WHERE key = ?1",
+                    params![record.key],
+                    |row| {
+                        Ok(idempotency::Record {
+                            key: row.get(0)?,
+                            fingerprint: row.get(1)?,
+                            card_id: row.get(2)?,
+                            expires_at: row.get(3)?,
+                        })
+                    },
+                )?),
+                _ => None,
+            };
+            transaction.commit()?;
+            Ok(held)
+        })
+    }
+
+    fn complete(&self, key: &str, card_id: &str, expires_at: i64) -> Result<(), card::Error> {
+        self.execute(|connection| {
+            connection.execute(
+                "UPDATE idempotency_keys SET card_id = ?2, expires_at = ?3 WHERE key = ?1",
+                params![key, card_id, expires_at],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn release(&self, key: &str) -> Result<(), card::Error> {
+        self.execute(|connection| {
+            connection.execute("DELETE FROM idempotency_keys WHERE key = ?1", params![key])
+        })
+        .map(|_| ())
+    }
+}
+
+impl expiry::Lease for Repository {
+    // The upsert only overwrites a lease of the same holder or one already expired.
+    fn acquire(&self, name: &str, holder: &str, now: i64, until: i64) -> Result<bool, card::Error> {
+        let written = self.execute(|connection| {
+            connection.execute(
+                "INSERT INTO leases (name, holder, expires_at) VALUES (?1, ?2, ?4) \
+                 ON CONFLICT (name) DO UPDATE SET holder = excluded.holder, \
+                 expires_at = excluded.expires_at \
+                 WHERE leases.holder = excluded.holder OR leases.expires_at <= ?3",
+                params![name, holder, now, until],
+            )
+        })?;
+
+        Ok(written > 0)
+    }
+}
+
+impl program::Repository for Repository {
+    fn save(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        self.execute(|connection| {
+            connection.execute(
+                &format!(
+                    "INSERT INTO programs ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    PROGRAM_COLUMNS
+                ),
+                params![
+                    program.id,
+                    program.name,
+                    program.bin,
+                    program.pan_length as i64,
+                    program.kinds.join(","),
+                    program.validity_months,
+                    program.funding,
+                    program.active,
+                    program.temporary_ttl_hours,
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    fn find_by_id(&self, id: uuid::Uuid) -> Result<Option<protocol::Program>, card::Error> {
+        self.execute(|connection| {
+            connection
+                .query_row(
+                    &format!("SELECT {} FROM programs WHERE id = ?1", PROGRAM_COLUMNS),
+                    params![id.to_string()],
+                    program_from_row,
+                )
+                .optional()
+        })
+    }
+
+    fn list(&self) -> Result<Vec<protocol::Program>, card::Error> {
+        self.execute(|connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT {} FROM programs ORDER BY id",
+                PROGRAM_COLUMNS
+            ))?;
+            let rows = statement.query_map(params![], program_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<protocol::Program>>>()
+        })
+    }
+
+    fn update(&self, program: &protocol::Program) -> Result<(), card::Error> {
+        let updated = self.execute(|connection| {
+            connection.execute(
+                "UPDATE programs SET name = ?2, bin = ?3, pan_length = ?4, kinds = ?5, \
+                 validity_months = ?6, funding = ?7, active = ?8, temporary_ttl_hours = ?9 \
+                 WHERE id = ?1",
+                params![
+                    program.id,
+                    program.name,
+                    program.bin,
+                    program.pan_length as i64,
+                    program.kinds.join(","),
+                    program.validity_months,
+                    program.funding,
+                    program.active,
+                    program.temporary_ttl_hours,
+                ],
+            )
+        })?;
+
+        match updated {
+            0 => Err(card::Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    fn delete(&self, id: uuid::Uuid) -> Result<(), card::Error> {
+        let deleted = self.execute(|connection| {
+            connection.execute(
+                "DELETE FROM programs WHERE id = ?1",
+                params![id.to_string()],
+            )
+        })?;
+
+        match deleted {
+            0 => Err(card::Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::card::Repository as _;
+    use crate::repository::conformance::conformance_tests;
+
+    conformance_tests!(Repository::in_memory());
+
+    fn a_card(id: &str, pan: &str) -> protocol::Card {
+        protocol::Card {
+            id: String::from(id),
+            customer_id: String::from("29ce6541-302b-405e-9dfe-549934d4e4b2"),
+            org_id: String::from("876ce143-6fcb-4c17-aaf1-f02c1d3654ce"),
+            program_id: String::from("00c9e86a-8d55-4a95-884b-4a6faeb9289e"),
+            account_id: String::from("a2d46c49-262e-431d-8f1a-ff5b18b44982"),
+            printed_name: String::from("BAKER MAYFIELD"),
+            password: String::from("sealed password"),
+            expiration_date: String::from("sealed expiration date"),
+            issuing_date: String::from("2021-02-16 19:20:00"),
+            pan: String::from(pan),
+            pan_hash: format!("hash of {}", pan),
+            kind: String::from("PLASTIC"),
+            status: String::from("ENABLED"),
+            cvv: String::from("945"),
+            version: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn migrate_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        let version: i64 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn migrate_drops_the_stored_cvv() {
+        let connection = Connection::open_in_memory().unwrap();
+        for migration in MIGRATIONS.iter().take(8) {
+            connection.execute_batch(migration).unwrap();
+        }
+        connection.pragma_update(None, "user_version", &8).unwrap();
+        connection
+            .execute(
+                "INSERT INTO cards (id, customer_id, org_id, program_id, account_id, \
+                 printed_name, password, expiration_date, issuing_date, pan, pan_hash, kind, \
+                 status, cvv, version) VALUES ('5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10', 'c', 'o', \
+                 'p', 'a', 'BAKER', 'sealed', 'sealed', '2021-02-16 19:20:00', 'sealed', 'hash', \
+                 'PLASTIC', 'ENABLED', '945', 3)",
+                params![],
+            )
+            .unwrap();
+
+        let repository = Repository::new(connection).unwrap();
+        let act = repository
+            .find_by_id(uuid::Uuid::parse_str("5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10").unwrap())
+            .unwrap()
+            .unwrap();
+        let columns: Vec<String> = repository
+            .execute(|connection| {
+                let mut statement =
+                    connection.prepare("SELECT name FROM pragma_table_info('cards')")?;
+                let names = statement.query_map(params![], |row| row.get(0))?;
+                names.collect()
+            })
+            .unwrap();
+
+        assert_eq!((act.cvv.as_str(), act.version), ("", 3));
+        assert!(repository.exists_pan("hash").unwrap());
+        assert!(!columns.contains(&String::from("cvv")));
+    }
+
+    #[test]
+    fn save_keeps_the_pan_hash_unique() {
+        let repository = Repository::in_memory();
+        let card = protocol::Card {
+            pan_hash: String::from("hash"),
+            ..a_card("5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10", "sealed")
+        };
+        repository.save(&card).unwrap();
+
+        assert_eq!(
+            repository.save(&protocol::Card {
+                id: String::from("0d1c4a8e-2f6b-4c1e-9a7d-8e5b3f2a1c90"),
+                ..card.clone()
+            }),
+            Err(card::Error::DuplicatePan)
+        );
+        assert_eq!(
+            repository.save(&protocol::Card {
+                pan_hash: String::from("another hash"),
+                ..card
+            }),
+            Err(card::Error::DuplicateId)
+        );
+        assert!(repository.exists_pan("hash").unwrap());
+        assert!(!repository.exists_pan("sealed").unwrap());
+    }
+
+    #[test]
+    fn refuse_a_card_without_pan_hash() {
+        let repository = Repository::in_memory();
+        let card = a_card("5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10", "4012000033330026");
+        repository.save(&card).unwrap();
+        let unsealed = protocol::Card {
+            pan_hash: String::new(),
+            ..card
+        };
+
+        assert_eq!(
+            repository.save(&protocol::Card {
+                id: String::from("0d1c4a8e-2f6b-4c1e-9a7d-8e5b3f2a1c90"),
+                ..unsealed.clone()
+            }),
+            Err(card::Error::Unavailable)
+        );
+        assert_eq!(
+            repository.update(&protocol::Card {
+                version: 2,
+                ..unsealed
+            }),
+            Err(card::Error::Unavailable)
+        );
+        assert!(!repository.exists_pan("4012000033330026").unwrap());
+    }
+
+    #[test]
+    fn open_file() {
+        let path = std::env::temp_dir().join("cards-sqlite-test.db");
+        let _ = std::fs::remove_file(&path);
+        let settings = settings::Sqlite {
+            path: String::from(path.to_str().unwrap()),
+        };
+        let card = a_card("5f4f4f9e-3b8a-4b36-a8a5-6a1d3c2c6f10", "4012000033330026");
+
+        Repository::open(&settings).unwrap().save(&card).unwrap();
+        let act = Repository::open(&settings)
+            .unwrap()
+            .find_by_id(uuid::Uuid::parse_str(&card.id).unwrap());
+
+        assert_eq!(
+            act,
+            Ok(Some(protocol::Card {
+                cvv: String::new(),
+                pan_hash: String::new(),
+                ..card
+            }))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
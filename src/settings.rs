@@ -0,0 +1,669 @@
+use crate::crypto::key::{self, KeyProvider};
+use crate::generator::cvv;
+use rusoto_core::Region;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::{env, fs};
+
+// Path of the settings file, `cards.toml` in the working directory when unset.
+pub static CONFIG_VAR: &str = "CARDS_CONFIG";
+static DEFAULT_FILE: &str = "cards.toml";
+const MAX_WORKERS: usize = 256;
+
+// Server settings, read from a TOML file and overridden by `CARDS_*` environment variables so the
+// same binary runs locally, on staging and on production.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub(crate) bind: String,
+    pub(crate) workers: usize,
+    // largest JSON body accepted, in bytes
+    pub(crate) payload_limit: usize,
+    // `dynamo` keeps the cards in DynamoDB, `sqlite` in a local database file when built with
+    // the `sqlite` feature, `memory` in the process and loses them on exit
+    pub(crate) repository: String,
+    pub(crate) dynamo: Dynamo,
+    pub(crate) sqlite: Sqlite,
+    pub(crate) keys: Keys,
+    pub(crate) cvv: Cvv,
+    pub(crate) idempotency: Idempotency,
+    pub(crate) expiry: Expiry,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dynamo {
+    // custom endpoint such as localstack, the regional AWS one when empty
+    pub(crate) endpoint: String,
+    pub(crate) region: String,
+    pub(crate) table: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sqlite {
+    pub(crate) path: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    // `env` reads the keys from `CARDS_KEYS`, `file` from the file at `file`
+    pub(crate) provider: String,
+    pub(crate) file: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cvv {
+    // double-length card verification key in hex, kept out of the file through `CARDS_CVK`
+    pub(crate) key: String,
+    pub(crate) service_code: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Idempotency {
+    // seconds an Idempotency-Key of POST /cards is remembered
+    pub(crate) ttl: u64,
+    // seconds a request still running holds its key, so a crashed one does not block retries
+    pub(crate) lease: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Expiry {
+    // seconds between two sweeps of the cards whose time ran out
+    pub(crate) interval: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            bind: String::from("127.0.0.1:8080"),
+            workers: 4,
+            payload_limit: 32 * 1024,
+            repository: String::from("dynamo"),
+            dynamo: Dynamo::default(),
+            sqlite: Sqlite::default(),
+            keys: Keys::default(),
+            cvv: Cvv::default(),
+            idempotency: Idempotency::default(),
+            expiry: Expiry::default(),
+        }
+    }
+}
+
+impl Default for Dynamo {
+    fn default() -> Dynamo {
+        Dynamo {
+            endpoint: String::from("http://localhost:4566"),
+            region: String::from("us-east-1"),
+            table: String::from("Cards"),
+        }
+    }
+}
+
+impl Default for Sqlite {
+    fn default() -> Sqlite {
+        Sqlite {
+            path: String::from("cards.db"),
+        }
+    }
+}
+
+impl Default for Keys {
+    fn default() -> Keys {
+        Keys {
+            provider: String::from("env"),
+            file: String::new(),
+        }
+    }
+}
+
+impl Default for Cvv {
+    fn default() -> Cvv {
+        Cvv {
+            key: String::new(),
+            service_code: String::from(cvv::PRINTED_SERVICE_CODE),
+        }
+    }
+}
+
+impl Default for Idempotency {
+    fn default() -> Idempotency {
+        Idempotency {
+            ttl: 24 * 60 * 60,
+            lease: 60,
+        }
+    }
+}
+
+impl Default for Expiry {
+    fn default() -> Expiry {
+        Expiry { interval: 5 * 60 }
+    }
+}
+
+impl Settings {
+    // Reads the settings file and the environment, failing on the first source that cannot be
+    // read and on every invalid setting.
+    pub fn load() -> Result<Settings, String> {
+        let contents = match env::var(CONFIG_VAR) {
+            Ok(path) => fs::read_to_string(&path)
+                .map_err(|err| format!("Cannot read settings file {}: {}", path, err))?,
+            Err(_) => fs::read_to_string(DEFAULT_FILE).unwrap_or_default(),
+        };
+
+        Settings::parse(&contents, &|name| env::var(name).ok())
+    }
+
+    pub(crate) fn parse(
+        contents: &str,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Settings, String> {
+        let settings: Settings =
+            toml::from_str(contents).map_err(|err| format!("Invalid settings file: {}", err))?;
+
+        settings.with_env(env)?.validate()
+    }
+
+    fn with_env(mut self, env: &dyn Fn(&str) -> Option<String>) -> Result<Settings, String> {
+        let number = |name: &str, value: String| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} must be a number, got \"{}\"", name, value))
+        };
+
+        if let Some(bind) = env("CARDS_BIND") {
+            self.bind = bind
+        }
+        if let Some(workers) = env("CARDS_WORKERS") {
+            self.workers = number("CARDS_WORKERS", workers)?
+        }
+        if let Some(limit) = env("CARDS_PAYLOAD_LIMIT") {
+            self.payload_limit = number("CARDS_PAYLOAD_LIMIT", limit)?
+        }
+        if let Some(repository) = env("CARDS_REPOSITORY") {
+            self.repository = repository
+        }
+        if let Some(endpoint) = env("CARDS_DYNAMO_ENDPOINT") {
+            self.dynamo.endpoint = endpoint
+        }
+        if let Some(region) = env("CARDS_REGION") {
+            self.dynamo.region = region
+        }
+        if let Some(table) = env("CARDS_DYNAMO_TABLE") {
+            self.dynamo.table = table
+        }
+        if let Some(path) = env("CARDS_SQLITE_PATH") {
+            self.sqlite.path = path
+        }
+        if let Some(provider) = env("CARDS_KEY_PROVIDER") {
+            self.keys.provider = provider
+        }
+        if let Some(file) = env("CARDS_KEY_FILE") {
+            self.keys.file = file
+        }
+        if let Some(key) = env("CARDS_CVK") {
+            self.cvv.key = key
+        }
+        if let Some(ttl) = env("CARDS_IDEMPOTENCY_TTL") {
+            self.idempotency.ttl = number("CARDS_IDEMPOTENCY_TTL", ttl)? as u64
+        }
+        if let Some(lease) = env("CARDS_IDEMPOTENCY_LEASE") {
+            self.idempotency.lease = number("CARDS_IDEMPOTENCY_LEASE", lease)? as u64
+        }
+        if let Some(interval) = env("CARDS_EXPIRY_INTERVAL") {
+            self.expiry.interval = number("CARDS_EXPIRY_INTERVAL", interval)? as u64
+        }
+
+        Ok(self)
+    }
+
+    fn validate(self) -> Result<Settings, String> {
+        let mut errors = vec![];
+
+        if SocketAddr::from_str(&self.bind).is_err() {
+            errors.push(format!(
+                "bind must be an address and port such as 0.0.0.0:8080, got \"{}\"",
+                self.bind
+            ));
+        }
+        if self.workers == 0 || self.workers > MAX_WORKERS {
+            errors.push(format!(
+                "workers must be between 1 and {}, got {}",
+                MAX_WORKERS, self.workers
+            ));
+        }
+        if self.payload_limit == 0 {
+            errors.push(String::from("payload_limit must be greater than 0"));
+        }
+        match self.repository.as_str() {
+            "dynamo" | "memory" => (),
+            "sqlite" if cfg!(feature = "sqlite") => (),
+            "sqlite" => errors.push(String::from(
+                "repository sqlite needs a build with the sqlite feature",
+            )),
+            repository => errors.push(format!(
+                "repository must be dynamo, sqlite or memory, got \"{}\"",
+                repository
+            )),
+        }
+        if self.sqlite.path.is_empty() {
+            errors.push(String::from("sqlite.path is required"));
+        }
+        let endpoint = &self.dynamo.endpoint;
+        if !endpoint.is_empty()
+            && !endpoint.starts_with("http://")
+            && !endpoint.starts_with("https://")
+        {
+            errors.push(format!(
+                "dynamo.endpoint must be an http(s) URL, got \"{}\"",
+                endpoint
+            ));
+        }
+        if self.dynamo.region.is_empty()
+            || (endpoint.is_empty() && Region::from_str(&self.dynamo.region).is_err())
+        {
+            errors.push(format!(
+                "dynamo.region must be an AWS region, got \"{}\"",
+                self.dynamo.region
+            ));
+        }
+        if self.dynamo.table.is_empty() {
+            errors.push(String::from("dynamo.table is required"));
+        }
+        match (self.keys.provider.as_str(), self.keys.file.is_empty()) {
+            ("env", _) | ("file", false) => (),
+            ("file", true) => {
+                errors.push(String::from("keys.file is required by the file provider"))
+            }
+            (provider, _) => errors.push(format!(
+                "keys.provider must be env or file, got \"{}\"",
+                provider
+            )),
+        }
+        if !self.cvv.key.is_empty()
+            && cvv::Generator::new(&self.cvv.key, &self.cvv.service_code).is_err()
+        {
+            errors.push(String::from(
+                "cvv.key must be 32 hex digits and cvv.service_code 3 digits",
+            ));
+        }
+        if self.idempotency.ttl == 0 || self.idempotency.ttl > i64::MAX as u64 {
+            errors.push(format!(
+                "idempotency.ttl must be a number of seconds greater than 0, got {}",
+                self.idempotency.ttl
+            ));
+        }
+        if self.idempotency.lease == 0 || self.idempotency.lease > self.idempotency.ttl {
+            errors.push(format!(
+                "idempotency.lease must be a number of seconds between 1 and idempotency.ttl, got {}",
+                self.idempotency.lease
+            ));
+        }
+
+        // the lease of a sweep is held for two intervals, which must fit in the stored timestamps
+        if self.expiry.interval == 0 || self.expiry.interval > (i64::MAX / 4) as u64 {
+            errors.push(format!(
+                "expiry.interval must be a number of seconds greater than 0, got {}",
+                self.expiry.interval
+            ));
+        }
+
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(errors.join("; ")),
+        }
+    }
+
+    pub fn bind(&self) -> String {
+        self.bind.clone()
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn payload_limit(&self) -> usize {
+        self.payload_limit
+    }
+
+    pub(crate) fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn sqlite(&self) -> &Sqlite {
+        &self.sqlite
+    }
+
+    pub(crate) fn dynamo(&self) -> &Dynamo {
+        &self.dynamo
+    }
+
+    pub(crate) fn idempotency_ttl(&self) -> i64 {
+        self.idempotency.ttl as i64
+    }
+
+    pub(crate) fn idempotency_lease(&self) -> i64 {
+        self.idempotency.lease as i64
+    }
+
+    pub(crate) fn expiry_interval(&self) -> u64 {
+        self.expiry.interval
+    }
+
+    // Builds the CVV generator, failing when no card verification key was supplied.
+    pub(crate) fn cvv_generator(&self) -> Result<cvv::Generator, String> {
+        if self.cvv.key.is_empty() {
+            return Err(String::from("cvv.key or CARDS_CVK is required"));
+        }
+
+        cvv::Generator::new(&self.cvv.key, &self.cvv.service_code)
+            .map_err(|_| String::from("Invalid card verification key"))
+    }
+
+    // Loads the keys of the selected provider, failing when they are missing or malformed.
+    pub(crate) fn key_provider(&self) -> Result<Box<dyn KeyProvider>, String> {
+        let provider = match self.keys.provider.as_str() {
+            "file" => key::Local::from_file(&self.keys.file),
+            _ => key::Local::from_env(key::KEYS_VAR),
+        };
+
+        match provider {
+            Ok(provider) => Ok(Box::new(provider)),
+            Err(_) => Err(format!(
+                "Cannot load the encryption keys of the {} provider",
+                self.keys.provider
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(contents: &str, vars: &[(&str, &str)]) -> Result<Settings, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Settings::parse(contents, &|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(parse("", &[]), Ok(Settings::default()));
+    }
+
+    #[test]
+    fn parse_file() {
+        let contents = r#"
+            bind = "0.0.0.0:9090"
+            workers = 8
+            payload_limit = 1024
+            repository = "memory"
+
+            [dynamo]
+            endpoint = ""
+            region = "sa-east-1"
+            table = "StagingCards"
+
+            [keys]
+            provider = "file"
+            file = "/run/secrets/cards-keys"
+        "#;
+
+        let act = parse(contents, &[]).unwrap();
+
+        assert_eq!(act.bind(), "0.0.0.0:9090");
+        assert_eq!(act.workers(), 8);
+        assert_eq!(act.payload_limit(), 1024);
+        assert_eq!(act.repository(), "memory");
+        assert_eq!(act.dynamo().endpoint, "");
+        assert_eq!(act.dynamo().region, "sa-east-1");
+        assert_eq!(act.dynamo().table, "StagingCards");
+        assert_eq!(act.keys.provider, "file");
+        assert_eq!(act.keys.file, "/run/secrets/cards-keys");
+    }
+
+    #[test]
+    fn parse_env_overrides_file() {
+        let contents = "bind = \"0.0.0.0:9090\"\n[dynamo]\nregion = \"sa-east-1\"\n";
+
+        let act = parse(
+            contents,
+            &[
+                ("CARDS_BIND", "0.0.0.0:80"),
+                ("CARDS_WORKERS", "2"),
+                ("CARDS_PAYLOAD_LIMIT", "2048"),
+                ("CARDS_REPOSITORY", "memory"),
+                ("CARDS_DYNAMO_ENDPOINT", "http://dynamodb:8000"),
+                ("CARDS_REGION", "us-west-2"),
+                ("CARDS_DYNAMO_TABLE", "TestCards"),
+                ("CARDS_SQLITE_PATH", "/var/lib/cards/cards.db"),
+                ("CARDS_KEY_PROVIDER", "file"),
+                ("CARDS_KEY_FILE", "keys.txt"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(act.bind(), "0.0.0.0:80");
+        assert_eq!(act.workers(), 2);
+        assert_eq!(act.payload_limit(), 2048);
+        assert_eq!(act.repository(), "memory");
+        assert_eq!(act.dynamo().endpoint, "http://dynamodb:8000");
+        assert_eq!(act.dynamo().region, "us-west-2");
+        assert_eq!(act.dynamo().table, "TestCards");
+        assert_eq!(act.sqlite.path, "/var/lib/cards/cards.db");
+        assert_eq!(act.keys.provider, "file");
+        assert_eq!(act.keys.file, "keys.txt");
+    }
+
+    macro_rules! test_invalid {
+        ($name:ident, $contents:expr, $vars:expr, $exp:expr) => {
+            #[test]
+            fn $name() {
+                let act = parse($contents, $vars).unwrap_err();
+
+                assert!(act.contains($exp), "{}", act);
+            }
+        };
+    }
+
+    test_invalid!(test_invalid_toml, "bind = ", &[], "Invalid settings file");
+    test_invalid!(
+        test_unknown_setting,
+        "port = 8080",
+        &[],
+        "unknown field `port`"
+    );
+    test_invalid!(
+        test_invalid_bind,
+        "",
+        &[("CARDS_BIND", "localhost")],
+        "bind must be an address and port"
+    );
+    test_invalid!(
+        test_invalid_workers_number,
+        "",
+        &[("CARDS_WORKERS", "many")],
+        "CARDS_WORKERS must be a number"
+    );
+    test_invalid!(
+        test_invalid_workers,
+        "workers = 0",
+        &[],
+        "workers must be between 1 and 256"
+    );
+    test_invalid!(
+        test_invalid_payload_limit,
+        "payload_limit = 0",
+        &[],
+        "payload_limit must be greater than 0"
+    );
+    test_invalid!(
+        test_invalid_repository,
+        "repository = \"postgres\"",
+        &[],
+        "repository must be dynamo, sqlite or memory"
+    );
+    test_invalid!(
+        test_empty_sqlite_path,
+        "[sqlite]\npath = \"\"",
+        &[],
+        "sqlite.path is required"
+    );
+    test_invalid!(
+        test_invalid_endpoint,
+        "",
+        &[("CARDS_DYNAMO_ENDPOINT", "localhost:4566")],
+        "dynamo.endpoint must be an http(s) URL"
+    );
+    test_invalid!(
+        test_invalid_region,
+        "[dynamo]\nendpoint = \"\"\nregion = \"moon-1\"",
+        &[],
+        "dynamo.region must be an AWS region"
+    );
+    test_invalid!(
+        test_empty_table,
+        "[dynamo]\ntable = \"\"",
+        &[],
+        "dynamo.table is required"
+    );
+    test_invalid!(
+        test_invalid_key_provider,
+        "",
+        &[("CARDS_KEY_PROVIDER", "vault")],
+        "keys.provider must be env or file"
+    );
+    test_invalid!(
+        test_key_file_required,
+        "[keys]\nprovider = \"file\"",
+        &[],
+        "keys.file is required"
+    );
+
+    #[test]
+    fn parse_reports_every_invalid_setting() {
+        let act = parse("workers = 0\npayload_limit = 0", &[]).unwrap_err();
+
+        assert_eq!(
+            act,
+            "workers must be between 1 and 256, got 0; payload_limit must be greater than 0"
+        );
+    }
+
+    test_invalid!(
+        test_invalid_cvv_key,
+        "",
+        &[("CARDS_CVK", "0123")],
+        "cvv.key must be 32 hex digits"
+    );
+    test_invalid!(
+        test_invalid_idempotency_ttl,
+        "[idempotency]\nttl = 0",
+        &[],
+        "idempotency.ttl must be a number of seconds greater than 0"
+    );
+    test_invalid!(
+        test_invalid_idempotency_lease,
+        "[idempotency]\nttl = 600\nlease = 601",
+        &[],
+        "idempotency.lease must be a number of seconds between 1 and idempotency.ttl"
+    );
+    test_invalid!(
+        test_invalid_expiry_interval,
+        "[expiry]\ninterval = 0",
+        &[],
+        "expiry.interval must be a number of seconds greater than 0"
+    );
+    test_invalid!(
+        test_bins_moved_to_programs,
+        "[[bins]]\nprogram_id = \"35d86d4b-55e6-49c6-bc83-0b52d68b75f5\"\nprefix = \"412345\"\nlength = 16",
+        &[],
+        "Invalid settings file"
+    );
+
+    #[test]
+    fn parse_cvv() {
+        let contents = r#"
+            [cvv]
+            service_code = "101"
+        "#;
+
+        let act = parse(
+            contents,
+            &[("CARDS_CVK", "0123456789ABCDEFFEDCBA9876543210")],
+        )
+        .unwrap();
+
+        assert_eq!(act.cvv.service_code, "101");
+        assert!(act.cvv_generator().is_ok());
+    }
+
+    #[test]
+    fn parse_idempotency_ttl() {
+        let act = parse("[idempotency]\nttl = 600", &[]).unwrap();
+        let env = parse(
+            "[idempotency]\nttl = 600",
+            &[("CARDS_IDEMPOTENCY_TTL", "60")],
+        )
+        .unwrap();
+
+        assert_eq!(Settings::default().idempotency_ttl(), 86400);
+        assert_eq!(act.idempotency_ttl(), 600);
+        assert_eq!(env.idempotency_ttl(), 60);
+    }
+
+    #[test]
+    fn parse_idempotency_lease() {
+        let act = parse("[idempotency]\nlease = 30", &[]).unwrap();
+        let env = parse(
+            "[idempotency]\nlease = 30",
+            &[("CARDS_IDEMPOTENCY_LEASE", "10")],
+        )
+        .unwrap();
+
+        assert_eq!(Settings::default().idempotency_lease(), 60);
+        assert_eq!(act.idempotency_lease(), 30);
+        assert_eq!(env.idempotency_lease(), 10);
+    }
+
+    #[test]
+    fn parse_expiry_interval() {
+        let act = parse("[expiry]\ninterval = 60", &[]).unwrap();
+        let env = parse(
+            "[expiry]\ninterval = 60",
+            &[("CARDS_EXPIRY_INTERVAL", "3600")],
+        )
+        .unwrap();
+
+        assert_eq!(Settings::default().expiry_interval(), 300);
+        assert_eq!(act.expiry_interval(), 60);
+        assert_eq!(env.expiry_interval(), 3600);
+    }
+
+    #[test]
+    fn cvv_generator_requires_key() {
+        assert!(Settings::default().cvv_generator().is_err());
+    }
+
+    #[test]
+    fn key_provider_missing_file() {
+        let settings = parse(
+            "[keys]\nprovider = \"file\"\nfile = \"/nonexistent/cards-keys\"",
+            &[],
+        )
+        .unwrap();
+
+        assert!(settings.key_provider().is_err());
+    }
+}
@@ -8,6 +8,67 @@ resource "aws_dynamodb_table" "cards" {
     type = "S"
   }
 
+  attribute {
+    name = "AccountID"
+    type = "S"
+  }
+
+  attribute {
+    name = "CustomerID"
+    type = "S"
+  }
+
+  attribute {
+    name = "ProgramID"
+    type = "S"
+  }
+
+  attribute {
+    name = "OrgID"
+    type = "S"
+  }
+
+  attribute {
+    name = "PANHash"
+    type = "S"
+  }
+
+  global_secondary_index {
+    name            = "AccountID-index"
+    hash_key        = "AccountID"
+    projection_type = "ALL"
+  }
+
+  global_secondary_index {
+    name            = "CustomerID-index"
+    hash_key        = "CustomerID"
+    projection_type = "ALL"
+  }
+
+  global_secondary_index {
+    name            = "ProgramID-index"
+    hash_key        = "ProgramID"
+    projection_type = "ALL"
+  }
+
+  global_secondary_index {
+    name            = "OrgID-index"
+    hash_key        = "OrgID"
+    projection_type = "ALL"
+  }
+
+  global_secondary_index {
+    name            = "PANHash-index"
+    hash_key        = "PANHash"
+    projection_type = "KEYS_ONLY"
+  }
+
+  # idempotency keys of create requests are deleted once expired
+  ttl {
+    attribute_name = "ExpiresAt"
+    enabled        = true
+  }
+
   tags = {
     Environment = var.account
   }